@@ -1,70 +1,286 @@
-use actix_cors::Cors;
-use actix_web::{web, App, HttpServer};
+use actix_web::{
+    dev::Service,
+    middleware::{from_fn, Compress},
+    web, App, HttpServer,
+};
+use api_lib::{
+    access_log::access_log,
+    database::{DatabaseError, DatabaseSettings},
+    deprecation::Deprecation,
+    events::FilmEvents,
+    film_repository::{
+        read_your_writes, AnyFilmRepository, CachedFilmRepository, MemoryFilmRepository,
+        MeteredFilmRepository, PostgresFilmRepository, PublishingFilmRepository, RepositoryKind,
+        SqliteFilmRepository,
+    },
+    grpc::FilmGrpcService,
+    metrics::Metrics,
+    rate_limit::RateLimit,
+    security_headers::security_headers,
+    settings::{LogFormat, Settings},
+    supervisor::{shutdown_signal, Supervisor},
+    telemetry::{self, trace_requests},
+    webhooks::{
+        AnyWebhookRepository, MemoryWebhookRepository, PostgresWebhookRepository, WebhookDispatcher,
+    },
+};
+use std::{error::Error, fmt, time::Duration};
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+
+type Repository =
+    PublishingFilmRepository<CachedFilmRepository<MeteredFilmRepository<AnyFilmRepository>>>;
 
 #[actix_web::main]
-async fn main() -> std::io::Result<()> {
+async fn main() -> Result<(), Box<dyn Error>> {
     // init env vars
     dotenv::dotenv().ok();
+    // settings
+    let settings = Settings::load().unwrap_or_else(|err| {
+        eprintln!("🔥🔥🔥 {}", err);
+        std::process::exit(1)
+    });
     // init tracing subscriber
     let tracing = tracing_subscriber::fmt()
         .with_timer(tracing_subscriber::fmt::time::UtcTime::rfc_3339())
         .with_env_filter(tracing_subscriber::EnvFilter::from_default_env());
 
-    if cfg!(debug_assertions) {
-        tracing.pretty().init();
-    } else {
-        tracing.json().init();
+    // export the spans too when an OTLP endpoint is set
+    let tracer_provider = telemetry::tracer_provider(&settings.telemetry).unwrap_or_else(|err| {
+        eprintln!("🔥🔥🔥 Couldn't export the spans: {}", err);
+        std::process::exit(1)
+    });
+    let otel = tracer_provider.as_ref();
+
+    match settings.log.format {
+        LogFormat::Pretty => tracing
+            .pretty()
+            .finish()
+            .with(otel.map(telemetry::layer))
+            .init(),
+        LogFormat::Json => tracing
+            .json()
+            .finish()
+            .with(otel.map(telemetry::layer))
+            .init(),
     }
 
     // building address
-    let port = std::env::var("PORT").unwrap_or("8080".to_string());
-    let address = format!("127.0.0.1:{}", port);
+    let address = settings.server.address();
+
+    // metrics
+    let metrics = Metrics::new();
+
+    // background workers, stopped along with the server
+    let mut supervisor = Supervisor::new();
 
     // repository
-    let repo = get_repo().await.expect("Couldn't get the repository");
-    let repo = web::Data::new(repo);
-    tracing::info!("Repository initialized");
+    let kind = settings.repository.kind;
+    let (films, webhooks): (AnyFilmRepository, AnyWebhookRepository) = match kind {
+        // persisted to `repository.memory_file` when given
+        RepositoryKind::Memory => {
+            let repo = match &settings.repository.memory_file {
+                Some(path) => {
+                    MemoryFilmRepository::open(path).map_err(fail("Couldn't load the films"))?
+                }
+                None => MemoryFilmRepository::new(),
+            };
+            (repo.into(), MemoryWebhookRepository::new().into())
+        }
+        // webhooks are only persisted in postgres
+        RepositoryKind::Sqlite => {
+            let repo = SqliteFilmRepository::connect(&settings.repository.sqlite_url)
+                .await
+                .map_err(fail("Couldn't open the SQLite database"))?;
+            metrics.register_pool("sqlite", repo.pool().clone());
+            supervisor.close_on_shutdown("sqlite", repo.pool().clone());
+            (repo.into(), MemoryWebhookRepository::new().into())
+        }
+        RepositoryKind::Postgres => {
+            let (pool, replicas) = connect_database(&settings.database)
+                .await
+                .inspect_err(|err| tracing::error!("🔥🔥🔥 {}", err))?;
+            metrics.register_pool("primary", pool.clone());
+            supervisor.close_on_shutdown("primary", pool.clone());
+            for (i, replica) in replicas.iter().enumerate() {
+                let name = format!("replica-{}", i);
+                metrics.register_pool(&name, replica.clone());
+                supervisor.close_on_shutdown(&name, replica.clone());
+            }
+            // webhook secrets are encrypted, including the ones stored before
+            let cipher = settings
+                .webhooks
+                .secret_cipher()
+                .map_err(fail("Couldn't read the webhooks secret key"))?;
+            let webhooks = PostgresWebhookRepository::new(pool.clone(), cipher);
+            webhooks
+                .seal_plain_secrets()
+                .await
+                .map_err(fail("Couldn't encrypt the webhook secrets"))?;
+            (
+                PostgresFilmRepository::new(pool)
+                    .with_replicas(replicas)
+                    .into(),
+                webhooks.into(),
+            )
+        }
+    };
+    // cache reads in front of whichever backend was selected, timing the
+    // backend itself, but not what replicas may not have caught up with yet
+    let replica_lag = if settings.database.replica_urls.is_empty() {
+        Duration::ZERO
+    } else {
+        settings.repository.cache_replica_lag()
+    };
+    let films = CachedFilmRepository::new(
+        MeteredFilmRepository::new(films, metrics.clone()),
+        settings.repository.cache_capacity,
+        settings.repository.cache_ttl(),
+    )
+    .with_replica_lag(replica_lag)
+    .with_metrics(metrics.clone());
+    let events = FilmEvents::new();
+    let repo = web::Data::new(PublishingFilmRepository::new(films, events.clone()));
+    tracing::info!("Repository initialized ({})", kind);
+
+    // seed the database with the bundled fixtures when asked to
+    let seed = std::env::args().any(|arg| arg == "--seed") || settings.repository.seed;
+    if seed {
+        api_lib::seed::seed(repo.get_ref())
+            .await
+            .map_err(fail("Couldn't seed the database"))?;
+    }
+
+    // webhooks
+    let webhooks = web::Data::new(webhooks);
+    let dispatcher = WebhookDispatcher::new(webhooks.clone().into_inner())
+        .allow_private_addresses(settings.webhooks.allow_private_addresses);
+    let film_events = events.subscribe();
+    supervisor.spawn("webhooks", |shutdown| dispatcher.run(film_events, shutdown));
+
+    // grpc
+    let grpc_address = settings.server.grpc_address();
+    let grpc_listener = tokio::net::TcpListener::bind(&grpc_address)
+        .await
+        .map_err(fail(format!(
+            "Couldn't start the gRPC server at {}",
+            grpc_address
+        )))?;
+    let grpc = FilmGrpcService::new(repo.clone().into_inner()).with_events(events.clone());
+    tracing::info!("🚀🚀🚀 Starting gRPC server at {}", grpc_address);
+    supervisor.spawn("grpc", |shutdown| async move {
+        if let Err(err) = api_lib::grpc::serve(grpc, grpc_listener, shutdown).await {
+            tracing::error!("gRPC server stopped: {:?}", err);
+        }
+    });
+    let events = web::Data::new(events);
+
+    // presence
+    let presence = web::Data::new(api_lib::presence::PresenceHub::new());
 
     // starting the server
     tracing::info!("🚀🚀🚀 Starting Actix server at {}", address);
 
     // static files
-    let static_folder = std::env::var("STATIC_FOLDER").unwrap_or("./front/dist".to_string());
+    let static_folder = settings.server.static_folder.clone();
+
+    // CORS
+    let cors_settings = settings.cors.clone();
 
-    HttpServer::new(move || {
-        // CORS
-        let cors = Cors::permissive();
+    // rate limiting, shared by every worker
+    let rate_limit = RateLimit::in_memory(settings.rate_limit.clone());
 
+    // Deprecation and Sunset headers, shared by every worker
+    let deprecation = Deprecation::new(&settings.deprecation);
+
+    // CSP, HSTS & co. on the API and the front end, whose inline scripts
+    // are allowed by hash
+    let security_settings = settings
+        .security_headers
+        .clone()
+        .allow_inline_scripts(&static_folder.join("index.html"));
+
+    // metrics, scraped at /metrics with the metrics token
+    let metrics_data = web::Data::new(metrics.clone());
+    let metrics_settings = settings.metrics.clone();
+
+    let server = HttpServer::new(move || {
         App::new()
-            .wrap(cors)
+            .wrap(security_headers(&security_settings))
             .service(
                 web::scope("/api")
+                    // every request reads its own writes, even with read replicas
+                    .wrap_fn(|req, srv| read_your_writes(srv.call(req)))
+                    .wrap(rate_limit.middleware())
+                    // Deprecation and Sunset headers on the v1 routes
+                    .wrap(deprecation.middleware())
+                    .wrap(api_lib::cors::cors(&cors_settings))
+                    .wrap(metrics.middleware())
+                    // gives every request an id and logs it once served
+                    .wrap(from_fn(access_log))
+                    // continues the trace of callers sending a `traceparent`
+                    .wrap(from_fn(trace_requests))
+                    .wrap(Compress::default())
                     .app_data(repo.clone())
+                    .app_data(presence.clone())
+                    .app_data(webhooks.clone())
+                    .app_data(events.clone())
                     .configure(api_lib::health::service)
-                    .configure(
-                        api_lib::v1::service::<api_lib::film_repository::PostgresFilmRepository>,
-                    ),
-            )
-            .service(
-                actix_files::Files::new("/", &static_folder)
-                    .show_files_listing()
-                    .index_file("index.html"),
+                    .configure(api_lib::health::ready_service::<Repository>)
+                    .configure(api_lib::openapi::service)
+                    .configure(api_lib::presence::service)
+                    .configure(api_lib::v1::service::<Repository>)
+                    .configure(api_lib::v2::service::<Repository>)
+                    .configure(api_lib::graphql::service::<Repository>)
+                    .configure(api_lib::webhooks::service::<AnyWebhookRepository>),
             )
+            .app_data(metrics_data.clone())
+            .configure(api_lib::metrics::service(&metrics_settings))
+            // last, the front end answers every other path
+            .configure(api_lib::static_files::service(&static_folder))
     })
+    // stopped below, along with the workers
+    .disable_signals()
+    .shutdown_timeout(settings.server.shutdown_timeout_seconds)
     .bind(&address)
-    .unwrap_or_else(|err| {
-        panic!(
-            "🔥🔥🔥 Couldn't start the server in port {}: {:?}",
-            port, err
-        )
-    })
-    .run()
-    .await
+    .map_err(fail(format!("Couldn't start the server at {}", address)))?
+    .run();
+
+    // on SIGTERM, stop accepting connections and drain the in-flight requests
+    let handle = server.handle();
+    actix_web::rt::spawn(async move {
+        shutdown_signal().await;
+        tracing::info!("Shutting down, draining the in-flight requests");
+        handle.stop(true).await;
+    });
+    let server = server.await;
+
+    // then stop the workers and close the pools
+    supervisor
+        .shutdown(settings.server.shutdown_timeout())
+        .await;
+
+    // flush the spans that weren't exported yet
+    if let Some(provider) = tracer_provider {
+        if let Err(err) = provider.shutdown() {
+            eprintln!("Couldn't export the last spans: {}", err);
+        }
+    }
+    Ok(server?)
+}
+
+/// Logs why the API couldn't start, before `main` returns the error.
+fn fail<E: fmt::Display>(context: impl fmt::Display) -> impl FnOnce(E) -> Box<dyn Error> {
+    move |err| {
+        let message = format!("{}: {}", context, err);
+        tracing::error!("🔥🔥🔥 {}", message);
+        message.into()
+    }
 }
 
-async fn get_repo() -> Result<impl api_lib::film_repository::FilmRepository, sqlx::Error> {
-    let conn_str =
-        std::env::var("DATABASE_URL").map_err(|e| sqlx::Error::Configuration(Box::new(e)))?;
-    let pool = sqlx::PgPool::connect(&conn_str).await?;
-    Ok(api_lib::film_repository::PostgresFilmRepository::new(pool))
+/// Connects to the primary and the read replicas.
+async fn connect_database(
+    settings: &DatabaseSettings,
+) -> Result<(sqlx::PgPool, Vec<sqlx::PgPool>), DatabaseError> {
+    let pool = settings.connect().await?;
+    Ok((pool, settings.connect_replicas()?))
 }
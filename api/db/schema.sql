@@ -10,3 +10,25 @@ CREATE TABLE IF NOT EXISTS films
     created_at timestamp with time zone default CURRENT_TIMESTAMP,
     updated_at timestamp with time zone
 );
+
+CREATE TABLE IF NOT EXISTS webhooks
+(
+    id uuid DEFAULT uuid_generate_v1() NOT NULL CONSTRAINT webhooks_pkey PRIMARY KEY,
+    url text NOT NULL,
+    secret text NOT NULL,
+    events text[] NOT NULL,
+    created_at timestamp with time zone default CURRENT_TIMESTAMP
+);
+
+CREATE TABLE IF NOT EXISTS webhook_deliveries
+(
+    id uuid NOT NULL CONSTRAINT webhook_deliveries_pkey PRIMARY KEY,
+    webhook_id uuid NOT NULL REFERENCES webhooks (id) ON DELETE CASCADE,
+    event_id uuid NOT NULL,
+    event text NOT NULL,
+    attempt integer NOT NULL,
+    status_code smallint,
+    error text,
+    success boolean NOT NULL,
+    attempted_at timestamp with time zone NOT NULL
+);
@@ -0,0 +1,12 @@
+-- ids are stored as 16-byte blobs and timestamps as RFC 3339 text,
+-- which is how sqlx encodes `Uuid` and `DateTime<Utc>` for SQLite
+CREATE TABLE IF NOT EXISTS films
+(
+    id blob NOT NULL CONSTRAINT films_pkey PRIMARY KEY,
+    title text NOT NULL,
+    director text NOT NULL,
+    year integer NOT NULL,
+    poster text NOT NULL,
+    created_at text,
+    updated_at text
+);
@@ -0,0 +1,12 @@
+//! Generates the gRPC messages, client and server of `api/proto/films.proto`.
+//!
+//! `protoc` comes vendored with the build, so it doesn't need to be installed.
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    println!("cargo:rerun-if-changed=../proto/films.proto");
+
+    std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path()?);
+    std::env::set_var("PROTOC_INCLUDE", protoc_bin_vendored::include_path()?);
+    tonic_build::configure().compile_protos(&["../proto/films.proto"], &["../proto"])?;
+    Ok(())
+}
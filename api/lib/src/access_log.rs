@@ -0,0 +1,198 @@
+use std::{fmt, time::Instant};
+
+use actix_web::{
+    body::MessageBody,
+    dev::{Payload, ServiceRequest, ServiceResponse},
+    http::header::{HeaderName, HeaderValue},
+    middleware::Next,
+    Error, FromRequest, HttpMessage, HttpRequest,
+};
+use futures_util::future::{ready, Ready};
+use uuid::Uuid;
+
+use crate::rate_limit::token_hash;
+
+pub const REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Longest `X-Request-Id` taken from a client, longer ones are replaced.
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// Identifies a request in the logs, the traces and the error bodies. Taken
+/// from the `X-Request-Id` header when the client or a proxy sends one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestId(String);
+
+impl RequestId {
+    pub fn new() -> Self {
+        Self(Uuid::new_v4().to_string())
+    }
+
+    /// The id sent by the client, if it's a sensible one.
+    fn from_header(value: &HeaderValue) -> Option<Self> {
+        let value = value.to_str().ok()?;
+        let valid = !value.is_empty()
+            && value.len() <= MAX_REQUEST_ID_LEN
+            && value.chars().all(|c| c.is_ascii_graphic());
+        valid.then(|| Self(value.to_string()))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Default for RequestId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// The id [`access_log`] gave the request, or a new one when it isn't used.
+impl FromRequest for RequestId {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let id = req.extensions().get::<RequestId>().cloned();
+        ready(Ok(id.unwrap_or_default()))
+    }
+}
+
+/// Middleware giving every request a [`RequestId`], sent back in the
+/// `X-Request-Id` header and recorded in the `request_id` field of the
+/// current span, and logging a structured access line once it's served.
+///
+/// Wrap it inside [`trace_requests`](crate::telemetry::trace_requests) so the
+/// request span carries the id.
+pub async fn access_log<B: MessageBody>(
+    req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<B>, Error> {
+    let start = Instant::now();
+    let request_id = req
+        .headers()
+        .get(REQUEST_ID)
+        .and_then(RequestId::from_header)
+        .unwrap_or_default();
+    req.extensions_mut().insert(request_id.clone());
+    tracing::Span::current().record("request_id", request_id.as_str());
+
+    let method = req.method().to_string();
+    let route = req
+        .match_pattern()
+        .unwrap_or_else(|| "unmatched".to_string());
+    let user = token_hash(req.headers())
+        // the start of the hash is plenty to tell clients apart
+        .map(|hash| format!("token:{}", &hash[..16]))
+        .unwrap_or_else(|| "anonymous".to_string());
+    let client_ip = req.peer_addr().map(|address| address.ip().to_string());
+
+    let mut res = next.call(req).await;
+    let status = match &mut res {
+        Ok(res) => {
+            if let Ok(value) = HeaderValue::from_str(request_id.as_str()) {
+                res.headers_mut().insert(REQUEST_ID, value);
+            }
+            res.status()
+        }
+        Err(e) => e.as_response_error().status_code(),
+    };
+    let latency_ms = start.elapsed().as_secs_f64() * 1000.0;
+
+    macro_rules! access {
+        ($level:expr) => {
+            tracing::event!(
+                target: "access_log",
+                $level,
+                request_id = request_id.as_str(),
+                method = %method,
+                route = %route,
+                status = status.as_u16(),
+                latency_ms,
+                user = %user,
+                client_ip = client_ip.as_deref(),
+                "{} {} {}",
+                method,
+                route,
+                status.as_u16()
+            )
+        };
+    }
+    if status.is_server_error() {
+        access!(tracing::Level::ERROR);
+    } else {
+        access!(tracing::Level::INFO);
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use actix_web::{
+        middleware::from_fn,
+        web::{self, ServiceConfig},
+        HttpResponse,
+    };
+
+    fn films(cfg: &mut ServiceConfig) {
+        cfg.service(web::scope("").wrap(from_fn(access_log)).route(
+            "/films",
+            web::get().to(|request_id: RequestId| async move {
+                HttpResponse::Ok().body(request_id.to_string())
+            }),
+        ));
+    }
+
+    #[actix_rt::test]
+    async fn requests_get_an_id() {
+        let app = testing::app(films).await;
+        let req = actix_web::test::TestRequest::get()
+            .uri("/films")
+            .to_request();
+
+        let res = actix_web::test::call_service(&app, req).await;
+
+        let header = res.headers().get(REQUEST_ID).unwrap().clone();
+        let body = actix_web::test::read_body(res).await;
+        assert!(Uuid::parse_str(header.to_str().unwrap()).is_ok());
+        assert_eq!(body, header.as_bytes());
+    }
+
+    #[actix_rt::test]
+    async fn the_id_of_the_client_is_kept() {
+        let app = testing::app(films).await;
+        let req = actix_web::test::TestRequest::get()
+            .uri("/films")
+            .insert_header((REQUEST_ID, "from-the-proxy-42"))
+            .to_request();
+
+        let res = actix_web::test::call_service(&app, req).await;
+
+        assert_eq!(res.headers().get(REQUEST_ID).unwrap(), "from-the-proxy-42");
+        assert_eq!(actix_web::test::read_body(res).await, "from-the-proxy-42");
+    }
+
+    #[actix_rt::test]
+    async fn unreasonable_ids_are_replaced() {
+        let app = testing::app(films).await;
+        for id in ["with spaces", &"a".repeat(MAX_REQUEST_ID_LEN + 1)] {
+            let req = actix_web::test::TestRequest::get()
+                .uri("/films")
+                .insert_header((REQUEST_ID, id))
+                .to_request();
+
+            let res = actix_web::test::call_service(&app, req).await;
+
+            let header = res.headers().get(REQUEST_ID).unwrap();
+            assert!(Uuid::parse_str(header.to_str().unwrap()).is_ok());
+        }
+    }
+}
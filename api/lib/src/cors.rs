@@ -0,0 +1,302 @@
+use actix_cors::Cors;
+use actix_web::http::{header::HeaderName, Method};
+use serde::{Deserialize, Serialize};
+
+use crate::settings::Validate;
+
+/// Which browsers may call the API from another origin, the `[cors]` section
+/// of the [`Settings`](crate::settings::Settings).
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct CorsSettings {
+    /// `*` allows any origin. Debug builds allow any origin by default, while
+    /// release builds only allow the API's own origin.
+    pub allowed_origins: Vec<String>,
+    pub allowed_methods: Vec<String>,
+    pub allowed_headers: Vec<String>,
+    /// Response headers the browser lets the calling page read.
+    pub exposed_headers: Vec<String>,
+    /// Whether cookies and `Authorization` headers are sent along.
+    pub allow_credentials: bool,
+    /// How long browsers may cache a preflight response.
+    pub max_age_seconds: Option<usize>,
+}
+
+impl Default for CorsSettings {
+    fn default() -> Self {
+        let allowed_origins = if cfg!(debug_assertions) {
+            vec!["*".to_string()]
+        } else {
+            Vec::new()
+        };
+        Self {
+            allowed_origins,
+            allowed_methods: ["GET", "POST", "PUT", "PATCH", "DELETE"]
+                .map(String::from)
+                .to_vec(),
+            // along with the request id and trace context clients may send
+            allowed_headers: [
+                "accept",
+                "authorization",
+                "content-type",
+                "traceparent",
+                "tracestate",
+                "x-request-id",
+            ]
+            .map(String::from)
+            .to_vec(),
+            exposed_headers: [
+                "deprecation",
+                "link",
+                "location",
+                "ratelimit-limit",
+                "ratelimit-remaining",
+                "ratelimit-reset",
+                "retry-after",
+                "sunset",
+                "x-request-id",
+            ]
+            .map(String::from)
+            .to_vec(),
+            allow_credentials: false,
+            max_age_seconds: Some(3600),
+        }
+    }
+}
+
+impl CorsSettings {
+    pub fn allows_any_origin(&self) -> bool {
+        self.allowed_origins.iter().any(|origin| origin == "*")
+    }
+}
+
+impl Validate for CorsSettings {
+    fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        for origin in &self.allowed_origins {
+            if origin != "*" && !is_origin(origin) {
+                problems.push(format!(
+                    "cors.allowed_origins (CORS_ORIGINS): `{}` isn't `*` or an origin like https://films.example.com",
+                    origin
+                ));
+            }
+        }
+        for method in &self.allowed_methods {
+            if method.parse::<Method>().is_err() {
+                problems.push(format!(
+                    "cors.allowed_methods (CORS_METHODS): `{}` isn't an HTTP method",
+                    method
+                ));
+            }
+        }
+        for (setting, headers) in [
+            ("cors.allowed_headers (CORS_HEADERS)", &self.allowed_headers),
+            (
+                "cors.exposed_headers (CORS_EXPOSED_HEADERS)",
+                &self.exposed_headers,
+            ),
+        ] {
+            for header in headers {
+                if header.parse::<HeaderName>().is_err() {
+                    problems.push(format!("{}: `{}` isn't a header name", setting, header));
+                }
+            }
+        }
+        if self.allow_credentials && self.allows_any_origin() {
+            problems.push(
+                "cors.allow_credentials (CORS_ALLOW_CREDENTIALS) needs the allowed origins to be listed instead of `*`"
+                    .to_string(),
+            );
+        }
+        problems
+    }
+}
+
+/// `scheme://host[:port]`, without a path.
+pub(crate) fn is_origin(origin: &str) -> bool {
+    match origin.split_once("://") {
+        Some((scheme, host)) => {
+            (scheme == "http" || scheme == "https") && !host.is_empty() && !host.contains('/')
+        }
+        None => false,
+    }
+}
+
+/// The CORS middleware for the given, already validated, settings. Both API
+/// binaries wrap their `/api` scope with it.
+pub fn cors(settings: &CorsSettings) -> Cors {
+    let cors = if settings.allows_any_origin() {
+        Cors::default().allow_any_origin()
+    } else {
+        settings
+            .allowed_origins
+            .iter()
+            .fold(Cors::default(), |cors, origin| cors.allowed_origin(origin))
+    };
+    let cors = cors
+        .allowed_methods(settings.allowed_methods.iter().map(String::as_str))
+        .allowed_headers(settings.allowed_headers.iter().map(String::as_str))
+        .max_age(settings.max_age_seconds);
+    let cors = if settings.exposed_headers.is_empty() {
+        cors
+    } else {
+        cors.expose_headers(settings.exposed_headers.iter().map(String::as_str))
+    };
+    if settings.allow_credentials {
+        cors.supports_credentials()
+    } else {
+        cors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{
+        http::{header, StatusCode},
+        web, App, HttpResponse,
+    };
+
+    fn settings(origins: &[&str]) -> CorsSettings {
+        CorsSettings {
+            allowed_origins: origins.iter().map(|origin| origin.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    async fn preflight(settings: &CorsSettings, origin: &str, method: &str) -> StatusCode {
+        let app = actix_web::test::init_service(
+            App::new()
+                .wrap(cors(settings))
+                .route("/films", web::post().to(HttpResponse::Ok)),
+        )
+        .await;
+        let req = actix_web::test::TestRequest::default()
+            .method(Method::OPTIONS)
+            .uri("/films")
+            .insert_header((header::ORIGIN, origin))
+            .insert_header((header::ACCESS_CONTROL_REQUEST_METHOD, method))
+            .to_request();
+        actix_web::test::call_service(&app, req).await.status()
+    }
+
+    #[actix_rt::test]
+    async fn listed_origins_are_allowed() {
+        let settings = settings(&["https://films.example.com"]);
+
+        assert_eq!(
+            preflight(&settings, "https://films.example.com", "POST").await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            preflight(&settings, "https://evil.example.com", "POST").await,
+            StatusCode::BAD_REQUEST
+        );
+    }
+
+    #[actix_rt::test]
+    async fn only_listed_methods_are_allowed() {
+        let settings = CorsSettings {
+            allowed_methods: vec!["GET".to_string()],
+            ..settings(&["*"])
+        };
+
+        assert_eq!(
+            preflight(&settings, "https://films.example.com", "GET").await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            preflight(&settings, "https://films.example.com", "DELETE").await,
+            StatusCode::BAD_REQUEST
+        );
+    }
+
+    #[actix_rt::test]
+    async fn no_origin_is_allowed_without_any_listed() {
+        let settings = settings(&[]);
+
+        assert_eq!(
+            preflight(&settings, "https://films.example.com", "GET").await,
+            StatusCode::BAD_REQUEST
+        );
+    }
+
+    #[actix_rt::test]
+    async fn request_ids_and_trace_context_are_allowed() {
+        let app = actix_web::test::init_service(
+            App::new()
+                .wrap(cors(&settings(&["https://films.example.com"])))
+                .route("/films", web::post().to(HttpResponse::Ok)),
+        )
+        .await;
+        let req = actix_web::test::TestRequest::default()
+            .method(Method::OPTIONS)
+            .uri("/films")
+            .insert_header((header::ORIGIN, "https://films.example.com"))
+            .insert_header((header::ACCESS_CONTROL_REQUEST_METHOD, "POST"))
+            .insert_header((
+                header::ACCESS_CONTROL_REQUEST_HEADERS,
+                "content-type, traceparent, tracestate, x-request-id",
+            ))
+            .to_request();
+
+        let res = actix_web::test::call_service(&app, req).await;
+
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[actix_rt::test]
+    async fn credentials_are_only_allowed_when_asked_for() {
+        let app = actix_web::test::init_service(
+            App::new()
+                .wrap(cors(&CorsSettings {
+                    allow_credentials: true,
+                    ..settings(&["https://films.example.com"])
+                }))
+                .route("/films", web::get().to(HttpResponse::Ok)),
+        )
+        .await;
+        let req = actix_web::test::TestRequest::get()
+            .uri("/films")
+            .insert_header((header::ORIGIN, "https://films.example.com"))
+            .to_request();
+
+        let res = actix_web::test::call_service(&app, req).await;
+
+        assert_eq!(
+            res.headers()
+                .get(header::ACCESS_CONTROL_ALLOW_CREDENTIALS)
+                .unwrap(),
+            "true"
+        );
+        assert_eq!(
+            res.headers()
+                .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+                .unwrap(),
+            "https://films.example.com"
+        );
+    }
+
+    #[test]
+    fn invalid_settings_are_reported() {
+        let settings = CorsSettings {
+            allowed_methods: vec!["GET".to_string(), "NOT A METHOD".to_string()],
+            allowed_headers: vec!["x y".to_string()],
+            allow_credentials: true,
+            ..settings(&["*", "films.example.com"])
+        };
+
+        let problems = settings.problems();
+
+        assert_eq!(problems.len(), 4, "{:?}", problems);
+        assert!(problems[0].starts_with("cors.allowed_origins"));
+        assert!(problems[1].starts_with("cors.allowed_methods"));
+        assert!(problems[2].starts_with("cors.allowed_headers"));
+        assert!(problems[3].starts_with("cors.allow_credentials"));
+    }
+
+    #[test]
+    fn defaults_are_valid() {
+        assert!(CorsSettings::default().problems().is_empty());
+    }
+}
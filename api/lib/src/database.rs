@@ -0,0 +1,432 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use config::{Config, Environment, File};
+use serde::{Deserialize, Serialize};
+use sqlx::{
+    postgres::{PgConnectOptions, PgPoolOptions, PgSslMode},
+    PgPool,
+};
+
+/// The optional file the database settings are read from, unless
+/// `DATABASE_CONFIG` points somewhere else.
+pub const DEFAULT_CONFIG_FILE: &str = "database.toml";
+
+/// Connection pool and database settings shared by the API binaries, the
+/// `[database]` section of the [`Settings`](crate::settings::Settings).
+///
+/// They can also be kept in a TOML file of their own, which the `DATABASE_*`
+/// environment variables override, e.g. `DATABASE_URL` or
+/// `DATABASE_MAX_CONNECTIONS`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct DatabaseSettings {
+    pub url: Option<String>,
+    /// Comma separated in `DATABASE_REPLICA_URLS`.
+    pub replica_urls: Vec<String>,
+    pub max_connections: u32,
+    pub min_connections: u32,
+    pub acquire_timeout_seconds: u64,
+    /// Kept short so a replica that is down doesn't hold reads up for long
+    /// before they move on to the next one.
+    pub replica_acquire_timeout_ms: u64,
+    /// Idle connections are never closed when unset.
+    pub idle_timeout_seconds: Option<u64>,
+    /// Queries run for as long as they need when unset.
+    pub statement_timeout_ms: Option<u64>,
+    pub ssl_mode: SslMode,
+    /// How many times to try connecting again on startup before giving up.
+    pub connect_retries: u32,
+    /// Doubled after every failed attempt, up to [`MAX_RETRY_DELAY`].
+    pub retry_delay_ms: u64,
+}
+
+/// The longest we wait between two connection attempts on startup.
+pub const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+impl Default for DatabaseSettings {
+    fn default() -> Self {
+        Self {
+            url: None,
+            replica_urls: Vec::new(),
+            max_connections: 10,
+            min_connections: 0,
+            acquire_timeout_seconds: 30,
+            replica_acquire_timeout_ms: 1000,
+            idle_timeout_seconds: Some(600),
+            statement_timeout_ms: None,
+            ssl_mode: SslMode::default(),
+            connect_retries: 5,
+            retry_delay_ms: 500,
+        }
+    }
+}
+
+impl DatabaseSettings {
+    /// Loads the settings from `DATABASE_CONFIG` (or [`DEFAULT_CONFIG_FILE`])
+    /// and the environment.
+    pub fn load() -> Result<Self, DatabaseError> {
+        Self::from_sources(Some(&config_file()), None)
+    }
+
+    /// Reads `file`, when it exists, and then the environment. `env` replaces
+    /// the process environment when given.
+    pub fn from_sources(
+        file: Option<&Path>,
+        env: Option<config::Map<String, String>>,
+    ) -> Result<Self, DatabaseError> {
+        Self::default().layered(file, env)
+    }
+
+    /// Like [`DatabaseSettings::from_sources`], on top of these settings
+    /// rather than the defaults.
+    pub fn layered(
+        &self,
+        file: Option<&Path>,
+        env: Option<config::Map<String, String>>,
+    ) -> Result<Self, DatabaseError> {
+        let base = Config::try_from(self).map_err(DatabaseError::Config)?;
+        let mut builder = Config::builder().add_source(base);
+        if let Some(file) = file {
+            builder = builder.add_source(File::from(file).required(false));
+        }
+        builder
+            .add_source(
+                Environment::with_prefix("DATABASE")
+                    .prefix_separator("_")
+                    .try_parsing(true)
+                    .ignore_empty(true)
+                    .list_separator(",")
+                    .with_list_parse_key("replica_urls")
+                    .source(env),
+            )
+            .build()
+            .and_then(Config::try_deserialize)
+            .map_err(DatabaseError::Config)
+    }
+
+    pub fn acquire_timeout(&self) -> Duration {
+        Duration::from_secs(self.acquire_timeout_seconds)
+    }
+
+    pub fn replica_acquire_timeout(&self) -> Duration {
+        Duration::from_millis(self.replica_acquire_timeout_ms)
+    }
+
+    pub fn idle_timeout(&self) -> Option<Duration> {
+        self.idle_timeout_seconds.map(Duration::from_secs)
+    }
+
+    /// How long to wait before the given (zero based) connection retry.
+    pub fn retry_delay(&self, retry: u32) -> Duration {
+        Duration::from_millis(self.retry_delay_ms)
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(MAX_RETRY_DELAY)
+    }
+
+    /// The options every connection to `url` is opened with.
+    pub fn connect_options(&self, url: &str) -> Result<PgConnectOptions, DatabaseError> {
+        let options = PgConnectOptions::from_str(url)
+            .map_err(|source| DatabaseError::InvalidUrl { source })?
+            .ssl_mode(self.ssl_mode.into());
+        Ok(match self.statement_timeout_ms {
+            Some(timeout) => options.options([("statement_timeout", timeout.to_string())]),
+            None => options,
+        })
+    }
+
+    pub fn pool_options(&self) -> PgPoolOptions {
+        PgPoolOptions::new()
+            .max_connections(self.max_connections)
+            .min_connections(self.min_connections)
+            .acquire_timeout(self.acquire_timeout())
+            .idle_timeout(self.idle_timeout())
+    }
+
+    /// Connects to the primary database, trying again with an exponential
+    /// backoff while it can't be reached.
+    pub async fn connect(&self) -> Result<PgPool, DatabaseError> {
+        let url = self.url.as_deref().ok_or(DatabaseError::MissingUrl)?;
+        let options = self.connect_options(url)?;
+        let mut retry = 0;
+        loop {
+            match self.pool_options().connect_with(options.clone()).await {
+                Ok(pool) => return Ok(pool),
+                Err(sqlx::Error::Configuration(e)) => {
+                    return Err(DatabaseError::InvalidUrl {
+                        source: sqlx::Error::Configuration(e),
+                    })
+                }
+                Err(source) if retry >= self.connect_retries => {
+                    return Err(DatabaseError::Connect {
+                        attempts: retry + 1,
+                        source,
+                    })
+                }
+                Err(e) => {
+                    let delay = self.retry_delay(retry);
+                    tracing::warn!(
+                        attempt = retry + 1,
+                        "Couldn't connect to the database, trying again in {:?}: {}",
+                        delay,
+                        e
+                    );
+                    tokio::time::sleep(delay).await;
+                    retry += 1;
+                }
+            }
+        }
+    }
+
+    /// Replicas are connected lazily so the API still starts, reading from the
+    /// primary, when they are down.
+    pub fn connect_replicas(&self) -> Result<Vec<PgPool>, DatabaseError> {
+        self.replica_urls
+            .iter()
+            .map(|url| {
+                Ok(self
+                    .pool_options()
+                    .acquire_timeout(self.replica_acquire_timeout())
+                    .connect_lazy_with(self.connect_options(url)?))
+            })
+            .collect()
+    }
+}
+
+/// `DATABASE_CONFIG`, or [`DEFAULT_CONFIG_FILE`].
+pub fn config_file() -> PathBuf {
+    std::env::var("DATABASE_CONFIG")
+        .unwrap_or_else(|_| DEFAULT_CONFIG_FILE.to_string())
+        .into()
+}
+
+/// Mirrors Postgres' `sslmode`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SslMode {
+    Disable,
+    Allow,
+    #[default]
+    Prefer,
+    Require,
+    VerifyCa,
+    VerifyFull,
+}
+
+impl From<SslMode> for PgSslMode {
+    fn from(mode: SslMode) -> Self {
+        match mode {
+            SslMode::Disable => PgSslMode::Disable,
+            SslMode::Allow => PgSslMode::Allow,
+            SslMode::Prefer => PgSslMode::Prefer,
+            SslMode::Require => PgSslMode::Require,
+            SslMode::VerifyCa => PgSslMode::VerifyCa,
+            SslMode::VerifyFull => PgSslMode::VerifyFull,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum DatabaseError {
+    /// The settings file or environment couldn't be read.
+    Config(config::ConfigError),
+    /// Neither the settings file nor `DATABASE_URL` say where the database is.
+    MissingUrl,
+    InvalidUrl {
+        source: sqlx::Error,
+    },
+    /// The database still couldn't be reached after retrying.
+    Connect {
+        attempts: u32,
+        source: sqlx::Error,
+    },
+    /// The schema couldn't be created.
+    Schema(sqlx::Error),
+}
+
+impl fmt::Display for DatabaseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DatabaseError::Config(e) => write!(f, "Invalid database settings: {}", e),
+            DatabaseError::MissingUrl => write!(
+                f,
+                "No database configured: set DATABASE_URL, `url` in {} or `database.url` in the settings file",
+                DEFAULT_CONFIG_FILE
+            ),
+            DatabaseError::InvalidUrl { source } => write!(f, "Invalid database URL: {}", source),
+            DatabaseError::Connect { attempts, source } => write!(
+                f,
+                "Couldn't connect to the database after {} attempt(s): {}",
+                attempts, source
+            ),
+            DatabaseError::Schema(e) => write!(f, "Couldn't create the database schema: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for DatabaseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DatabaseError::Config(e) => Some(e),
+            DatabaseError::MissingUrl => None,
+            DatabaseError::InvalidUrl { source }
+            | DatabaseError::Connect { source, .. }
+            | DatabaseError::Schema(source) => Some(source),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env(vars: &[(&str, &str)]) -> Option<config::Map<String, String>> {
+        Some(
+            vars.iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn defaults_are_used_without_a_file_or_env() {
+        let settings = DatabaseSettings::from_sources(None, env(&[])).unwrap();
+
+        assert_eq!(settings, DatabaseSettings::default());
+    }
+
+    #[test]
+    fn env_overrides_the_file() {
+        let file = std::env::temp_dir().join(format!("{}.toml", uuid::Uuid::new_v4()));
+        std::fs::write(
+            &file,
+            r#"
+url = "postgres://file/films"
+max_connections = 20
+ssl_mode = "require"
+"#,
+        )
+        .unwrap();
+
+        let settings = DatabaseSettings::from_sources(
+            Some(&file),
+            env(&[
+                ("DATABASE_MAX_CONNECTIONS", "5"),
+                ("DATABASE_STATEMENT_TIMEOUT_MS", "1500"),
+                (
+                    "DATABASE_REPLICA_URLS",
+                    "postgres://a/films,postgres://b/films",
+                ),
+            ]),
+        )
+        .unwrap();
+        std::fs::remove_file(file).unwrap();
+
+        assert_eq!(settings.url.as_deref(), Some("postgres://file/films"));
+        assert_eq!(settings.max_connections, 5);
+        assert_eq!(settings.statement_timeout_ms, Some(1500));
+        assert_eq!(settings.ssl_mode, SslMode::Require);
+        assert_eq!(
+            settings.replica_urls,
+            vec!["postgres://a/films", "postgres://b/films"]
+        );
+    }
+
+    #[test]
+    fn invalid_settings_are_reported() {
+        let result =
+            DatabaseSettings::from_sources(None, env(&[("DATABASE_SSL_MODE", "sometimes")]));
+
+        assert!(matches!(result, Err(DatabaseError::Config(_))));
+    }
+
+    #[test]
+    fn layered_settings_keep_what_the_file_and_env_dont_set() {
+        let base = DatabaseSettings {
+            url: Some("postgres://base/films".to_string()),
+            max_connections: 20,
+            ..Default::default()
+        };
+
+        let settings = base
+            .layered(None, env(&[("DATABASE_MAX_CONNECTIONS", "5")]))
+            .unwrap();
+
+        assert_eq!(settings.url.as_deref(), Some("postgres://base/films"));
+        assert_eq!(settings.max_connections, 5);
+    }
+
+    #[test]
+    fn retry_delay_backs_off_exponentially() {
+        let settings = DatabaseSettings::default();
+
+        assert_eq!(settings.retry_delay(0), Duration::from_millis(500));
+        assert_eq!(settings.retry_delay(1), Duration::from_secs(1));
+        assert_eq!(settings.retry_delay(3), Duration::from_secs(4));
+        assert_eq!(settings.retry_delay(10), MAX_RETRY_DELAY);
+        assert_eq!(settings.retry_delay(u32::MAX), MAX_RETRY_DELAY);
+    }
+
+    #[test]
+    fn connect_options_apply_the_settings() {
+        let settings = DatabaseSettings {
+            ssl_mode: SslMode::Disable,
+            statement_timeout_ms: Some(1500),
+            ..Default::default()
+        };
+
+        let options = settings
+            .connect_options("postgres://postgres@localhost:5433/films")
+            .unwrap();
+
+        assert_eq!(options.get_port(), 5433);
+        assert_eq!(options.get_database(), Some("films"));
+        assert!(matches!(options.get_ssl_mode(), PgSslMode::Disable));
+        assert_eq!(options.get_options(), Some("-c statement_timeout=1500"));
+    }
+
+    #[actix_rt::test]
+    async fn connect_fails_without_a_url() {
+        let result = DatabaseSettings::default().connect().await;
+
+        assert!(matches!(result, Err(DatabaseError::MissingUrl)));
+    }
+
+    #[actix_rt::test]
+    async fn connect_gives_up_after_retrying() {
+        let settings = DatabaseSettings {
+            url: Some("postgres://postgres@127.0.0.1:1/films".to_string()),
+            ssl_mode: SslMode::Disable,
+            acquire_timeout_seconds: 1,
+            connect_retries: 2,
+            retry_delay_ms: 1,
+            ..Default::default()
+        };
+
+        let result = settings.connect().await;
+
+        assert!(matches!(
+            result,
+            Err(DatabaseError::Connect { attempts: 3, .. })
+        ));
+    }
+
+    #[actix_rt::test]
+    async fn replicas_give_up_quickly() {
+        let settings = DatabaseSettings {
+            replica_urls: vec!["postgres://postgres@127.0.0.1:1/films".to_string()],
+            ssl_mode: SslMode::Disable,
+            replica_acquire_timeout_ms: 100,
+            ..Default::default()
+        };
+        let replicas = settings.connect_replicas().unwrap();
+
+        let started = std::time::Instant::now();
+        assert!(replicas[0].acquire().await.is_err());
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
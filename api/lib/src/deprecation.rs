@@ -0,0 +1,369 @@
+use std::sync::Arc;
+
+use actix_web::{
+    body::MessageBody,
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    http::{
+        header::{HeaderName, HeaderValue, LINK},
+        Method,
+    },
+    middleware::{from_fn, Next},
+    Error,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::settings::Validate;
+
+pub const DEPRECATION: HeaderName = HeaderName::from_static("deprecation");
+pub const SUNSET: HeaderName = HeaderName::from_static("sunset");
+
+/// The `[deprecation]` section of the [`Settings`](crate::settings::Settings).
+/// Only `deprecated_at` can be set from the environment, the routes are only
+/// read from the settings file.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct DeprecationSettings {
+    /// The date of the routes that don't have their own. Routes without any
+    /// aren't announced.
+    pub deprecated_at: Option<DateTime<Utc>>,
+    /// The first route matching a request wins.
+    pub routes: Vec<DeprecatedRoute>,
+}
+
+impl Default for DeprecationSettings {
+    fn default() -> Self {
+        // every v1 route, with the v2 one replacing it, once v1 is given a date
+        let v1 = |method: &str, path: &str, successor: &str| DeprecatedRoute {
+            method: Some(method.to_string()),
+            path: format!("/api/v1/films{}", path),
+            deprecated_at: None,
+            sunset_at: None,
+            successor: Some(format!("/api/v2/films{}", successor)),
+        };
+        Self {
+            deprecated_at: None,
+            routes: vec![
+                v1("GET", "", ""),
+                v1("POST", "", ""),
+                v1("PUT", "", "/{film_id}"),
+                v1("GET", "/{film_id}", "/{film_id}"),
+                v1("DELETE", "/{film_id}", "/{film_id}"),
+            ],
+        }
+    }
+}
+
+/// A route, or every route under a prefix, announced as deprecated with the
+/// `Deprecation` and `Sunset` headers.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct DeprecatedRoute {
+    /// Every method when unset.
+    #[serde(default)]
+    pub method: Option<String>,
+    /// A route like `/api/v1/films/{film_id}`, or a prefix ending with `*`
+    /// like `/api/v1/*`.
+    pub path: String,
+    /// Since when the route is deprecated, which can be in the future. The
+    /// section's `deprecated_at` when unset.
+    #[serde(default)]
+    pub deprecated_at: Option<DateTime<Utc>>,
+    /// When the route stops being served.
+    #[serde(default)]
+    pub sunset_at: Option<DateTime<Utc>>,
+    /// What replaces the route, sent as a `successor-version` link.
+    #[serde(default)]
+    pub successor: Option<String>,
+}
+
+impl Validate for DeprecationSettings {
+    fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        for route in &self.routes {
+            let setting = format!("deprecation.routes `{}`", route.path);
+            if let Some(method) = &route.method {
+                if method.parse::<Method>().is_err() {
+                    problems.push(format!("{}: `{}` isn't an HTTP method", setting, method));
+                }
+            }
+            if !route.path.starts_with('/') {
+                problems.push(format!("{}: the path should start with /", setting));
+            }
+            let deprecated_at = route.deprecated_at.or(self.deprecated_at);
+            if route
+                .sunset_at
+                .zip(deprecated_at)
+                .is_some_and(|(sunset_at, deprecated_at)| sunset_at < deprecated_at)
+            {
+                problems.push(format!("{}: sunset_at is before deprecated_at", setting));
+            }
+            if let Some(successor) = &route.successor {
+                let valid = !successor.is_empty()
+                    && successor
+                        .chars()
+                        .all(|c| c.is_ascii_graphic() && c != '<' && c != '>');
+                if !valid {
+                    problems.push(format!(
+                        "{}: `{}` isn't a URL like /api/v2/films",
+                        setting, successor
+                    ));
+                }
+            }
+        }
+        problems
+    }
+}
+
+struct Rule {
+    method: Option<Method>,
+    path: String,
+    headers: Vec<(HeaderName, HeaderValue)>,
+}
+
+impl Rule {
+    fn new(route: &DeprecatedRoute, deprecated_at: DateTime<Utc>) -> Self {
+        let mut headers = vec![(
+            DEPRECATION,
+            // a structured field date, in seconds since the epoch
+            format!("@{}", deprecated_at.timestamp()),
+        )];
+        if let Some(sunset_at) = route.sunset_at {
+            headers.push((
+                SUNSET,
+                sunset_at.format("%a, %d %b %Y %H:%M:%S GMT").to_string(),
+            ));
+        }
+        if let Some(successor) = &route.successor {
+            headers.push((LINK, format!("<{}>; rel=\"successor-version\"", successor)));
+        }
+        let headers = headers
+            .into_iter()
+            .filter_map(|(name, value)| match HeaderValue::from_str(&value) {
+                Ok(value) => Some((name, value)),
+                Err(_) => {
+                    tracing::warn!("Skipping the invalid {} header: {}", name, value);
+                    None
+                }
+            })
+            .collect();
+        Self {
+            method: route
+                .method
+                .as_deref()
+                .and_then(|method| method.parse().ok()),
+            path: route.path.clone(),
+            headers,
+        }
+    }
+
+    fn matches(&self, req: &ServiceRequest) -> bool {
+        if self
+            .method
+            .as_ref()
+            .is_some_and(|method| method != req.method())
+        {
+            return false;
+        }
+        match self.path.strip_suffix('*') {
+            Some(prefix) => req.path().starts_with(prefix),
+            None => req.match_pattern().as_deref() == Some(self.path.as_str()),
+        }
+    }
+}
+
+/// Announces the deprecated routes, with a `Deprecation` header,
+/// and a `Sunset` one once it's known when they go away.
+#[derive(Clone)]
+pub struct Deprecation {
+    rules: Arc<Vec<Rule>>,
+}
+
+impl Deprecation {
+    /// The headers for the given, already validated, settings.
+    pub fn new(settings: &DeprecationSettings) -> Self {
+        Self {
+            rules: Arc::new(
+                settings
+                    .routes
+                    .iter()
+                    .filter_map(|route| {
+                        let deprecated_at = route.deprecated_at.or(settings.deprecated_at)?;
+                        Some(Rule::new(route, deprecated_at))
+                    })
+                    .collect(),
+            ),
+        }
+    }
+
+    /// The middleware adding the headers to the responses of the services it
+    /// wraps.
+    pub fn middleware<S, B>(
+        &self,
+    ) -> impl Transform<S, ServiceRequest, Response = ServiceResponse<B>, Error = Error, InitError = ()>
+    where
+        S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+        B: MessageBody + 'static,
+    {
+        let deprecation = self.clone();
+        from_fn(move |req, next| deprecation.clone().announce(req, next))
+    }
+
+    async fn announce<B: MessageBody>(
+        self,
+        req: ServiceRequest,
+        next: Next<B>,
+    ) -> Result<ServiceResponse<B>, Error> {
+        let rule = self.rules.iter().position(|rule| rule.matches(&req));
+        let mut res = next.call(req).await?;
+        if let Some(rule) = rule {
+            for (name, value) in &self.rules[rule].headers {
+                res.headers_mut().insert(name.clone(), value.clone());
+            }
+        }
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{web, App, HttpResponse};
+    use chrono::TimeZone;
+
+    fn route(method: Option<&str>, path: &str) -> DeprecatedRoute {
+        DeprecatedRoute {
+            method: method.map(String::from),
+            path: path.to_string(),
+            deprecated_at: Some(Utc.with_ymd_and_hms(2026, 10, 19, 0, 0, 0).unwrap()),
+            sunset_at: None,
+            successor: None,
+        }
+    }
+
+    fn settings(routes: Vec<DeprecatedRoute>) -> DeprecationSettings {
+        DeprecationSettings {
+            deprecated_at: None,
+            routes,
+        }
+    }
+
+    async fn headers(
+        settings: DeprecationSettings,
+        method: Method,
+        uri: &str,
+    ) -> actix_web::http::header::HeaderMap {
+        let app = actix_web::test::init_service(
+            App::new()
+                .wrap(Deprecation::new(&settings).middleware())
+                .route("/api/v1/films", web::get().to(HttpResponse::Ok))
+                .route("/api/v1/films", web::put().to(HttpResponse::Ok))
+                .route("/api/v1/films/{film_id}", web::get().to(HttpResponse::Ok))
+                .route(
+                    "/api/v1/films/{film_id}",
+                    web::delete().to(HttpResponse::Ok),
+                )
+                .route("/api/v2/films", web::get().to(HttpResponse::Ok)),
+        )
+        .await;
+        let req = actix_web::test::TestRequest::default()
+            .method(method)
+            .uri(uri)
+            .to_request();
+        actix_web::test::call_service(&app, req)
+            .await
+            .headers()
+            .clone()
+    }
+
+    #[actix_rt::test]
+    async fn deprecated_routes_are_announced() {
+        let routes = vec![DeprecatedRoute {
+            sunset_at: Some(Utc.with_ymd_and_hms(2027, 4, 1, 0, 0, 0).unwrap()),
+            successor: Some("/api/v2/films/{film_id}".to_string()),
+            ..route(None, "/api/v1/films/{film_id}")
+        }];
+
+        let headers = headers(settings(routes), Method::GET, "/api/v1/films/42").await;
+
+        assert_eq!(headers.get(DEPRECATION).unwrap(), "@1792368000");
+        assert_eq!(
+            headers.get(SUNSET).unwrap(),
+            "Thu, 01 Apr 2027 00:00:00 GMT"
+        );
+        assert_eq!(
+            headers.get(LINK).unwrap(),
+            r#"</api/v2/films/{film_id}>; rel="successor-version""#
+        );
+    }
+
+    #[actix_rt::test]
+    async fn prefixes_match_every_route_under_them() {
+        let settings = settings(vec![route(None, "/api/v1/*")]);
+
+        let v1 = headers(settings.clone(), Method::GET, "/api/v1/films/42").await;
+        let v2 = headers(settings, Method::GET, "/api/v2/films").await;
+
+        assert!(v1.contains_key(DEPRECATION));
+        assert!(!v1.contains_key(SUNSET));
+        assert!(!v2.contains_key(DEPRECATION));
+    }
+
+    #[actix_rt::test]
+    async fn v1_is_announced_once_given_a_date() {
+        let undated = DeprecationSettings::default();
+        let dated = DeprecationSettings {
+            deprecated_at: Some(Utc.with_ymd_and_hms(2026, 10, 19, 0, 0, 0).unwrap()),
+            ..DeprecationSettings::default()
+        };
+
+        let before = headers(undated, Method::GET, "/api/v1/films").await;
+        let get = headers(dated.clone(), Method::GET, "/api/v1/films").await;
+        let delete = headers(dated.clone(), Method::DELETE, "/api/v1/films/42").await;
+        let v2 = headers(dated, Method::GET, "/api/v2/films").await;
+
+        assert!(!before.contains_key(DEPRECATION));
+        assert_eq!(get.get(DEPRECATION).unwrap(), "@1792368000");
+        assert_eq!(
+            get.get(LINK).unwrap(),
+            r#"</api/v2/films>; rel="successor-version""#
+        );
+        assert_eq!(
+            delete.get(LINK).unwrap(),
+            r#"</api/v2/films/{film_id}>; rel="successor-version""#
+        );
+        assert!(!v2.contains_key(DEPRECATION));
+    }
+
+    #[actix_rt::test]
+    async fn routes_can_be_deprecated_for_a_single_method() {
+        let routes = vec![route(Some("PUT"), "/api/v1/films")];
+
+        let put = headers(settings(routes.clone()), Method::PUT, "/api/v1/films").await;
+        let get = headers(settings(routes), Method::GET, "/api/v1/films").await;
+
+        assert!(put.contains_key(DEPRECATION));
+        assert!(!get.contains_key(DEPRECATION));
+    }
+
+    #[test]
+    fn invalid_routes_are_reported() {
+        let settings = settings(vec![
+            DeprecatedRoute {
+                sunset_at: Some(Utc.with_ymd_and_hms(2020, 1, 1, 0, 0, 0).unwrap()),
+                ..route(Some("FETCH ME"), "api/v1/films")
+            },
+            DeprecatedRoute {
+                successor: Some("the v2 API".to_string()),
+                ..route(None, "/api/v1/*")
+            },
+        ]);
+
+        let problems = settings.problems();
+
+        assert_eq!(problems.len(), 4, "{:?}", problems);
+        assert!(problems[0].contains("isn't an HTTP method"));
+        assert!(problems[1].contains("should start with /"));
+        assert!(problems[2].contains("sunset_at is before deprecated_at"));
+        assert!(problems[3].contains("isn't a URL"));
+    }
+}
@@ -0,0 +1,135 @@
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+use shared::models::Film;
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+const EVENTS_CAPACITY: usize = 1024;
+
+/// Something that happened to the films catalogue.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FilmEvent {
+    Created(Film),
+    Updated(Film),
+    Deleted(Uuid),
+}
+
+impl FilmEvent {
+    pub fn kind(&self) -> FilmEventKind {
+        match self {
+            FilmEvent::Created(_) => FilmEventKind::Created,
+            FilmEvent::Updated(_) => FilmEventKind::Updated,
+            FilmEvent::Deleted(_) => FilmEventKind::Deleted,
+        }
+    }
+
+    pub fn film_id(&self) -> Uuid {
+        match self {
+            FilmEvent::Created(film) | FilmEvent::Updated(film) => film.id,
+            FilmEvent::Deleted(id) => *id,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum FilmEventKind {
+    #[serde(rename = "film.created")]
+    Created,
+    #[serde(rename = "film.updated")]
+    Updated,
+    #[serde(rename = "film.deleted")]
+    Deleted,
+}
+
+impl FilmEventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FilmEventKind::Created => "film.created",
+            FilmEventKind::Updated => "film.updated",
+            FilmEventKind::Deleted => "film.deleted",
+        }
+    }
+}
+
+impl fmt::Display for FilmEventKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for FilmEventKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "film.created" => Ok(FilmEventKind::Created),
+            "film.updated" => Ok(FilmEventKind::Updated),
+            "film.deleted" => Ok(FilmEventKind::Deleted),
+            _ => Err(format!("Unknown film event: {}", s)),
+        }
+    }
+}
+
+/// In-process bus where film changes are published. Cloning it gives
+/// another handle to the same bus.
+#[derive(Clone)]
+pub struct FilmEvents {
+    sender: broadcast::Sender<FilmEvent>,
+}
+
+impl FilmEvents {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(EVENTS_CAPACITY);
+        Self { sender }
+    }
+
+    pub fn publish(&self, event: FilmEvent) {
+        // an error only means nobody is subscribed right now
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<FilmEvent> {
+        self.sender.subscribe()
+    }
+}
+
+impl Default for FilmEvents {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn event_kind_round_trips() {
+        for kind in [
+            FilmEventKind::Created,
+            FilmEventKind::Updated,
+            FilmEventKind::Deleted,
+        ] {
+            assert_eq!(kind.as_str().parse::<FilmEventKind>(), Ok(kind));
+            assert_eq!(
+                serde_json::to_string(&kind).unwrap(),
+                format!("\"{}\"", kind)
+            );
+        }
+        assert!("film.archived".parse::<FilmEventKind>().is_err());
+    }
+
+    #[test]
+    fn subscribers_receive_published_events() {
+        let events = FilmEvents::new();
+        let mut receiver = events.subscribe();
+        let id = Uuid::new_v4();
+
+        events.publish(FilmEvent::Deleted(id));
+
+        let event = receiver.try_recv().unwrap();
+        assert_eq!(event.kind(), FilmEventKind::Deleted);
+        assert_eq!(event.film_id(), id);
+    }
+}
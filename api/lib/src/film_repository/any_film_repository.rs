@@ -0,0 +1,208 @@
+use std::{fmt, str::FromStr};
+
+use async_trait::async_trait;
+use shared::models::{CreateFilm, Film};
+use uuid::Uuid;
+
+use super::{
+    FilmRepository, FilmResult, MemoryFilmRepository, PostgresFilmRepository, SqliteFilmRepository,
+};
+
+/// Backend where the films are stored, chosen at startup with the
+/// `REPOSITORY` setting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Deserialize, serde::Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum RepositoryKind {
+    Memory,
+    #[default]
+    Postgres,
+    Sqlite,
+}
+
+impl RepositoryKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RepositoryKind::Memory => "memory",
+            RepositoryKind::Postgres => "postgres",
+            RepositoryKind::Sqlite => "sqlite",
+        }
+    }
+}
+
+impl fmt::Display for RepositoryKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for RepositoryKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "memory" => Ok(RepositoryKind::Memory),
+            "postgres" => Ok(RepositoryKind::Postgres),
+            "sqlite" => Ok(RepositoryKind::Sqlite),
+            _ => Err(format!(
+                "Unknown repository: {}. Expected memory, postgres or sqlite",
+                s
+            )),
+        }
+    }
+}
+
+impl From<RepositoryKind> for String {
+    fn from(kind: RepositoryKind) -> Self {
+        kind.as_str().to_string()
+    }
+}
+
+impl TryFrom<String> for RepositoryKind {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// Any of the available repositories, so the backend can be picked at
+/// runtime while the services keep a single concrete type.
+pub enum AnyFilmRepository {
+    Memory(MemoryFilmRepository),
+    Postgres(PostgresFilmRepository),
+    Sqlite(SqliteFilmRepository),
+}
+
+impl AnyFilmRepository {
+    pub fn kind(&self) -> RepositoryKind {
+        match self {
+            AnyFilmRepository::Memory(_) => RepositoryKind::Memory,
+            AnyFilmRepository::Postgres(_) => RepositoryKind::Postgres,
+            AnyFilmRepository::Sqlite(_) => RepositoryKind::Sqlite,
+        }
+    }
+}
+
+impl From<MemoryFilmRepository> for AnyFilmRepository {
+    fn from(repo: MemoryFilmRepository) -> Self {
+        AnyFilmRepository::Memory(repo)
+    }
+}
+
+impl From<PostgresFilmRepository> for AnyFilmRepository {
+    fn from(repo: PostgresFilmRepository) -> Self {
+        AnyFilmRepository::Postgres(repo)
+    }
+}
+
+impl From<SqliteFilmRepository> for AnyFilmRepository {
+    fn from(repo: SqliteFilmRepository) -> Self {
+        AnyFilmRepository::Sqlite(repo)
+    }
+}
+
+#[async_trait]
+impl FilmRepository for AnyFilmRepository {
+    async fn get_films(&self) -> FilmResult<Vec<Film>> {
+        match self {
+            AnyFilmRepository::Memory(repo) => repo.get_films().await,
+            AnyFilmRepository::Postgres(repo) => repo.get_films().await,
+            AnyFilmRepository::Sqlite(repo) => repo.get_films().await,
+        }
+    }
+
+    async fn get_films_page(&self, offset: u64, limit: u64) -> FilmResult<Vec<Film>> {
+        match self {
+            AnyFilmRepository::Memory(repo) => repo.get_films_page(offset, limit).await,
+            AnyFilmRepository::Postgres(repo) => repo.get_films_page(offset, limit).await,
+            AnyFilmRepository::Sqlite(repo) => repo.get_films_page(offset, limit).await,
+        }
+    }
+
+    async fn count_films(&self) -> FilmResult<u64> {
+        match self {
+            AnyFilmRepository::Memory(repo) => repo.count_films().await,
+            AnyFilmRepository::Postgres(repo) => repo.count_films().await,
+            AnyFilmRepository::Sqlite(repo) => repo.count_films().await,
+        }
+    }
+
+    async fn get_film(&self, film_id: &Uuid) -> FilmResult<Film> {
+        match self {
+            AnyFilmRepository::Memory(repo) => repo.get_film(film_id).await,
+            AnyFilmRepository::Postgres(repo) => repo.get_film(film_id).await,
+            AnyFilmRepository::Sqlite(repo) => repo.get_film(film_id).await,
+        }
+    }
+
+    async fn create_film(&self, create_film: &CreateFilm) -> FilmResult<Film> {
+        match self {
+            AnyFilmRepository::Memory(repo) => repo.create_film(create_film).await,
+            AnyFilmRepository::Postgres(repo) => repo.create_film(create_film).await,
+            AnyFilmRepository::Sqlite(repo) => repo.create_film(create_film).await,
+        }
+    }
+
+    async fn update_film(&self, film: &Film) -> FilmResult<Film> {
+        match self {
+            AnyFilmRepository::Memory(repo) => repo.update_film(film).await,
+            AnyFilmRepository::Postgres(repo) => repo.update_film(film).await,
+            AnyFilmRepository::Sqlite(repo) => repo.update_film(film).await,
+        }
+    }
+
+    async fn delete_film(&self, film_id: &Uuid) -> FilmResult<Uuid> {
+        match self {
+            AnyFilmRepository::Memory(repo) => repo.delete_film(film_id).await,
+            AnyFilmRepository::Postgres(repo) => repo.delete_film(film_id).await,
+            AnyFilmRepository::Sqlite(repo) => repo.delete_film(film_id).await,
+        }
+    }
+
+    async fn delete_film_if_present(&self, film_id: &Uuid) -> FilmResult<Option<Uuid>> {
+        match self {
+            AnyFilmRepository::Memory(repo) => repo.delete_film_if_present(film_id).await,
+            AnyFilmRepository::Postgres(repo) => repo.delete_film_if_present(film_id).await,
+            AnyFilmRepository::Sqlite(repo) => repo.delete_film_if_present(film_id).await,
+        }
+    }
+
+    async fn ping(&self) -> FilmResult<()> {
+        match self {
+            AnyFilmRepository::Memory(repo) => repo.ping().await,
+            AnyFilmRepository::Postgres(repo) => repo.ping().await,
+            AnyFilmRepository::Sqlite(repo) => repo.ping().await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repository_kind_parsing() {
+        assert_eq!("memory".parse(), Ok(RepositoryKind::Memory));
+        assert_eq!(" Postgres ".parse(), Ok(RepositoryKind::Postgres));
+        assert_eq!("SQLITE".parse(), Ok(RepositoryKind::Sqlite));
+        assert!("mongo".parse::<RepositoryKind>().is_err());
+        assert_eq!(RepositoryKind::default(), RepositoryKind::Postgres);
+    }
+
+    #[actix_rt::test]
+    async fn memory_repository_is_used() {
+        let repo = AnyFilmRepository::from(MemoryFilmRepository::new());
+        let film = repo
+            .create_film(&CreateFilm {
+                title: "Vertigo".to_string(),
+                director: "Alfred Hitchcock".to_string(),
+                year: 1958,
+                poster: "".to_string(),
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(repo.kind(), RepositoryKind::Memory);
+        assert_eq!(repo.get_film(&film.id).await.unwrap(), film);
+    }
+}
@@ -0,0 +1,569 @@
+use std::{
+    num::NonZeroUsize,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use lru::LruCache;
+use shared::models::{CreateFilm, Film};
+use uuid::Uuid;
+
+use super::{FilmRepository, FilmResult};
+use crate::metrics::Metrics;
+
+/// How many lookups were answered from the cache. Exported as well when the
+/// cache is given [`Metrics`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+struct Expiring<T> {
+    value: T,
+    expires_at: Instant,
+}
+
+impl<T: Clone> Expiring<T> {
+    fn get(&self) -> Option<T> {
+        (Instant::now() < self.expires_at).then(|| self.value.clone())
+    }
+}
+
+/// When a read of the inner repository started.
+struct Read {
+    generation: u64,
+    started_at: Instant,
+}
+
+/// Wraps any [`FilmRepository`] and keeps the films it reads in an LRU cache
+/// for `ttl`. Mutations go straight to the inner repository and invalidate
+/// what they change.
+pub struct CachedFilmRepository<R: FilmRepository> {
+    inner: R,
+    ttl: Duration,
+    films: Mutex<LruCache<Uuid, Expiring<Film>>>,
+    all: Mutex<Option<Expiring<Vec<Film>>>>,
+    /// Bumped by every invalidation, so a read that started before a change
+    /// doesn't cache what it read once the change is done.
+    generation: AtomicU64,
+    /// When the films last changed, reads starting less than `replica_lag`
+    /// later may have gone to a replica that doesn't have the change yet.
+    changed_at: Mutex<Option<Instant>>,
+    replica_lag: Duration,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    metrics: Option<Metrics>,
+}
+
+impl<R: FilmRepository> CachedFilmRepository<R> {
+    /// Caches up to `capacity` films, each for `ttl`.
+    pub fn new(inner: R, capacity: usize, ttl: Duration) -> Self {
+        Self {
+            inner,
+            ttl,
+            films: Mutex::new(LruCache::new(
+                NonZeroUsize::new(capacity).unwrap_or(NonZeroUsize::MIN),
+            )),
+            all: Mutex::new(None),
+            generation: AtomicU64::new(0),
+            changed_at: Mutex::new(None),
+            replica_lag: Duration::ZERO,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            metrics: None,
+        }
+    }
+
+    /// Counts the lookups in `metrics` too.
+    pub fn with_metrics(mut self, metrics: Metrics) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Doesn't cache what's read less than `replica_lag` after a change, as
+    /// it may come from a replica still missing it and would be served for
+    /// the whole TTL.
+    pub fn with_replica_lag(mut self, replica_lag: Duration) -> Self {
+        self.replica_lag = replica_lag;
+        self
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+
+    fn record(&self, hit: bool) {
+        let counter = if hit { &self.hits } else { &self.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+        if let Some(metrics) = &self.metrics {
+            metrics.observe_cache_lookup(hit);
+        }
+    }
+
+    fn expiring<T>(&self, value: T) -> Expiring<T> {
+        Expiring {
+            value,
+            expires_at: Instant::now() + self.ttl,
+        }
+    }
+
+    fn generation(&self) -> u64 {
+        self.generation.load(Ordering::SeqCst)
+    }
+
+    fn start_read(&self) -> Read {
+        Read {
+            generation: self.generation(),
+            started_at: Instant::now(),
+        }
+    }
+
+    /// Whether what `read` returned is still current, as far as we know.
+    fn can_cache(&self, read: &Read) -> bool {
+        let after_lag = self
+            .changed_at
+            .lock()
+            .map(|changed_at| {
+                changed_at.is_none_or(|changed_at| read.started_at >= changed_at + self.replica_lag)
+            })
+            .unwrap_or(false);
+        read.generation == self.generation() && after_lag
+    }
+
+    fn cache_film(&self, film: &Film) {
+        if let Ok(mut films) = self.films.lock() {
+            films.put(film.id, self.expiring(film.clone()));
+        }
+    }
+
+    /// Caches what `read` returned, unless the films changed in the meantime
+    /// or it may not have seen the last change.
+    fn cache_read_film(&self, film: &Film, read: &Read) {
+        if let Ok(mut films) = self.films.lock() {
+            if self.can_cache(read) {
+                films.put(film.id, self.expiring(film.clone()));
+            }
+        }
+    }
+
+    fn invalidate(&self, film_id: Option<&Uuid>) {
+        // bumped before the entries are dropped, which is under the same
+        // locks as the checks of `cache_read_film` and `get_films`
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        if let Ok(mut changed_at) = self.changed_at.lock() {
+            *changed_at = Some(Instant::now());
+        }
+        if let (Some(film_id), Ok(mut films)) = (film_id, self.films.lock()) {
+            films.pop(film_id);
+        }
+        if let Ok(mut all) = self.all.lock() {
+            *all = None;
+        }
+    }
+}
+
+#[async_trait]
+impl<R: FilmRepository> FilmRepository for CachedFilmRepository<R> {
+    async fn get_films(&self) -> FilmResult<Vec<Film>> {
+        let cached = self
+            .all
+            .lock()
+            .ok()
+            .and_then(|all| all.as_ref().and_then(Expiring::get));
+        self.record(cached.is_some());
+        if let Some(films) = cached {
+            return Ok(films);
+        }
+
+        let read = self.start_read();
+        let films = self.inner.get_films().await?;
+        if let Ok(mut all) = self.all.lock() {
+            if self.can_cache(&read) {
+                *all = Some(self.expiring(films.clone()));
+            }
+        }
+        Ok(films)
+    }
+
+    /// Not cached, every page would have to be invalidated on each change.
+    async fn get_films_page(&self, offset: u64, limit: u64) -> FilmResult<Vec<Film>> {
+        self.inner.get_films_page(offset, limit).await
+    }
+
+    async fn count_films(&self) -> FilmResult<u64> {
+        self.inner.count_films().await
+    }
+
+    async fn get_film(&self, film_id: &Uuid) -> FilmResult<Film> {
+        let cached = self
+            .films
+            .lock()
+            .ok()
+            .and_then(|mut films| films.get(film_id).and_then(Expiring::get));
+        self.record(cached.is_some());
+        if let Some(film) = cached {
+            return Ok(film);
+        }
+
+        let read = self.start_read();
+        let film = self.inner.get_film(film_id).await?;
+        self.cache_read_film(&film, &read);
+        Ok(film)
+    }
+
+    async fn create_film(&self, create_film: &CreateFilm) -> FilmResult<Film> {
+        let film = self.inner.create_film(create_film).await?;
+        self.invalidate(None);
+        self.cache_film(&film);
+        Ok(film)
+    }
+
+    async fn update_film(&self, film: &Film) -> FilmResult<Film> {
+        // invalidate first so a failed update doesn't leave a stale film, and
+        // again once done for what was read in the meantime
+        self.invalidate(Some(&film.id));
+        let result = self.inner.update_film(film).await;
+        self.invalidate(Some(&film.id));
+        let film = result?;
+        self.cache_film(&film);
+        Ok(film)
+    }
+
+    async fn delete_film(&self, film_id: &Uuid) -> FilmResult<Uuid> {
+        self.invalidate(Some(film_id));
+        let result = self.inner.delete_film(film_id).await;
+        self.invalidate(Some(film_id));
+        result
+    }
+
+    async fn delete_film_if_present(&self, film_id: &Uuid) -> FilmResult<Option<Uuid>> {
+        self.invalidate(Some(film_id));
+        let result = self.inner.delete_film_if_present(film_id).await;
+        self.invalidate(Some(film_id));
+        result
+    }
+
+    async fn ping(&self) -> FilmResult<()> {
+        self.inner.ping().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::film_repository::{FilmError, MemoryFilmRepository, MockFilmRepository};
+    use tokio::sync::Notify;
+
+    /// Reads return the films as they were when the read started, but only
+    /// once `release` is notified.
+    #[derive(Default)]
+    struct SlowReads {
+        inner: MemoryFilmRepository,
+        release: Notify,
+    }
+
+    #[async_trait]
+    impl FilmRepository for SlowReads {
+        async fn get_films(&self) -> FilmResult<Vec<Film>> {
+            let films = self.inner.get_films().await;
+            self.release.notified().await;
+            films
+        }
+
+        async fn get_films_page(&self, offset: u64, limit: u64) -> FilmResult<Vec<Film>> {
+            self.inner.get_films_page(offset, limit).await
+        }
+
+        async fn count_films(&self) -> FilmResult<u64> {
+            self.inner.count_films().await
+        }
+
+        async fn get_film(&self, film_id: &Uuid) -> FilmResult<Film> {
+            let film = self.inner.get_film(film_id).await;
+            self.release.notified().await;
+            film
+        }
+
+        async fn create_film(&self, create_film: &CreateFilm) -> FilmResult<Film> {
+            self.inner.create_film(create_film).await
+        }
+
+        async fn update_film(&self, film: &Film) -> FilmResult<Film> {
+            self.inner.update_film(film).await
+        }
+
+        async fn delete_film(&self, film_id: &Uuid) -> FilmResult<Uuid> {
+            self.inner.delete_film(film_id).await
+        }
+
+        async fn ping(&self) -> FilmResult<()> {
+            self.inner.ping().await
+        }
+    }
+
+    /// Writes go to the primary, reads to a replica that only has the films
+    /// as they were at the last `lag()` until it catches up.
+    #[derive(Default)]
+    struct LaggingReplica {
+        primary: MemoryFilmRepository,
+        replica: Mutex<Option<Vec<Film>>>,
+    }
+
+    impl LaggingReplica {
+        async fn lag(&self) {
+            let films = self.primary.get_films().await.unwrap();
+            *self.replica.lock().unwrap() = Some(films);
+        }
+
+        fn catch_up(&self) {
+            *self.replica.lock().unwrap() = None;
+        }
+
+        fn replicated(&self) -> Option<Vec<Film>> {
+            self.replica.lock().unwrap().clone()
+        }
+    }
+
+    #[async_trait]
+    impl FilmRepository for LaggingReplica {
+        async fn get_films(&self) -> FilmResult<Vec<Film>> {
+            match self.replicated() {
+                Some(films) => Ok(films),
+                None => self.primary.get_films().await,
+            }
+        }
+
+        async fn get_films_page(&self, offset: u64, limit: u64) -> FilmResult<Vec<Film>> {
+            self.primary.get_films_page(offset, limit).await
+        }
+
+        async fn count_films(&self) -> FilmResult<u64> {
+            self.primary.count_films().await
+        }
+
+        async fn get_film(&self, film_id: &Uuid) -> FilmResult<Film> {
+            match self.replicated() {
+                Some(films) => films
+                    .into_iter()
+                    .find(|film| film.id == *film_id)
+                    .ok_or(FilmError::NotFound(*film_id)),
+                None => self.primary.get_film(film_id).await,
+            }
+        }
+
+        async fn create_film(&self, create_film: &CreateFilm) -> FilmResult<Film> {
+            self.primary.create_film(create_film).await
+        }
+
+        async fn update_film(&self, film: &Film) -> FilmResult<Film> {
+            self.primary.update_film(film).await
+        }
+
+        async fn delete_film(&self, film_id: &Uuid) -> FilmResult<Uuid> {
+            self.primary.delete_film(film_id).await
+        }
+
+        async fn ping(&self) -> FilmResult<()> {
+            self.primary.ping().await
+        }
+    }
+
+    fn create_test_film(id: Uuid) -> Film {
+        Film {
+            id,
+            title: "title".to_string(),
+            director: "director".to_string(),
+            poster: "poster".to_string(),
+            year: 2001,
+            created_at: None,
+            updated_at: None,
+        }
+    }
+
+    fn create_test_create_film(id: &'static str) -> CreateFilm {
+        CreateFilm {
+            title: format!("title-{}", id),
+            director: format!("director-{}", id),
+            poster: format!("poster-{}", id),
+            year: 2001,
+        }
+    }
+
+    #[actix_rt::test]
+    async fn get_film_is_cached() {
+        let mut inner = MockFilmRepository::default();
+        inner
+            .expect_get_film()
+            .times(1)
+            .returning(|id| Ok(create_test_film(*id)));
+        let repo = CachedFilmRepository::new(inner, 10, Duration::from_secs(60));
+        let id = Uuid::new_v4();
+
+        let first = repo.get_film(&id).await.unwrap();
+        let second = repo.get_film(&id).await.unwrap();
+
+        assert_eq!(first, second);
+        assert_eq!(repo.stats(), CacheStats { hits: 1, misses: 1 });
+    }
+
+    #[actix_rt::test]
+    async fn lookups_are_exported() {
+        let metrics = Metrics::new();
+        let repo =
+            CachedFilmRepository::new(MemoryFilmRepository::new(), 10, Duration::from_secs(60))
+                .with_metrics(metrics.clone());
+
+        repo.get_films().await.unwrap();
+        repo.get_films().await.unwrap();
+
+        let body = metrics.render().unwrap();
+        assert!(body.contains(r#"film_cache_lookups_total{result="hit"} 1"#));
+        assert!(body.contains(r#"film_cache_lookups_total{result="miss"} 1"#));
+    }
+
+    #[actix_rt::test]
+    async fn entries_expire_after_ttl() {
+        let mut inner = MockFilmRepository::default();
+        inner.expect_get_films().times(2).returning(|| Ok(vec![]));
+        let repo = CachedFilmRepository::new(inner, 10, Duration::from_millis(20));
+
+        repo.get_films().await.unwrap();
+        actix_rt::time::sleep(Duration::from_millis(30)).await;
+        repo.get_films().await.unwrap();
+
+        assert_eq!(repo.stats(), CacheStats { hits: 0, misses: 2 });
+    }
+
+    #[actix_rt::test]
+    async fn least_recently_used_films_are_evicted() {
+        let mut inner = MockFilmRepository::default();
+        inner
+            .expect_get_film()
+            .times(3)
+            .returning(|id| Ok(create_test_film(*id)));
+        let repo = CachedFilmRepository::new(inner, 1, Duration::from_secs(60));
+        let (id1, id2) = (Uuid::new_v4(), Uuid::new_v4());
+
+        repo.get_film(&id1).await.unwrap();
+        repo.get_film(&id2).await.unwrap();
+        repo.get_film(&id1).await.unwrap();
+
+        assert_eq!(repo.stats(), CacheStats { hits: 0, misses: 3 });
+    }
+
+    #[actix_rt::test]
+    async fn errors_are_not_cached() {
+        let mut inner = MockFilmRepository::default();
+        inner
+            .expect_get_film()
+            .times(2)
+            .returning(|id| Err(FilmError::NotFound(*id)));
+        let repo = CachedFilmRepository::new(inner, 10, Duration::from_secs(60));
+        let id = Uuid::new_v4();
+
+        assert!(repo.get_film(&id).await.is_err());
+        assert!(repo.get_film(&id).await.is_err());
+    }
+
+    #[actix_rt::test]
+    async fn mutations_invalidate_the_cache() {
+        let repo =
+            CachedFilmRepository::new(MemoryFilmRepository::new(), 10, Duration::from_secs(60));
+        assert!(repo.get_films().await.unwrap().is_empty());
+
+        let film = repo
+            .create_film(&create_test_create_film("1"))
+            .await
+            .unwrap();
+        assert_eq!(repo.get_films().await.unwrap(), vec![film.clone()]);
+
+        let updated = repo
+            .update_film(&Film {
+                year: 1999,
+                ..film.clone()
+            })
+            .await
+            .unwrap();
+        assert_eq!(repo.get_film(&film.id).await.unwrap(), updated);
+        assert_eq!(repo.get_films().await.unwrap(), vec![updated]);
+
+        repo.delete_film(&film.id).await.unwrap();
+        assert!(repo.get_film(&film.id).await.is_err());
+        assert!(repo.get_films().await.unwrap().is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn reads_overtaken_by_a_change_are_not_cached() {
+        let inner = SlowReads::default();
+        let film = inner
+            .create_film(&create_test_create_film("1"))
+            .await
+            .unwrap();
+        let repo = CachedFilmRepository::new(inner, 10, Duration::from_secs(60));
+
+        let read = repo.get_film(&film.id);
+        let update = async {
+            let updated = repo
+                .update_film(&Film {
+                    year: 1999,
+                    ..film.clone()
+                })
+                .await
+                .unwrap();
+            repo.inner.release.notify_one();
+            updated
+        };
+        let (read, updated) = futures_util::join!(read, update);
+
+        assert_eq!(read.unwrap(), film);
+        assert_eq!(repo.get_film(&film.id).await.unwrap(), updated);
+    }
+
+    #[actix_rt::test]
+    async fn stale_replica_reads_are_not_cached() {
+        let repo =
+            CachedFilmRepository::new(LaggingReplica::default(), 10, Duration::from_secs(60))
+                .with_replica_lag(Duration::from_secs(60));
+
+        // the replica doesn't have the new film yet
+        repo.inner.lag().await;
+        let film = repo
+            .create_film(&create_test_create_film("1"))
+            .await
+            .unwrap();
+        assert!(repo.get_films().await.unwrap().is_empty());
+        repo.inner.catch_up();
+        assert_eq!(repo.get_films().await.unwrap(), vec![film.clone()]);
+
+        // nor that it was deleted
+        repo.inner.lag().await;
+        repo.delete_film(&film.id).await.unwrap();
+        assert_eq!(repo.get_film(&film.id).await.unwrap(), film);
+        repo.inner.catch_up();
+        assert!(repo.get_film(&film.id).await.is_err());
+    }
+
+    #[actix_rt::test]
+    async fn reads_are_cached_again_once_replicas_caught_up() {
+        let repo =
+            CachedFilmRepository::new(LaggingReplica::default(), 10, Duration::from_secs(60))
+                .with_replica_lag(Duration::from_millis(20));
+        repo.create_film(&create_test_create_film("1"))
+            .await
+            .unwrap();
+
+        actix_rt::time::sleep(Duration::from_millis(30)).await;
+        repo.get_films().await.unwrap();
+        repo.get_films().await.unwrap();
+
+        assert_eq!(repo.stats(), CacheStats { hits: 1, misses: 1 });
+    }
+}
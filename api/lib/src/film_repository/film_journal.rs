@@ -0,0 +1,328 @@
+//! Persistence for [`super::MemoryFilmRepository`]: a JSON snapshot of every
+//! film plus a write-ahead log with one JSON line per mutation. At startup
+//! the log is replayed on top of the snapshot, and it's folded back into the
+//! snapshot once it grows too long.
+
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use shared::models::Film;
+use uuid::Uuid;
+
+/// Log entries after which the snapshot is rewritten and the log emptied.
+const COMPACT_AFTER: usize = 1000;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub(super) enum JournalEntry {
+    /// The film was created or updated and now looks like this.
+    Upsert {
+        film: Film,
+    },
+    Delete {
+        id: Uuid,
+    },
+}
+
+impl JournalEntry {
+    pub fn apply(self, films: &mut HashMap<Uuid, Film>) {
+        match self {
+            JournalEntry::Upsert { film } => {
+                films.insert(film.id, film);
+            }
+            JournalEntry::Delete { id } => {
+                films.remove(&id);
+            }
+        }
+    }
+}
+
+pub(super) struct FilmJournal {
+    snapshot_path: PathBuf,
+    log_path: PathBuf,
+    log: File,
+    entries: usize,
+    compact_after: usize,
+    /// Set when a failed write couldn't be undone, after which the log may
+    /// end with a torn entry that later ones would follow.
+    broken: bool,
+}
+
+impl FilmJournal {
+    /// Opens the journal whose snapshot lives at `path`, with the log next to
+    /// it (`films.json` and `films.wal`), and returns the films they contain.
+    pub fn open(path: &Path) -> io::Result<(Self, HashMap<Uuid, Film>)> {
+        let snapshot_path = path.to_path_buf();
+        let log_path = path.with_extension("wal");
+        if let Some(parent) = snapshot_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut films = match fs::read(&snapshot_path) {
+            Ok(bytes) => serde_json::from_slice::<Vec<Film>>(&bytes)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+                .into_iter()
+                .map(|film| (film.id, film))
+                .collect(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e),
+        };
+
+        let replayed = match File::open(&log_path) {
+            Ok(file) => replay(file, &mut films)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => 0,
+            Err(e) => return Err(e),
+        };
+        tracing::info!(
+            "Loaded {} films from {} ({} log entries replayed)",
+            films.len(),
+            snapshot_path.display(),
+            replayed
+        );
+
+        let log = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&log_path)?;
+        let mut journal = Self {
+            snapshot_path,
+            log_path,
+            log,
+            entries: replayed,
+            compact_after: COMPACT_AFTER,
+            broken: false,
+        };
+        // start from a clean log, which also drops a torn last line
+        journal.compact(&films)?;
+        Ok((journal, films))
+    }
+
+    /// Whether the next [`FilmJournal::append`] should be given the films to
+    /// compact the log into.
+    pub fn compaction_due(&self) -> bool {
+        self.entries + 1 >= self.compact_after
+    }
+
+    /// Durably records `entry`, and then compacts the log into `films` when
+    /// given, which must be the state once `entry` is applied. A compaction
+    /// that fails is only logged, and tried again on the next append.
+    pub fn append(
+        &mut self,
+        entry: &JournalEntry,
+        films: Option<&HashMap<Uuid, Film>>,
+    ) -> io::Result<()> {
+        if self.broken {
+            return Err(io::Error::other(
+                "the log couldn't be repaired after a failed write",
+            ));
+        }
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+        let len = self.log.metadata()?.len();
+        if let Err(e) = self.log.write_all(&line).and_then(|_| self.log.sync_data()) {
+            // a partial line would be followed by the next entries and make
+            // the log unreadable
+            if let Err(truncate) = self.log.set_len(len) {
+                tracing::error!(
+                    "Couldn't undo a failed write to {}: {}",
+                    self.log_path.display(),
+                    truncate
+                );
+                self.broken = true;
+            }
+            return Err(e);
+        }
+        self.entries += 1;
+        if let Some(films) = films {
+            if let Err(e) = self.compact(films) {
+                tracing::warn!(
+                    "Couldn't compact {} into {}, trying again on the next change: {}",
+                    self.log_path.display(),
+                    self.snapshot_path.display(),
+                    e
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes every film to a new snapshot, atomically replacing the old one,
+    /// and empties the log.
+    fn compact(&mut self, films: &HashMap<Uuid, Film>) -> io::Result<()> {
+        let mut snapshot: Vec<&Film> = films.values().collect();
+        snapshot.sort_by_key(|film| film.id);
+
+        let tmp_path = self.snapshot_path.with_extension("tmp");
+        let mut tmp = File::create(&tmp_path)?;
+        tmp.write_all(&serde_json::to_vec_pretty(&snapshot)?)?;
+        tmp.sync_all()?;
+        fs::rename(&tmp_path, &self.snapshot_path)?;
+
+        self.log = File::create(&self.log_path)?;
+        self.log.sync_all()?;
+        self.log = OpenOptions::new().append(true).open(&self.log_path)?;
+        self.entries = 0;
+        Ok(())
+    }
+}
+
+/// Applies every entry of the log and returns how many there were. A last
+/// line that can't be parsed was cut by a crash while writing and is skipped.
+fn replay(file: File, films: &mut HashMap<Uuid, Film>) -> io::Result<usize> {
+    let mut lines = BufReader::new(file).lines().peekable();
+    let mut replayed = 0;
+    while let Some(line) = lines.next() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<JournalEntry>(&line) {
+            Ok(entry) => {
+                entry.apply(films);
+                replayed += 1;
+            }
+            Err(e) if lines.peek().is_none() => {
+                tracing::warn!("Skipping the incomplete last log entry: {}", e);
+            }
+            Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+        }
+    }
+    Ok(replayed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_path() -> PathBuf {
+        std::env::temp_dir()
+            .join(format!("film-journal-{}", Uuid::new_v4()))
+            .join("films.json")
+    }
+
+    fn create_test_film(id: &'static str) -> Film {
+        Film {
+            id: Uuid::new_v4(),
+            title: format!("title-{}", id),
+            director: format!("director-{}", id),
+            poster: format!("poster-{}", id),
+            year: 2001,
+            created_at: Some(chrono::Utc::now()),
+            updated_at: None,
+        }
+    }
+
+    fn append(films: &mut HashMap<Uuid, Film>, journal: &mut FilmJournal, entry: JournalEntry) {
+        entry.clone().apply(films);
+        let films = journal.compaction_due().then_some(&*films);
+        journal.append(&entry, films).unwrap();
+    }
+
+    fn upsert(films: &mut HashMap<Uuid, Film>, journal: &mut FilmJournal, film: Film) {
+        append(films, journal, JournalEntry::Upsert { film });
+    }
+
+    #[test]
+    fn log_is_replayed_on_open() {
+        let path = test_path();
+        let (mut journal, mut films) = FilmJournal::open(&path).unwrap();
+        let film1 = create_test_film("1");
+        let film2 = create_test_film("2");
+        upsert(&mut films, &mut journal, film1.clone());
+        upsert(&mut films, &mut journal, film2.clone());
+        append(
+            &mut films,
+            &mut journal,
+            JournalEntry::Delete { id: film1.id },
+        );
+        drop(journal);
+
+        let (_, reopened) = FilmJournal::open(&path).unwrap();
+
+        assert_eq!(reopened.len(), 1);
+        assert_eq!(reopened.get(&film2.id), Some(&film2));
+    }
+
+    #[test]
+    fn log_is_compacted_into_the_snapshot() {
+        let path = test_path();
+        let (mut journal, mut films) = FilmJournal::open(&path).unwrap();
+        journal.compact_after = 2;
+
+        upsert(&mut films, &mut journal, create_test_film("1"));
+        upsert(&mut films, &mut journal, create_test_film("2"));
+
+        assert_eq!(fs::read_to_string(path.with_extension("wal")).unwrap(), "");
+        let snapshot: Vec<Film> = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+        assert_eq!(snapshot.len(), 2);
+    }
+
+    #[test]
+    fn failed_compactions_are_tried_again() {
+        let path = test_path();
+        let (mut journal, mut films) = FilmJournal::open(&path).unwrap();
+        journal.compact_after = 1;
+        // the new snapshot can't be written where a directory is in the way
+        fs::create_dir(path.with_extension("tmp")).unwrap();
+
+        upsert(&mut films, &mut journal, create_test_film("1"));
+
+        assert_eq!(journal.entries, 1);
+        fs::remove_dir(path.with_extension("tmp")).unwrap();
+        upsert(&mut films, &mut journal, create_test_film("2"));
+        assert_eq!(journal.entries, 0);
+        let snapshot: Vec<Film> = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+        assert_eq!(snapshot.len(), 2);
+    }
+
+    #[test]
+    fn failed_writes_that_cant_be_undone_break_the_journal() {
+        let path = test_path();
+        let (mut journal, _) = FilmJournal::open(&path).unwrap();
+        // neither writable nor truncatable
+        journal.log = File::open(path.with_extension("wal")).unwrap();
+        let entry = JournalEntry::Upsert {
+            film: create_test_film("1"),
+        };
+
+        assert!(journal.append(&entry, None).is_err());
+        journal.log = OpenOptions::new()
+            .append(true)
+            .open(path.with_extension("wal"))
+            .unwrap();
+        assert!(journal.append(&entry, None).is_err());
+    }
+
+    #[test]
+    fn torn_last_entry_is_skipped() {
+        let path = test_path();
+        let (mut journal, mut films) = FilmJournal::open(&path).unwrap();
+        let film = create_test_film("1");
+        upsert(&mut films, &mut journal, film.clone());
+        drop(journal);
+        let mut log = OpenOptions::new()
+            .append(true)
+            .open(path.with_extension("wal"))
+            .unwrap();
+        log.write_all(br#"{"op":"delete","id":"#).unwrap();
+
+        let (_, reopened) = FilmJournal::open(&path).unwrap();
+
+        assert_eq!(reopened.get(&film.id), Some(&film));
+    }
+
+    #[test]
+    fn corrupted_log_fails_to_open() {
+        let path = test_path();
+        let (journal, _) = FilmJournal::open(&path).unwrap();
+        drop(journal);
+        fs::write(path.with_extension("wal"), "garbage\n{\"op\":\"delete\"}\n").unwrap();
+
+        assert!(FilmJournal::open(&path).is_err());
+    }
+}
@@ -1,22 +1,141 @@
-use std::{collections::HashMap, sync::RwLock};
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::{Arc, RwLock, RwLockReadGuard},
+};
 
 use async_trait::async_trait;
 use chrono::Utc;
 use shared::models::{CreateFilm, Film};
+use tokio::sync::{Mutex, OwnedMutexGuard};
 use uuid::Uuid;
 
-use super::{FilmRepository, FilmResult};
+use super::{
+    film_journal::{FilmJournal, JournalEntry},
+    FilmError, FilmRepository, FilmResult,
+};
 
 pub struct MemoryFilmRepository {
     films: RwLock<HashMap<Uuid, Film>>,
+    /// Only when the films are persisted to disk, see [`Self::open`]. Its
+    /// lock is held by each change until it's applied, so the films change in
+    /// the order the log records.
+    journal: Option<Arc<Mutex<FilmJournal>>>,
 }
 
 impl MemoryFilmRepository {
     pub fn new() -> Self {
         Self {
             films: RwLock::new(HashMap::new()),
+            journal: None,
         }
     }
+
+    /// A repository persisted to the JSON snapshot at `path` plus a
+    /// write-ahead log next to it, so the films survive restarts.
+    pub fn open(path: impl AsRef<Path>) -> FilmResult<Self> {
+        let path = path.as_ref();
+        let (journal, films) = FilmJournal::open(path).map_err(|e| {
+            FilmError::internal(format!(
+                "Couldn't open the films file {}: {}",
+                path.display(),
+                e
+            ))
+        })?;
+        Ok(Self {
+            films: RwLock::new(films),
+            journal: Some(Arc::new(Mutex::new(journal))),
+        })
+    }
+
+    /// A repository that already contains the bundled fixture films.
+    pub fn with_fixtures() -> Self {
+        Self::with_films(crate::seed::fixtures().iter().map(new_film))
+    }
+
+    /// A repository that already contains `films`, as they are.
+    pub fn with_films(films: impl IntoIterator<Item = Film>) -> Self {
+        Self {
+            films: RwLock::new(films.into_iter().map(|film| (film.id, film)).collect()),
+            journal: None,
+        }
+    }
+
+    /// Waits for the changes before this one to be done, when the films are
+    /// persisted.
+    async fn lock_journal(&self) -> Option<OwnedMutexGuard<FilmJournal>> {
+        match &self.journal {
+            Some(journal) => Some(journal.clone().lock_owned().await),
+            None => None,
+        }
+    }
+
+    fn read_films(&self) -> FilmResult<RwLockReadGuard<'_, HashMap<Uuid, Film>>> {
+        self.films.read().map_err(|e| {
+            FilmError::internal(format!(
+                "An error happened while trying to read films: {}",
+                e
+            ))
+        })
+    }
+
+    /// Records `entry` in the journal, if any, and then applies it. The files
+    /// are written on the blocking pool, without holding the films' lock.
+    async fn commit(
+        &self,
+        journal: Option<OwnedMutexGuard<FilmJournal>>,
+        entry: JournalEntry,
+    ) -> FilmResult<()> {
+        let _journal = match journal {
+            Some(mut journal) => {
+                let films = if journal.compaction_due() {
+                    let mut films = self.read_films()?.clone();
+                    entry.clone().apply(&mut films);
+                    Some(films)
+                } else {
+                    None
+                };
+                let logged = entry.clone();
+                let (journal, result) = tokio::task::spawn_blocking(move || {
+                    let result = journal.append(&logged, films.as_ref());
+                    (journal, result)
+                })
+                .await
+                .map_err(FilmError::internal)?;
+                if let Err(e) = result {
+                    let id = match &entry {
+                        JournalEntry::Upsert { film } => film.id,
+                        JournalEntry::Delete { id } => *id,
+                    };
+                    let err = format!("Couldn't persist the change to film {}: {}", id, e);
+                    tracing::error!(err);
+                    return Err(FilmError::Internal(err));
+                }
+                Some(journal)
+            }
+            None => None,
+        };
+        let mut films = self.films.write().map_err(|e| {
+            FilmError::internal(format!(
+                "An error happened while trying to change films: {}",
+                e
+            ))
+        })?;
+        entry.apply(&mut films);
+        Ok(())
+    }
+}
+
+fn new_film(create_film: &CreateFilm) -> Film {
+    Film {
+        id: uuid::Uuid::new_v4(),
+        title: create_film.title.clone(),
+        director: create_film.director.clone(),
+        year: create_film.year,
+        poster: create_film.poster.clone(),
+        created_at: Some(Utc::now()),
+        updated_at: None,
+    }
 }
 
 impl Default for MemoryFilmRepository {
@@ -27,12 +146,18 @@ impl Default for MemoryFilmRepository {
 
 #[async_trait]
 impl FilmRepository for MemoryFilmRepository {
+    #[tracing::instrument(skip_all, err)]
     async fn get_films(&self) -> FilmResult<Vec<Film>> {
         let result = self
             .films
             .read()
             .map(|films| films.clone().into_values().collect::<Vec<_>>())
-            .map_err(|e| format!("An error happened while trying to read films: {}", e));
+            .map_err(|e| {
+                FilmError::internal(format!(
+                    "An error happened while trying to read films: {}",
+                    e
+                ))
+            });
 
         if result.is_err() {
             tracing::error!("Couldn't retrive a films");
@@ -41,16 +166,40 @@ impl FilmRepository for MemoryFilmRepository {
         result
     }
 
+    #[tracing::instrument(skip_all, err)]
+    async fn get_films_page(&self, offset: u64, limit: u64) -> FilmResult<Vec<Film>> {
+        let mut films = self.get_films().await?;
+        films.sort_by_key(|film| (film.created_at, film.id));
+        Ok(films
+            .into_iter()
+            .skip(offset.try_into().unwrap_or(usize::MAX))
+            .take(limit.try_into().unwrap_or(usize::MAX))
+            .collect())
+    }
+
+    async fn count_films(&self) -> FilmResult<u64> {
+        self.films
+            .read()
+            .map(|films| films.len() as u64)
+            .map_err(FilmError::internal)
+    }
+
+    #[tracing::instrument(skip_all, err(level = "info"), fields(film.id = %film_id))]
     async fn get_film(&self, film_id: &uuid::Uuid) -> FilmResult<Film> {
         let result = self
             .films
             .read()
-            .map_err(|e| format!("An error happened while trying to read films: {}", e))
+            .map_err(|e| {
+                FilmError::internal(format!(
+                    "An error happened while trying to read films: {}",
+                    e
+                ))
+            })
             .and_then(|films| {
                 films
                     .get(film_id)
                     .cloned()
-                    .ok_or_else(|| format!("Couldn't find film: {}", film_id))
+                    .ok_or(FilmError::NotFound(*film_id))
             });
 
         if result.is_err() {
@@ -60,77 +209,71 @@ impl FilmRepository for MemoryFilmRepository {
         result
     }
 
+    #[tracing::instrument(skip_all, err)]
     async fn create_film(&self, create_film: &CreateFilm) -> FilmResult<Film> {
-        match self.films.write() {
-            Ok(mut films) => {
-                let new_film = Film {
-                    id: uuid::Uuid::new_v4(),
-                    title: create_film.title.clone(),
-                    director: create_film.director.clone(),
-                    year: create_film.year,
-                    poster: create_film.poster.clone(),
-                    created_at: Some(Utc::now()),
-                    updated_at: None,
-                };
-                films.insert(new_film.id, new_film.clone());
-                tracing::trace!("Film with id {} correctly created", new_film.id);
-                Ok(new_film)
-            }
-            Err(e) => {
-                let err = format!("An error happened while trying to update film: {}", e);
-                tracing::error!(err);
-                Err(err)
-            }
-        }
+        let journal = self.lock_journal().await;
+        let new_film = new_film(create_film);
+        self.commit(
+            journal,
+            JournalEntry::Upsert {
+                film: new_film.clone(),
+            },
+        )
+        .await?;
+        tracing::trace!("Film with id {} correctly created", new_film.id);
+        Ok(new_film)
     }
 
+    #[tracing::instrument(skip_all, err(level = "info"), fields(film.id = %film.id))]
     async fn update_film(&self, film: &Film) -> FilmResult<Film> {
-        match self.films.write() {
-            Ok(mut films) => {
-                let old_film = films.get_mut(&film.id);
-                match old_film {
-                    Some(old_film) => {
-                        let mut updated_film = film.to_owned();
-                        updated_film.created_at = old_film.created_at;
-                        updated_film.updated_at = Some(Utc::now());
-                        films.insert(film.id, updated_film.clone());
-                        tracing::debug!("Film with id {} correctly updated", film.id);
-                        Ok(updated_film)
-                    }
-                    None => {
-                        let err = format!("Film with id {} does not exist", film.id);
-                        tracing::error!(err);
-                        Err(err)
-                    }
-                }
+        let journal = self.lock_journal().await;
+        let created_at = match self.read_films()?.get(&film.id) {
+            Some(old_film) => old_film.created_at,
+            None => {
+                tracing::error!("Film with id {} does not exist", film.id);
+                return Err(FilmError::NotFound(film.id));
             }
-            Err(e) => {
-                let err = format!("An error happened while trying to update film: {}", e);
-                tracing::error!(err);
-                Err(err)
-            }
-        }
+        };
+        let mut updated_film = film.to_owned();
+        updated_film.created_at = created_at;
+        updated_film.updated_at = Some(Utc::now());
+        self.commit(
+            journal,
+            JournalEntry::Upsert {
+                film: updated_film.clone(),
+            },
+        )
+        .await?;
+        tracing::debug!("Film with id {} correctly updated", film.id);
+        Ok(updated_film)
     }
 
+    #[tracing::instrument(skip_all, err, fields(film.id = %film_id))]
     async fn delete_film(&self, film_id: &uuid::Uuid) -> FilmResult<Uuid> {
-        match self.films.write() {
-            Ok(mut films) => {
-                films.remove(film_id);
-                Ok(film_id.to_owned())
-            }
-            Err(e) => {
-                let err = format!("An error happened while trying to delete film: {}", e);
-                tracing::error!(err);
-                Err(err)
-            }
+        self.delete_film_if_present(film_id).await?;
+        Ok(film_id.to_owned())
+    }
+
+    async fn delete_film_if_present(&self, film_id: &uuid::Uuid) -> FilmResult<Option<Uuid>> {
+        let journal = self.lock_journal().await;
+        if !self.read_films()?.contains_key(film_id) {
+            return Ok(None);
         }
+        self.commit(journal, JournalEntry::Delete { id: *film_id })
+            .await?;
+        Ok(Some(film_id.to_owned()))
+    }
+
+    async fn ping(&self) -> FilmResult<()> {
+        // always reachable, unless a panic poisoned the lock
+        self.films.read().map(|_| ()).map_err(FilmError::internal)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::MemoryFilmRepository;
-    use crate::film_repository::FilmRepository;
+    use crate::film_repository::{FilmError, FilmRepository};
     use shared::models::{CreateFilm, Film};
     use std::{collections::HashMap, sync::RwLock};
 
@@ -175,6 +318,41 @@ mod tests {
         assert_eq!(result.len(), 0);
     }
 
+    #[actix_rt::test]
+    async fn opened_repo_survives_restarts() {
+        let path = std::env::temp_dir()
+            .join(format!("memory-film-repository-{}", uuid::Uuid::new_v4()))
+            .join("films.json");
+        let repo = MemoryFilmRepository::open(&path).unwrap();
+        let kept = repo
+            .create_film(&create_test_create_film("1"))
+            .await
+            .unwrap();
+        let deleted = repo
+            .create_film(&create_test_create_film("2"))
+            .await
+            .unwrap();
+        let kept = repo
+            .update_film(&Film { year: 1999, ..kept })
+            .await
+            .unwrap();
+        repo.delete_film(&deleted.id).await.unwrap();
+        drop(repo);
+
+        let repo = MemoryFilmRepository::open(&path).unwrap();
+
+        assert_eq!(repo.get_films().await.unwrap(), vec![kept]);
+    }
+
+    #[actix_rt::test]
+    async fn repo_with_fixtures_has_every_fixture() {
+        let repo = MemoryFilmRepository::with_fixtures();
+        let result = repo.get_films().await.unwrap();
+
+        assert_eq!(result.len(), crate::seed::fixtures().len());
+        assert!(result.iter().all(|f| f.created_at.is_some()));
+    }
+
     #[actix_rt::test]
     async fn get_films_works() {
         let store = RwLock::new(HashMap::new());
@@ -186,7 +364,10 @@ mod tests {
             store.insert(film2.id, film2.clone());
         }
 
-        let repo = MemoryFilmRepository { films: store };
+        let repo = MemoryFilmRepository {
+            films: store,
+            journal: None,
+        };
         let result = repo.get_films().await;
 
         assert!(result.is_ok());
@@ -196,13 +377,33 @@ mod tests {
         assert!(result.iter().any(|f| f.id == film2.id));
     }
 
+    #[actix_rt::test]
+    async fn get_films_page_is_ordered_by_creation() {
+        let first = chrono::Utc::now() - chrono::Duration::days(1);
+        let films: Vec<Film> = (0..4)
+            .map(|i| Film {
+                created_at: Some(first + chrono::Duration::seconds(i)),
+                ..create_test_film("1")
+            })
+            .collect();
+        let repo = MemoryFilmRepository::with_films(films.iter().rev().cloned());
+
+        let page = repo.get_films_page(1, 2).await.unwrap();
+
+        assert_eq!(page, films[1..3]);
+        assert_eq!(repo.count_films().await.unwrap(), 4);
+    }
+
     #[actix_rt::test]
     async fn get_film_works() {
         let store = RwLock::new(HashMap::new());
         let film = create_test_film("1");
         store.write().unwrap().insert(film.id, film.clone());
 
-        let repo = MemoryFilmRepository { films: store };
+        let repo = MemoryFilmRepository {
+            films: store,
+            journal: None,
+        };
         let result = repo.get_film(&film.id).await;
 
         assert!(result.is_ok());
@@ -217,8 +418,7 @@ mod tests {
         let result = repo.update_film(&film_update).await;
 
         assert!(result.is_err());
-        let err = result.unwrap_err();
-        assert!(err.contains("does not exist"));
+        assert_eq!(result.unwrap_err(), FilmError::NotFound(film_update.id));
     }
 
     #[actix_rt::test]
@@ -226,7 +426,10 @@ mod tests {
         let store = RwLock::new(HashMap::new());
         let create_film = create_test_create_film("1");
 
-        let repo = MemoryFilmRepository { films: store };
+        let repo = MemoryFilmRepository {
+            films: store,
+            journal: None,
+        };
         let result = repo.create_film(&create_film).await;
 
         assert!(result.is_ok());
@@ -248,7 +451,10 @@ mod tests {
         film_update.title = "new-title".to_string();
         film_update.year = 2002;
 
-        let repo = MemoryFilmRepository { films: store };
+        let repo = MemoryFilmRepository {
+            films: store,
+            journal: None,
+        };
         let result = repo.update_film(&film_update).await;
 
         assert!(result.is_ok());
@@ -273,12 +479,14 @@ mod tests {
 
         let film_update = create_test_film("2");
 
-        let repo = MemoryFilmRepository { films: store };
+        let repo = MemoryFilmRepository {
+            films: store,
+            journal: None,
+        };
         let result = repo.update_film(&film_update).await;
 
         assert!(result.is_err());
-        let err = result.unwrap_err();
-        assert!(err.contains("does not exist"));
+        assert_eq!(result.unwrap_err(), FilmError::NotFound(film_update.id));
     }
 
     #[actix_rt::test]
@@ -287,7 +495,10 @@ mod tests {
         let film = create_test_film("1");
         store.write().unwrap().insert(film.id, film.clone());
 
-        let repo = MemoryFilmRepository { films: store };
+        let repo = MemoryFilmRepository {
+            films: store,
+            journal: None,
+        };
         let result = repo.delete_film(&film.id).await;
 
         assert!(result.is_ok());
@@ -0,0 +1,100 @@
+use std::{future::Future, time::Instant};
+
+use async_trait::async_trait;
+use shared::models::{CreateFilm, Film};
+use uuid::Uuid;
+
+use super::{FilmRepository, FilmResult};
+use crate::metrics::Metrics;
+
+/// Wraps any [`FilmRepository`] and times every operation in the
+/// [`Metrics`], by operation and outcome.
+pub struct MeteredFilmRepository<R: FilmRepository> {
+    inner: R,
+    metrics: Metrics,
+}
+
+impl<R: FilmRepository> MeteredFilmRepository<R> {
+    pub fn new(inner: R, metrics: Metrics) -> Self {
+        Self { inner, metrics }
+    }
+
+    async fn timed<T>(
+        &self,
+        operation: &str,
+        future: impl Future<Output = FilmResult<T>>,
+    ) -> FilmResult<T> {
+        let start = Instant::now();
+        let result = future.await;
+        self.metrics
+            .observe_operation(operation, result.is_ok(), start.elapsed());
+        result
+    }
+}
+
+#[async_trait]
+impl<R: FilmRepository> FilmRepository for MeteredFilmRepository<R> {
+    async fn get_films(&self) -> FilmResult<Vec<Film>> {
+        self.timed("get_films", self.inner.get_films()).await
+    }
+
+    async fn get_films_page(&self, offset: u64, limit: u64) -> FilmResult<Vec<Film>> {
+        self.timed("get_films_page", self.inner.get_films_page(offset, limit))
+            .await
+    }
+
+    async fn count_films(&self) -> FilmResult<u64> {
+        self.timed("count_films", self.inner.count_films()).await
+    }
+
+    async fn get_film(&self, film_id: &Uuid) -> FilmResult<Film> {
+        self.timed("get_film", self.inner.get_film(film_id)).await
+    }
+
+    async fn create_film(&self, create_film: &CreateFilm) -> FilmResult<Film> {
+        self.timed("create_film", self.inner.create_film(create_film))
+            .await
+    }
+
+    async fn update_film(&self, film: &Film) -> FilmResult<Film> {
+        self.timed("update_film", self.inner.update_film(film))
+            .await
+    }
+
+    async fn delete_film(&self, film_id: &Uuid) -> FilmResult<Uuid> {
+        self.timed("delete_film", self.inner.delete_film(film_id))
+            .await
+    }
+
+    async fn delete_film_if_present(&self, film_id: &Uuid) -> FilmResult<Option<Uuid>> {
+        self.timed("delete_film", self.inner.delete_film_if_present(film_id))
+            .await
+    }
+
+    async fn ping(&self) -> FilmResult<()> {
+        self.timed("ping", self.inner.ping()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::film_repository::MemoryFilmRepository;
+
+    #[actix_rt::test]
+    async fn operations_are_timed() {
+        let metrics = Metrics::new();
+        let repo = MeteredFilmRepository::new(MemoryFilmRepository::new(), metrics.clone());
+
+        let _ = repo.get_films().await.unwrap();
+        let _ = repo.get_film(&Uuid::new_v4()).await.unwrap_err();
+
+        let body = metrics.render().unwrap();
+        assert!(body.contains(
+            r#"repository_operation_duration_seconds_count{operation="get_films",outcome="ok"} 1"#
+        ));
+        assert!(body.contains(
+            r#"repository_operation_duration_seconds_count{operation="get_film",outcome="error"} 1"#
+        ));
+    }
+}
@@ -1,22 +1,92 @@
+mod any_film_repository;
+mod cached_film_repository;
+mod film_journal;
 mod memory_film_repository;
+mod metered_film_repository;
 mod postgres_film_repository;
+mod publishing_film_repository;
+mod sqlite_film_repository;
 
+pub use any_film_repository::{AnyFilmRepository, RepositoryKind};
+pub use cached_film_repository::{CacheStats, CachedFilmRepository};
 pub use memory_film_repository::MemoryFilmRepository;
-pub use postgres_film_repository::PostgresFilmRepository;
+pub use metered_film_repository::MeteredFilmRepository;
+pub use postgres_film_repository::{read_your_writes, PostgresFilmRepository};
+pub use publishing_film_repository::PublishingFilmRepository;
+pub use sqlite_film_repository::SqliteFilmRepository;
+
+use std::fmt;
 
 use async_trait::async_trait;
 use shared::models::{CreateFilm, Film};
 use uuid::Uuid;
 
-pub type FilmError = String;
+/// Why a [`FilmRepository`] call failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FilmError {
+    /// There's no film with this id.
+    NotFound(Uuid),
+    /// The films couldn't be read or written, the message is for the logs.
+    Internal(String),
+}
+
+impl FilmError {
+    pub fn internal(error: impl fmt::Display) -> Self {
+        FilmError::Internal(error.to_string())
+    }
+
+    /// For the statements on a single film, where no row means no film.
+    fn from_sqlx(error: sqlx::Error, film_id: &Uuid) -> Self {
+        match error {
+            sqlx::Error::RowNotFound => FilmError::NotFound(*film_id),
+            error => FilmError::internal(error),
+        }
+    }
+}
+
+impl fmt::Display for FilmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FilmError::NotFound(film_id) => write!(f, "Film {} doesn't exist", film_id),
+            FilmError::Internal(message) => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for FilmError {}
+
+impl From<sqlx::Error> for FilmError {
+    fn from(error: sqlx::Error) -> Self {
+        FilmError::internal(error)
+    }
+}
+
 pub type FilmResult<T> = Result<T, FilmError>;
 
 #[cfg_attr(test, mockall::automock)]
 #[async_trait]
 pub trait FilmRepository: Send + Sync + 'static {
     async fn get_films(&self) -> FilmResult<Vec<Film>>;
+    /// Up to `limit` films after the first `offset`, oldest first, with the
+    /// id breaking ties.
+    async fn get_films_page(&self, offset: u64, limit: u64) -> FilmResult<Vec<Film>>;
+    async fn count_films(&self) -> FilmResult<u64>;
     async fn get_film(&self, id: &Uuid) -> FilmResult<Film>;
     async fn create_film(&self, id: &CreateFilm) -> FilmResult<Film>;
     async fn update_film(&self, id: &Film) -> FilmResult<Film>;
     async fn delete_film(&self, id: &Uuid) -> FilmResult<Uuid>;
+    /// Like [`delete_film`](Self::delete_film), with `None` when there was no
+    /// film to delete rather than the backend's usual answer. Only backends
+    /// that don't report missing films need to override it.
+    async fn delete_film_if_present(&self, id: &Uuid) -> FilmResult<Option<Uuid>> {
+        self.delete_film(id).await.map(Some)
+    }
+    /// Checks the films can be reached, for readiness probes.
+    async fn ping(&self) -> FilmResult<()>;
+}
+
+/// Records how many rows the statement of the current span returned or
+/// changed, in its `db.rows` field.
+fn record_rows(rows: u64) {
+    tracing::Span::current().record("db.rows", rows);
 }
@@ -1,94 +1,376 @@
+use std::{
+    cell::Cell,
+    future::Future,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
 use async_trait::async_trait;
 use shared::models::{CreateFilm, Film};
+use sqlx::PgPool;
+use tracing::field::Empty;
 use uuid::Uuid;
 
-use super::{FilmRepository, FilmResult};
+use super::{record_rows, FilmError, FilmRepository, FilmResult};
+
+const SELECT_FILMS: &str =
+    "SELECT id, title, director, year, poster, created_at, updated_at FROM films";
+const SELECT_FILMS_PAGE: &str =
+    "SELECT id, title, director, year, poster, created_at, updated_at FROM films \
+     ORDER BY created_at NULLS FIRST, id LIMIT $1 OFFSET $2";
+const COUNT_FILMS: &str = "SELECT COUNT(*) FROM films";
+const SELECT_FILM: &str =
+    "SELECT id, title, director, year, poster, created_at, updated_at FROM films WHERE id = $1";
+const INSERT_FILM: &str =
+    "INSERT INTO films (title, director, year, poster) VALUES ($1, $2, $3, $4) \
+     RETURNING id, title, director, year, poster, created_at, updated_at";
+const UPDATE_FILM: &str = "UPDATE films \
+     SET title = $2, director = $3, year = $4, poster = $5, updated_at = CURRENT_TIMESTAMP \
+     WHERE id = $1 \
+     RETURNING id, title, director, year, poster, created_at, updated_at";
+const DELETE_FILM: &str = "DELETE FROM films WHERE id = $1 RETURNING id";
+const PING: &str = "SELECT 1";
+
+/// How long reads skip a replica that couldn't be reached.
+const REPLICA_DOWN_FOR: Duration = Duration::from_secs(30);
+
+tokio::task_local! {
+    static WROTE_TO_PRIMARY: Cell<bool>;
+}
+
+/// Runs `f` as a single unit of work: once it writes through a
+/// [`PostgresFilmRepository`], the rest of its reads go to the primary so it
+/// always sees its own writes, regardless of replication lag.
+///
+/// Outside of this scope reads are always routed to the replicas.
+pub async fn read_your_writes<F: Future>(f: F) -> F::Output {
+    WROTE_TO_PRIMARY.scope(Cell::new(false), f).await
+}
+
+/// A read replica, and until when it's considered down.
+struct Replica {
+    pool: PgPool,
+    down_until: Mutex<Option<Instant>>,
+}
+
+impl Replica {
+    fn is_up(&self) -> bool {
+        self.down_until
+            .lock()
+            .map(|down_until| down_until.is_none_or(|until| until <= Instant::now()))
+            .unwrap_or(true)
+    }
+
+    fn mark_down(&self) {
+        if let Ok(mut down_until) = self.down_until.lock() {
+            *down_until = Some(Instant::now() + REPLICA_DOWN_FOR);
+        }
+    }
+}
 
 pub struct PostgresFilmRepository {
-    pool: sqlx::PgPool,
+    pool: PgPool,
+    replicas: Vec<Replica>,
+    next_replica: AtomicUsize,
 }
 
 impl PostgresFilmRepository {
-    pub fn new(pool: sqlx::PgPool) -> Self {
-        Self { pool }
+    pub fn new(pool: PgPool) -> Self {
+        Self {
+            pool,
+            replicas: Vec::new(),
+            next_replica: AtomicUsize::new(0),
+        }
+    }
+
+    /// Routes `get_films` and `get_film` to `replicas`, round-robin. Writes
+    /// always go to the primary pool given to [`PostgresFilmRepository::new`].
+    /// A replica that can't be reached is skipped for [`REPLICA_DOWN_FOR`].
+    pub fn with_replicas(mut self, replicas: Vec<PgPool>) -> Self {
+        self.replicas = replicas
+            .into_iter()
+            .map(|pool| Replica {
+                pool,
+                down_until: Mutex::new(None),
+            })
+            .collect();
+        self
+    }
+
+    /// The pools a read should try, in order: the replicas that are up
+    /// starting with the next one in turn, and the primary as the last resort.
+    fn read_pools(&self) -> Vec<&PgPool> {
+        let wrote = WROTE_TO_PRIMARY.try_with(Cell::get).unwrap_or(false);
+        if wrote || self.replicas.is_empty() {
+            return vec![&self.pool];
+        }
+        let start = self.next_replica.fetch_add(1, Ordering::Relaxed);
+        (0..self.replicas.len())
+            .map(|i| &self.replicas[(start + i) % self.replicas.len()])
+            .filter(|replica| replica.is_up())
+            .map(|replica| &replica.pool)
+            .chain(std::iter::once(&self.pool))
+            .collect()
+    }
+
+    async fn read<'a, T, F, Fut>(&'a self, query: F) -> Result<T, sqlx::Error>
+    where
+        F: Fn(&'a PgPool) -> Fut,
+        Fut: Future<Output = Result<T, sqlx::Error>>,
+    {
+        let pools = self.read_pools();
+        let last = pools.len() - 1;
+        for (i, pool) in pools.into_iter().enumerate() {
+            match query(pool).await {
+                Err(e) if i < last && is_unavailable(&e) => {
+                    tracing::warn!(
+                        "Read replica unavailable, skipping it for {:?}: {}",
+                        REPLICA_DOWN_FOR,
+                        e
+                    );
+                    if let Some(replica) = self
+                        .replicas
+                        .iter()
+                        .find(|replica| std::ptr::eq(&replica.pool, pool))
+                    {
+                        replica.mark_down();
+                    }
+                }
+                result => return result,
+            }
+        }
+        unreachable!("there is always the primary pool to read from")
+    }
+
+    /// Pins the reads of the current [`read_your_writes`] scope to the primary.
+    fn wrote_to_primary(&self) {
+        let _ = WROTE_TO_PRIMARY.try_with(|wrote| wrote.set(true));
     }
 }
 
+/// Whether the database couldn't be reached at all, as opposed to the query
+/// failing.
+fn is_unavailable(error: &sqlx::Error) -> bool {
+    matches!(
+        error,
+        sqlx::Error::Io(_)
+            | sqlx::Error::Tls(_)
+            | sqlx::Error::PoolTimedOut
+            | sqlx::Error::PoolClosed
+            | sqlx::Error::WorkerCrashed
+    )
+}
+
 #[async_trait]
 impl FilmRepository for PostgresFilmRepository {
+    #[tracing::instrument(skip_all, err, fields(
+        otel.kind = "client", db.system = "postgresql", db.statement = SELECT_FILMS, db.rows = Empty,
+    ))]
     async fn get_films(&self) -> FilmResult<Vec<Film>> {
-        sqlx::query_as::<_, Film>(
-            r#"
-      SELECT id, title, director, year, poster, created_at, updated_at
-      FROM films
-      "#,
-        )
-        .fetch_all(&self.pool)
-        .await
-        .map_err(|e| e.to_string())
+        let films = self
+            .read(|pool| sqlx::query_as::<_, Film>(SELECT_FILMS).fetch_all(pool))
+            .await?;
+        record_rows(films.len() as u64);
+        Ok(films)
+    }
+
+    #[tracing::instrument(skip_all, err, fields(
+        otel.kind = "client", db.system = "postgresql", db.statement = SELECT_FILMS_PAGE,
+        db.rows = Empty,
+    ))]
+    async fn get_films_page(&self, offset: u64, limit: u64) -> FilmResult<Vec<Film>> {
+        let films = self
+            .read(|pool| {
+                sqlx::query_as::<_, Film>(SELECT_FILMS_PAGE)
+                    .bind(i64::try_from(limit).unwrap_or(i64::MAX))
+                    .bind(i64::try_from(offset).unwrap_or(i64::MAX))
+                    .fetch_all(pool)
+            })
+            .await?;
+        record_rows(films.len() as u64);
+        Ok(films)
     }
 
+    #[tracing::instrument(skip_all, err, fields(
+        otel.kind = "client", db.system = "postgresql", db.statement = COUNT_FILMS,
+    ))]
+    async fn count_films(&self) -> FilmResult<u64> {
+        let count = self
+            .read(|pool| sqlx::query_scalar::<_, i64>(COUNT_FILMS).fetch_one(pool))
+            .await?;
+        Ok(count as u64)
+    }
+
+    #[tracing::instrument(skip_all, err(level = "info"), fields(
+        otel.kind = "client", db.system = "postgresql", db.statement = SELECT_FILM, db.rows = Empty,
+        film.id = %film_id,
+    ))]
     async fn get_film(&self, film_id: &uuid::Uuid) -> FilmResult<Film> {
-        sqlx::query_as::<_, Film>(
-            r#"
-      SELECT id, title, director, year, poster, created_at, updated_at
-      FROM films
-      WHERE id = $1
-      "#,
-        )
-        .bind(film_id)
-        .fetch_one(&self.pool)
-        .await
-        .map_err(|e| e.to_string())
+        let film = self
+            .read(|pool| {
+                sqlx::query_as::<_, Film>(SELECT_FILM)
+                    .bind(film_id)
+                    .fetch_one(pool)
+            })
+            .await
+            .map_err(|e| FilmError::from_sqlx(e, film_id))?;
+        record_rows(1);
+        Ok(film)
     }
 
+    #[tracing::instrument(skip_all, err, fields(
+        otel.kind = "client", db.system = "postgresql", db.statement = INSERT_FILM, db.rows = Empty,
+    ))]
     async fn create_film(&self, create_film: &CreateFilm) -> FilmResult<Film> {
-        sqlx::query_as::<_, Film>(
-            r#"
-      INSERT INTO films (title, director, year, poster)
-      VALUES ($1, $2, $3, $4)
-      RETURNING id, title, director, year, poster, created_at, updated_at
-      "#,
-        )
-        .bind(&create_film.title)
-        .bind(&create_film.director)
-        .bind(create_film.year as i16)
-        .bind(&create_film.poster)
-        .fetch_one(&self.pool)
-        .await
-        .map_err(|e| e.to_string())
+        self.wrote_to_primary();
+        let film = sqlx::query_as::<_, Film>(INSERT_FILM)
+            .bind(&create_film.title)
+            .bind(&create_film.director)
+            .bind(create_film.year as i16)
+            .bind(&create_film.poster)
+            .fetch_one(&self.pool)
+            .await?;
+        record_rows(1);
+        Ok(film)
     }
 
+    #[tracing::instrument(skip_all, err(level = "info"), fields(
+        otel.kind = "client", db.system = "postgresql", db.statement = UPDATE_FILM, db.rows = Empty,
+        film.id = %film.id,
+    ))]
     async fn update_film(&self, film: &Film) -> FilmResult<Film> {
-        sqlx::query_as::<_, Film>(
-            r#"
-      UPDATE films
-      SET title = $2, director = $3, year = $4, poster = $5
-      WHERE id = $1
-      RETURNING id, title, director, year, poster, created_at, updated_at
-      "#,
-        )
-        .bind(film.id)
-        .bind(&film.title)
-        .bind(&film.director)
-        .bind(film.year as i16)
-        .bind(&film.poster)
-        .fetch_one(&self.pool)
-        .await
-        .map_err(|e| e.to_string())
+        self.wrote_to_primary();
+        let film = sqlx::query_as::<_, Film>(UPDATE_FILM)
+            .bind(film.id)
+            .bind(&film.title)
+            .bind(&film.director)
+            .bind(film.year as i16)
+            .bind(&film.poster)
+            .fetch_one(&self.pool)
+            .await
+            .map_err(|e| FilmError::from_sqlx(e, &film.id))?;
+        record_rows(1);
+        Ok(film)
     }
 
+    #[tracing::instrument(skip_all, err(level = "info"), fields(
+        otel.kind = "client", db.system = "postgresql", db.statement = DELETE_FILM, db.rows = Empty,
+        film.id = %film_id,
+    ))]
     async fn delete_film(&self, film_id: &uuid::Uuid) -> FilmResult<Uuid> {
-        sqlx::query_scalar::<_, Uuid>(
-            r#"
-      DELETE FROM films
-      WHERE id = $1
-      RETURNING id
-      "#,
-        )
-        .bind(film_id)
-        .fetch_one(&self.pool)
-        .await
-        .map_err(|e| e.to_string())
+        self.wrote_to_primary();
+        let id = sqlx::query_scalar::<_, Uuid>(DELETE_FILM)
+            .bind(film_id)
+            .fetch_one(&self.pool)
+            .await
+            .map_err(|e| FilmError::from_sqlx(e, film_id))?;
+        record_rows(1);
+        Ok(id)
+    }
+
+    /// Only the primary is checked, reads fall back to it anyway.
+    #[tracing::instrument(skip_all, err, fields(
+        otel.kind = "client", db.system = "postgresql", db.statement = PING,
+    ))]
+    async fn ping(&self) -> FilmResult<()> {
+        sqlx::query(PING).execute(&self.pool).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::postgres::PgPoolOptions;
+
+    fn lazy_pool(port: u16) -> PgPool {
+        PgPoolOptions::new()
+            .connect_lazy(&format!("postgres://postgres@127.0.0.1:{}/films", port))
+            .unwrap()
+    }
+
+    fn ports(pools: Vec<&PgPool>) -> Vec<u16> {
+        pools
+            .into_iter()
+            .map(|pool| pool.connect_options().get_port())
+            .collect()
+    }
+
+    #[actix_rt::test]
+    async fn reads_go_to_the_primary_without_replicas() {
+        let repo = PostgresFilmRepository::new(lazy_pool(1));
+
+        assert_eq!(ports(repo.read_pools()), vec![1]);
+    }
+
+    #[actix_rt::test]
+    async fn reads_rotate_over_the_replicas_and_fall_back_to_the_primary() {
+        let repo = PostgresFilmRepository::new(lazy_pool(1))
+            .with_replicas(vec![lazy_pool(2), lazy_pool(3)]);
+
+        assert_eq!(ports(repo.read_pools()), vec![2, 3, 1]);
+        assert_eq!(ports(repo.read_pools()), vec![3, 2, 1]);
+    }
+
+    #[actix_rt::test]
+    async fn reads_after_a_write_go_to_the_primary() {
+        let repo = PostgresFilmRepository::new(lazy_pool(1)).with_replicas(vec![lazy_pool(2)]);
+
+        read_your_writes(async {
+            assert_eq!(ports(repo.read_pools()), vec![2, 1]);
+            repo.wrote_to_primary();
+            assert_eq!(ports(repo.read_pools()), vec![1]);
+        })
+        .await;
+        // a new unit of work starts reading from the replicas again
+        read_your_writes(async { assert_eq!(ports(repo.read_pools()), vec![2, 1]) }).await;
+    }
+
+    #[actix_rt::test]
+    async fn reads_fall_back_to_the_primary_when_replicas_are_down() {
+        let repo = PostgresFilmRepository::new(lazy_pool(1)).with_replicas(vec![lazy_pool(2)]);
+
+        let result = repo
+            .read(|pool| async move {
+                match pool.connect_options().get_port() {
+                    1 => Ok("primary"),
+                    _ => Err(sqlx::Error::PoolTimedOut),
+                }
+            })
+            .await;
+
+        assert_eq!(result.ok(), Some("primary"));
+    }
+
+    #[actix_rt::test]
+    async fn replicas_that_are_down_are_skipped_for_a_while() {
+        let repo = PostgresFilmRepository::new(lazy_pool(1))
+            .with_replicas(vec![lazy_pool(2), lazy_pool(3)]);
+
+        let _ = repo
+            .read(|pool| async move {
+                match pool.connect_options().get_port() {
+                    2 => Err(sqlx::Error::PoolTimedOut),
+                    _ => Ok(()),
+                }
+            })
+            .await;
+
+        assert_eq!(ports(repo.read_pools()), vec![3, 1]);
+        assert_eq!(ports(repo.read_pools()), vec![3, 1]);
+        *repo.replicas[0].down_until.lock().unwrap() = Some(Instant::now());
+        assert_eq!(ports(repo.read_pools()), vec![3, 2, 1]);
+    }
+
+    #[actix_rt::test]
+    async fn query_errors_do_not_fall_back() {
+        let repo = PostgresFilmRepository::new(lazy_pool(1)).with_replicas(vec![lazy_pool(2)]);
+
+        let result: Result<(), _> = repo.read(|_| async { Err(sqlx::Error::RowNotFound) }).await;
+
+        assert!(result.is_err());
     }
 }
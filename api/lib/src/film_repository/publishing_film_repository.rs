@@ -0,0 +1,127 @@
+use async_trait::async_trait;
+use shared::models::{CreateFilm, Film};
+use uuid::Uuid;
+
+use super::{FilmRepository, FilmResult};
+use crate::events::{FilmEvent, FilmEvents};
+
+/// Wraps any [`FilmRepository`] and publishes a [`FilmEvent`] for every
+/// successful create, update or delete. Deleting a film that isn't there
+/// publishes nothing, even with backends that don't report it.
+pub struct PublishingFilmRepository<R: FilmRepository> {
+    inner: R,
+    events: FilmEvents,
+}
+
+impl<R: FilmRepository> PublishingFilmRepository<R> {
+    pub fn new(inner: R, events: FilmEvents) -> Self {
+        Self { inner, events }
+    }
+}
+
+#[async_trait]
+impl<R: FilmRepository> FilmRepository for PublishingFilmRepository<R> {
+    async fn get_films(&self) -> FilmResult<Vec<Film>> {
+        self.inner.get_films().await
+    }
+
+    async fn get_films_page(&self, offset: u64, limit: u64) -> FilmResult<Vec<Film>> {
+        self.inner.get_films_page(offset, limit).await
+    }
+
+    async fn count_films(&self) -> FilmResult<u64> {
+        self.inner.count_films().await
+    }
+
+    async fn get_film(&self, film_id: &Uuid) -> FilmResult<Film> {
+        self.inner.get_film(film_id).await
+    }
+
+    async fn create_film(&self, create_film: &CreateFilm) -> FilmResult<Film> {
+        let film = self.inner.create_film(create_film).await?;
+        self.events.publish(FilmEvent::Created(film.clone()));
+        Ok(film)
+    }
+
+    async fn update_film(&self, film: &Film) -> FilmResult<Film> {
+        let film = self.inner.update_film(film).await?;
+        self.events.publish(FilmEvent::Updated(film.clone()));
+        Ok(film)
+    }
+
+    async fn delete_film(&self, film_id: &Uuid) -> FilmResult<Uuid> {
+        self.delete_film_if_present(film_id).await?;
+        Ok(film_id.to_owned())
+    }
+
+    async fn delete_film_if_present(&self, film_id: &Uuid) -> FilmResult<Option<Uuid>> {
+        let id = self.inner.delete_film_if_present(film_id).await?;
+        if let Some(id) = id {
+            self.events.publish(FilmEvent::Deleted(id));
+        }
+        Ok(id)
+    }
+
+    async fn ping(&self) -> FilmResult<()> {
+        self.inner.ping().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::film_repository::MemoryFilmRepository;
+
+    #[actix_rt::test]
+    async fn mutations_are_published() {
+        let events = FilmEvents::new();
+        let mut receiver = events.subscribe();
+        let repo = PublishingFilmRepository::new(MemoryFilmRepository::new(), events);
+
+        let film = repo
+            .create_film(&CreateFilm {
+                title: "title".to_string(),
+                director: "director".to_string(),
+                year: 2001,
+                poster: "poster".to_string(),
+            })
+            .await
+            .unwrap();
+        let _ = repo.get_films().await.unwrap();
+        let updated = repo.update_film(&film).await.unwrap();
+        repo.delete_film(&film.id).await.unwrap();
+
+        assert_eq!(
+            receiver.try_recv().unwrap(),
+            FilmEvent::Created(film.clone())
+        );
+        assert_eq!(receiver.try_recv().unwrap(), FilmEvent::Updated(updated));
+        assert_eq!(receiver.try_recv().unwrap(), FilmEvent::Deleted(film.id));
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[actix_rt::test]
+    async fn failures_are_not_published() {
+        let events = FilmEvents::new();
+        let mut receiver = events.subscribe();
+        let repo = PublishingFilmRepository::new(MemoryFilmRepository::new(), events);
+
+        let result = repo.update_film(&Film::default()).await;
+
+        assert!(result.is_err());
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[actix_rt::test]
+    async fn deleting_missing_films_is_not_published() {
+        let events = FilmEvents::new();
+        let mut receiver = events.subscribe();
+        let repo = PublishingFilmRepository::new(MemoryFilmRepository::new(), events);
+        let film_id = Uuid::new_v4();
+
+        let result = repo.delete_film(&film_id).await;
+
+        assert_eq!(result.unwrap(), film_id);
+        assert!(receiver.try_recv().is_err());
+    }
+}
@@ -0,0 +1,309 @@
+use std::str::FromStr;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use shared::models::{CreateFilm, Film};
+use sqlx::{
+    sqlite::{SqliteConnectOptions, SqlitePoolOptions},
+    Executor,
+};
+use tracing::field::Empty;
+use uuid::{Builder, Uuid};
+
+use super::{record_rows, FilmError, FilmRepository, FilmResult};
+
+const SELECT_FILMS: &str =
+    "SELECT id, title, director, year, poster, created_at, updated_at FROM films";
+const SELECT_FILMS_PAGE: &str =
+    "SELECT id, title, director, year, poster, created_at, updated_at FROM films \
+     ORDER BY created_at NULLS FIRST, id LIMIT ?1 OFFSET ?2";
+const COUNT_FILMS: &str = "SELECT COUNT(*) FROM films";
+const SELECT_FILM: &str =
+    "SELECT id, title, director, year, poster, created_at, updated_at FROM films WHERE id = ?1";
+const INSERT_FILM: &str = "INSERT INTO films (id, title, director, year, poster, created_at) \
+     VALUES (?1, ?2, ?3, ?4, ?5, ?6) \
+     RETURNING id, title, director, year, poster, created_at, updated_at";
+const UPDATE_FILM: &str = "UPDATE films \
+     SET title = ?2, director = ?3, year = ?4, poster = ?5, updated_at = ?6 \
+     WHERE id = ?1 \
+     RETURNING id, title, director, year, poster, created_at, updated_at";
+const DELETE_FILM: &str = "DELETE FROM films WHERE id = ?1 RETURNING id";
+const PING: &str = "SELECT 1";
+
+/// A version 1 id, like the ones Postgres' `uuid_generate_v1()` makes, with
+/// a random node since there's no MAC address to use.
+fn time_based_id(now: DateTime<Utc>) -> Uuid {
+    // in 100ns ticks since the Gregorian calendar started, on 1582-10-15
+    const GREGORIAN_EPOCH: u64 = 0x01B2_1DD2_1381_4000;
+    let since_unix_epoch =
+        now.timestamp() as u64 * 10_000_000 + now.timestamp_subsec_nanos() as u64 / 100;
+    let random = Uuid::new_v4().into_bytes();
+    let counter = u16::from_be_bytes([random[0], random[1]]);
+    let mut node = [0; 6];
+    node.copy_from_slice(&random[10..]);
+    // the multicast bit tells a random node from a MAC address
+    node[0] |= 0x01;
+    Builder::from_gregorian_timestamp(GREGORIAN_EPOCH + since_unix_epoch, counter, &node)
+        .into_uuid()
+}
+
+pub struct SqliteFilmRepository {
+    pool: sqlx::SqlitePool,
+}
+
+impl SqliteFilmRepository {
+    pub fn new(pool: sqlx::SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Connects to the database at `url` (e.g. `sqlite://films.db`), creating
+    /// the file if needed, and makes sure the schema exists.
+    pub async fn connect(url: &str) -> Result<Self, sqlx::Error> {
+        let options = SqliteConnectOptions::from_str(url)?.create_if_missing(true);
+        let pool = SqlitePoolOptions::new().connect_with(options).await?;
+        let repo = Self::new(pool);
+        repo.migrate().await?;
+        Ok(repo)
+    }
+
+    pub fn pool(&self) -> &sqlx::SqlitePool {
+        &self.pool
+    }
+
+    /// Creates the tables if they don't exist yet.
+    pub async fn migrate(&self) -> Result<(), sqlx::Error> {
+        self.pool
+            .execute(include_str!("../../../db/sqlite/schema.sql"))
+            .await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl FilmRepository for SqliteFilmRepository {
+    #[tracing::instrument(skip_all, err, fields(
+        otel.kind = "client", db.system = "sqlite", db.statement = SELECT_FILMS, db.rows = Empty,
+    ))]
+    async fn get_films(&self) -> FilmResult<Vec<Film>> {
+        let films = sqlx::query_as::<_, Film>(SELECT_FILMS)
+            .fetch_all(&self.pool)
+            .await?;
+        record_rows(films.len() as u64);
+        Ok(films)
+    }
+
+    #[tracing::instrument(skip_all, err, fields(
+        otel.kind = "client", db.system = "sqlite", db.statement = SELECT_FILMS_PAGE,
+        db.rows = Empty,
+    ))]
+    async fn get_films_page(&self, offset: u64, limit: u64) -> FilmResult<Vec<Film>> {
+        let films = sqlx::query_as::<_, Film>(SELECT_FILMS_PAGE)
+            .bind(i64::try_from(limit).unwrap_or(i64::MAX))
+            .bind(i64::try_from(offset).unwrap_or(i64::MAX))
+            .fetch_all(&self.pool)
+            .await?;
+        record_rows(films.len() as u64);
+        Ok(films)
+    }
+
+    #[tracing::instrument(skip_all, err, fields(
+        otel.kind = "client", db.system = "sqlite", db.statement = COUNT_FILMS,
+    ))]
+    async fn count_films(&self) -> FilmResult<u64> {
+        let count = sqlx::query_scalar::<_, i64>(COUNT_FILMS)
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(count as u64)
+    }
+
+    #[tracing::instrument(skip_all, err(level = "info"), fields(
+        otel.kind = "client", db.system = "sqlite", db.statement = SELECT_FILM, db.rows = Empty,
+        film.id = %film_id,
+    ))]
+    async fn get_film(&self, film_id: &uuid::Uuid) -> FilmResult<Film> {
+        let film = sqlx::query_as::<_, Film>(SELECT_FILM)
+            .bind(film_id)
+            .fetch_one(&self.pool)
+            .await
+            .map_err(|e| FilmError::from_sqlx(e, film_id))?;
+        record_rows(1);
+        Ok(film)
+    }
+
+    #[tracing::instrument(skip_all, err, fields(
+        otel.kind = "client", db.system = "sqlite", db.statement = INSERT_FILM, db.rows = Empty,
+    ))]
+    async fn create_film(&self, create_film: &CreateFilm) -> FilmResult<Film> {
+        let now = Utc::now();
+        let film = sqlx::query_as::<_, Film>(INSERT_FILM)
+            .bind(time_based_id(now))
+            .bind(&create_film.title)
+            .bind(&create_film.director)
+            .bind(create_film.year as i16)
+            .bind(&create_film.poster)
+            .bind(now)
+            .fetch_one(&self.pool)
+            .await?;
+        record_rows(1);
+        Ok(film)
+    }
+
+    #[tracing::instrument(skip_all, err(level = "info"), fields(
+        otel.kind = "client", db.system = "sqlite", db.statement = UPDATE_FILM, db.rows = Empty,
+        film.id = %film.id,
+    ))]
+    async fn update_film(&self, film: &Film) -> FilmResult<Film> {
+        let film = sqlx::query_as::<_, Film>(UPDATE_FILM)
+            .bind(film.id)
+            .bind(&film.title)
+            .bind(&film.director)
+            .bind(film.year as i16)
+            .bind(&film.poster)
+            .bind(Utc::now())
+            .fetch_one(&self.pool)
+            .await
+            .map_err(|e| FilmError::from_sqlx(e, &film.id))?;
+        record_rows(1);
+        Ok(film)
+    }
+
+    #[tracing::instrument(skip_all, err(level = "info"), fields(
+        otel.kind = "client", db.system = "sqlite", db.statement = DELETE_FILM, db.rows = Empty,
+        film.id = %film_id,
+    ))]
+    async fn delete_film(&self, film_id: &uuid::Uuid) -> FilmResult<Uuid> {
+        let id = sqlx::query_scalar::<_, Uuid>(DELETE_FILM)
+            .bind(film_id)
+            .fetch_one(&self.pool)
+            .await
+            .map_err(|e| FilmError::from_sqlx(e, film_id))?;
+        record_rows(1);
+        Ok(id)
+    }
+
+    #[tracing::instrument(skip_all, err, fields(
+        otel.kind = "client", db.system = "sqlite", db.statement = PING,
+    ))]
+    async fn ping(&self) -> FilmResult<()> {
+        sqlx::query(PING).execute(&self.pool).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn create_test_repository() -> SqliteFilmRepository {
+        // an in-memory database only lives as long as its connection
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .connect("sqlite::memory:")
+            .await
+            .expect("couldn't open the database");
+        let repo = SqliteFilmRepository::new(pool);
+        repo.migrate().await.expect("couldn't create the schema");
+        repo
+    }
+
+    fn create_test_create_film(id: &'static str) -> CreateFilm {
+        CreateFilm {
+            title: format!("title-{}", id),
+            director: format!("director-{}", id),
+            poster: format!("poster-{}", id),
+            year: 2001,
+        }
+    }
+
+    #[test]
+    fn ids_carry_the_creation_time() {
+        let now = Utc::now();
+
+        let (first, second) = (time_based_id(now), time_based_id(now));
+
+        assert_eq!(first.get_version_num(), 1);
+        assert_ne!(first, second);
+        let (seconds, nanos) = first.get_timestamp().unwrap().to_unix();
+        assert_eq!(seconds, now.timestamp() as u64);
+        assert_eq!(nanos / 100, now.timestamp_subsec_nanos() / 100);
+    }
+
+    #[actix_rt::test]
+    async fn migrate_is_idempotent() {
+        let repo = create_test_repository().await;
+
+        assert!(repo.migrate().await.is_ok());
+    }
+
+    #[actix_rt::test]
+    async fn created_film_roundtrips() {
+        let repo = create_test_repository().await;
+
+        let film = repo
+            .create_film(&create_test_create_film("1"))
+            .await
+            .unwrap();
+
+        assert_eq!(film.id.get_version_num(), 1);
+        assert!(film.created_at.is_some());
+        assert!(film.updated_at.is_none());
+        assert_eq!(repo.get_film(&film.id).await.unwrap(), film);
+        assert_eq!(repo.get_films().await.unwrap(), vec![film]);
+    }
+
+    #[actix_rt::test]
+    async fn pages_are_ordered_by_creation() {
+        let repo = create_test_repository().await;
+        let mut films = vec![];
+        for id in ["1", "2", "3"] {
+            films.push(
+                repo.create_film(&create_test_create_film(id))
+                    .await
+                    .unwrap(),
+            );
+        }
+
+        assert_eq!(repo.get_films_page(1, 5).await.unwrap(), films[1..]);
+        assert_eq!(repo.count_films().await.unwrap(), 3);
+    }
+
+    #[actix_rt::test]
+    async fn update_sets_updated_at() {
+        let repo = create_test_repository().await;
+        let film = repo
+            .create_film(&create_test_create_film("1"))
+            .await
+            .unwrap();
+
+        let updated = repo
+            .update_film(&Film {
+                year: 1999,
+                ..film.clone()
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(updated.year, 1999);
+        assert_eq!(updated.created_at, film.created_at);
+        assert!(updated.updated_at.is_some());
+        assert!(repo.update_film(&Film::default()).await.is_err());
+    }
+
+    #[actix_rt::test]
+    async fn delete_removes_the_film() {
+        let repo = create_test_repository().await;
+        let film = repo
+            .create_film(&create_test_create_film("1"))
+            .await
+            .unwrap();
+
+        assert_eq!(repo.delete_film(&film.id).await.unwrap(), film.id);
+        assert!(repo.get_film(&film.id).await.is_err());
+        assert_eq!(
+            repo.delete_film(&film.id).await,
+            Err(FilmError::NotFound(film.id))
+        );
+    }
+}
@@ -0,0 +1,145 @@
+mod schema;
+
+pub use schema::{
+    schema, CreateFilmInput, FilmChange, FilmChanged, FilmObject, FilmSchema, MutationRoot,
+    QueryRoot, SubscriptionRoot, UpdateFilmInput,
+};
+
+use actix_web::{
+    http::header,
+    web::{self, ServiceConfig},
+    HttpRequest, HttpResponse,
+};
+use async_graphql::{
+    http::{WebSocket, WebSocketProtocols, WsMessage},
+    BatchRequest, Data,
+};
+use futures_util::StreamExt;
+
+use crate::{events::FilmEvents, film_repository::FilmRepository};
+
+pub fn service<R: FilmRepository>(cfg: &mut ServiceConfig) {
+    cfg.app_data(web::Data::new(schema::<R>())).service(
+        web::scope("/graphql")
+            // GET
+            .route("", web::get().to(playground))
+            .route("/ws", web::get().to(subscriptions::<R>))
+            // POST
+            .route("", web::post().to(execute::<R>)),
+    );
+}
+
+async fn execute<R: FilmRepository>(
+    request: web::Json<BatchRequest>,
+    schema: web::Data<FilmSchema<R>>,
+    repo: web::Data<R>,
+) -> HttpResponse {
+    let response = schema.execute_batch(request.into_inner().data(repo)).await;
+    HttpResponse::Ok().json(response)
+}
+
+/// GraphQL Playground, only available in debug builds.
+async fn playground(req: HttpRequest) -> HttpResponse {
+    if !cfg!(debug_assertions) {
+        return HttpResponse::NotFound().body("Not found");
+    }
+    let connection = req.connection_info();
+    let ws_scheme = if connection.scheme() == "https" {
+        "wss"
+    } else {
+        "ws"
+    };
+    let subscription_endpoint = format!("{}://{}{}/ws", ws_scheme, connection.host(), req.path());
+    let config = async_graphql::http::GraphQLPlaygroundConfig::new(req.path())
+        .subscription_endpoint(&subscription_endpoint);
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .insert_header((header::CONTENT_SECURITY_POLICY, PLAYGROUND_CSP))
+        .body(async_graphql::http::playground_source(config))
+}
+
+/// Lets the playground load from its CDN, with its fonts, and run its inline
+/// scripts.
+const PLAYGROUND_CSP: &str = "default-src 'self'; script-src 'self' 'unsafe-inline' https://cdn.jsdelivr.net; style-src 'self' 'unsafe-inline' https://cdn.jsdelivr.net https://fonts.googleapis.com; font-src 'self' https://fonts.gstatic.com; img-src 'self' data: https://cdn.jsdelivr.net; connect-src 'self' ws: wss:; frame-ancestors 'none'";
+
+/// Subscriptions over WebSocket, using either the `graphql-ws` or the
+/// `graphql-transport-ws` protocol.
+async fn subscriptions<R: FilmRepository>(
+    req: HttpRequest,
+    body: web::Payload,
+    schema: web::Data<FilmSchema<R>>,
+    repo: web::Data<R>,
+    events: Option<web::Data<FilmEvents>>,
+) -> actix_web::Result<HttpResponse> {
+    let protocol: WebSocketProtocols = req
+        .headers()
+        .get(header::SEC_WEBSOCKET_PROTOCOL)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|protocols| {
+            protocols
+                .split(',')
+                .find_map(|protocol| protocol.trim().parse().ok())
+        })
+        .ok_or_else(|| actix_web::error::ErrorBadRequest("Unsupported websocket protocol"))?;
+
+    let (mut response, session, stream) = actix_ws::handle(&req, body)?;
+    response.headers_mut().insert(
+        header::SEC_WEBSOCKET_PROTOCOL,
+        header::HeaderValue::from_static(protocol.sec_websocket_protocol()),
+    );
+
+    let mut data = Data::default();
+    data.insert(repo);
+    if let Some(events) = events {
+        data.insert(events.get_ref().clone());
+    }
+
+    let pong_session = session.clone();
+    let messages = stream
+        .take_while(|message| {
+            let open = !matches!(message, Ok(actix_ws::Message::Close(_)) | Err(_));
+            async move { open }
+        })
+        .filter_map(move |message| {
+            let mut session = pong_session.clone();
+            async move {
+                match message {
+                    Ok(actix_ws::Message::Text(text)) => Some(text.into_bytes()),
+                    Ok(actix_ws::Message::Binary(bytes)) => Some(bytes),
+                    Ok(actix_ws::Message::Ping(bytes)) => {
+                        let _ = session.pong(&bytes).await;
+                        None
+                    }
+                    _ => None,
+                }
+            }
+        });
+
+    let schema = schema.get_ref().clone();
+    actix_web::rt::spawn(async move {
+        let mut session = session;
+        let outgoing = WebSocket::new(schema, messages, protocol).connection_data(data);
+        let mut outgoing = std::pin::pin!(outgoing);
+        while let Some(message) = outgoing.next().await {
+            match message {
+                WsMessage::Text(text) => {
+                    if session.text(text).await.is_err() {
+                        return;
+                    }
+                }
+                WsMessage::Close(code, reason) => {
+                    let _ = session
+                        .close(Some(actix_ws::CloseReason {
+                            code: code.into(),
+                            description: Some(reason),
+                        }))
+                        .await;
+                    return;
+                }
+            }
+        }
+        let _ = session.close(None).await;
+    });
+
+    Ok(response)
+}
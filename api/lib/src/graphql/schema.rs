@@ -0,0 +1,441 @@
+use std::marker::PhantomData;
+
+use actix_web::web;
+use async_graphql::{
+    futures_util::{stream, Stream},
+    Context, Enum, Error, InputObject, Object, Result, Schema, SimpleObject, Subscription,
+};
+use chrono::{DateTime, Utc};
+use shared::models::{CreateFilm, Film};
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+use crate::{
+    events::{FilmEvent, FilmEventKind, FilmEvents},
+    film_repository::{FilmError, FilmRepository},
+};
+
+pub type FilmSchema<R> = Schema<QueryRoot<R>, MutationRoot<R>, SubscriptionRoot>;
+
+/// How deep and how big queries may be. The playground's introspection query,
+/// the largest we expect, is 13 deep with a complexity of 181.
+const MAX_DEPTH: usize = 15;
+const MAX_COMPLEXITY: usize = 250;
+
+/// Builds the schema. The repository (`web::Data<R>`) and, for
+/// subscriptions, the [`FilmEvents`] bus are provided with every request.
+pub fn schema<R: FilmRepository>() -> FilmSchema<R> {
+    Schema::build(
+        QueryRoot(PhantomData),
+        MutationRoot(PhantomData),
+        SubscriptionRoot,
+    )
+    .limit_depth(MAX_DEPTH)
+    .limit_complexity(MAX_COMPLEXITY)
+    .finish()
+}
+
+#[derive(SimpleObject, Debug, Clone, PartialEq, Eq)]
+#[graphql(name = "Film")]
+pub struct FilmObject {
+    pub id: Uuid,
+    pub title: String,
+    pub director: String,
+    pub year: u16,
+    pub poster: String,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+impl From<Film> for FilmObject {
+    fn from(film: Film) -> Self {
+        Self {
+            id: film.id,
+            title: film.title,
+            director: film.director,
+            year: film.year,
+            poster: film.poster,
+            created_at: film.created_at,
+            updated_at: film.updated_at,
+        }
+    }
+}
+
+#[derive(InputObject, Debug, Clone)]
+pub struct CreateFilmInput {
+    pub title: String,
+    pub director: String,
+    pub year: u16,
+    pub poster: String,
+}
+
+/// Only the given fields are changed, the rest keep their current value.
+#[derive(InputObject, Debug, Clone)]
+pub struct UpdateFilmInput {
+    pub id: Uuid,
+    pub title: Option<String>,
+    pub director: Option<String>,
+    pub year: Option<u16>,
+    pub poster: Option<String>,
+}
+
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilmChange {
+    Created,
+    Updated,
+    Deleted,
+}
+
+impl From<FilmEventKind> for FilmChange {
+    fn from(kind: FilmEventKind) -> Self {
+        match kind {
+            FilmEventKind::Created => FilmChange::Created,
+            FilmEventKind::Updated => FilmChange::Updated,
+            FilmEventKind::Deleted => FilmChange::Deleted,
+        }
+    }
+}
+
+#[derive(SimpleObject, Debug, Clone, PartialEq, Eq)]
+pub struct FilmChanged {
+    pub change: FilmChange,
+    pub film_id: Uuid,
+    /// The film after the change. Empty when it was deleted.
+    pub film: Option<FilmObject>,
+}
+
+impl From<FilmEvent> for FilmChanged {
+    fn from(event: FilmEvent) -> Self {
+        Self {
+            change: event.kind().into(),
+            film_id: event.film_id(),
+            film: match event {
+                FilmEvent::Created(film) | FilmEvent::Updated(film) => Some(film.into()),
+                FilmEvent::Deleted(_) => None,
+            },
+        }
+    }
+}
+
+fn repository<'a, R: FilmRepository>(ctx: &Context<'a>) -> Result<&'a web::Data<R>> {
+    ctx.data::<web::Data<R>>()
+}
+
+/// What was missing, or only that something went wrong for everything else,
+/// which is logged rather than shown to clients.
+fn graphql_error(error: FilmError) -> Error {
+    match error {
+        FilmError::NotFound(_) => Error::new(error.to_string()),
+        error => {
+            tracing::error!("{}", error);
+            Error::new("Internal server error")
+        }
+    }
+}
+
+pub struct QueryRoot<R>(PhantomData<R>);
+
+#[Object]
+impl<R: FilmRepository> QueryRoot<R> {
+    /// Every film, optionally only those whose title contains `title`.
+    async fn films(&self, ctx: &Context<'_>, title: Option<String>) -> Result<Vec<FilmObject>> {
+        let films = repository::<R>(ctx)?
+            .get_films()
+            .await
+            .map_err(graphql_error)?;
+        let title = title.map(|t| t.to_lowercase());
+        Ok(films
+            .into_iter()
+            .filter(|film| {
+                title
+                    .as_ref()
+                    .is_none_or(|t| film.title.to_lowercase().contains(t))
+            })
+            .map(FilmObject::from)
+            .collect())
+    }
+
+    /// The film with the given id, if it exists.
+    async fn film(&self, ctx: &Context<'_>, id: Uuid) -> Result<Option<FilmObject>> {
+        match repository::<R>(ctx)?.get_film(&id).await {
+            Ok(film) => Ok(Some(film.into())),
+            Err(FilmError::NotFound(_)) => Ok(None),
+            Err(e) => Err(graphql_error(e)),
+        }
+    }
+}
+
+pub struct MutationRoot<R>(PhantomData<R>);
+
+#[Object]
+impl<R: FilmRepository> MutationRoot<R> {
+    async fn create_film(&self, ctx: &Context<'_>, input: CreateFilmInput) -> Result<FilmObject> {
+        let create_film = CreateFilm {
+            title: input.title,
+            director: input.director,
+            year: input.year,
+            poster: input.poster,
+        };
+        let film = repository::<R>(ctx)?
+            .create_film(&create_film)
+            .await
+            .map_err(graphql_error)?;
+        Ok(film.into())
+    }
+
+    async fn update_film(&self, ctx: &Context<'_>, input: UpdateFilmInput) -> Result<FilmObject> {
+        let repo = repository::<R>(ctx)?;
+        let current = repo.get_film(&input.id).await.map_err(graphql_error)?;
+        let film = Film {
+            title: input.title.unwrap_or(current.title),
+            director: input.director.unwrap_or(current.director),
+            year: input.year.unwrap_or(current.year),
+            poster: input.poster.unwrap_or(current.poster),
+            ..current
+        };
+        Ok(repo.update_film(&film).await.map_err(graphql_error)?.into())
+    }
+
+    /// Deletes the film and returns its id.
+    async fn delete_film(&self, ctx: &Context<'_>, id: Uuid) -> Result<Uuid> {
+        repository::<R>(ctx)?
+            .delete_film(&id)
+            .await
+            .map_err(graphql_error)
+    }
+}
+
+pub struct SubscriptionRoot;
+
+#[Subscription]
+impl SubscriptionRoot {
+    /// Every change made to the catalogue from now on, optionally only the
+    /// given kinds of change.
+    async fn film_changed(
+        &self,
+        ctx: &Context<'_>,
+        changes: Option<Vec<FilmChange>>,
+    ) -> Result<impl Stream<Item = FilmChanged>> {
+        let receiver = ctx.data::<FilmEvents>()?.subscribe();
+        let events = stream::unfold(receiver, |mut receiver| async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(event) => return Some((event, receiver)),
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        });
+        Ok(stream::StreamExt::filter_map(events, move |event| {
+            let changed = FilmChanged::from(event);
+            let wanted = changes
+                .as_ref()
+                .is_none_or(|changes| changes.contains(&changed.change));
+            async move { wanted.then_some(changed) }
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::film_repository::{
+        MemoryFilmRepository, MockFilmRepository, PublishingFilmRepository,
+    };
+    use async_graphql::{futures_util::StreamExt, Request};
+
+    fn create_test_create_film(id: &'static str) -> CreateFilm {
+        CreateFilm {
+            title: format!("title-{}", id),
+            director: format!("director-{}", id),
+            poster: format!("poster-{}", id),
+            year: 2001,
+        }
+    }
+
+    #[actix_rt::test]
+    async fn films_query_filters_by_title() {
+        let repo = MemoryFilmRepository::new();
+        repo.create_film(&create_test_create_film("one"))
+            .await
+            .unwrap();
+        repo.create_film(&create_test_create_film("two"))
+            .await
+            .unwrap();
+
+        let response = schema::<MemoryFilmRepository>()
+            .execute(
+                Request::new(r#"{ films(title: "ONE") { title year } }"#)
+                    .data(web::Data::new(repo)),
+            )
+            .await;
+
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        assert_eq!(
+            response.data.into_json().unwrap(),
+            serde_json::json!({ "films": [{ "title": "title-one", "year": 2001 }] })
+        );
+    }
+
+    #[actix_rt::test]
+    async fn film_query_returns_null_when_missing() {
+        let response = schema::<MemoryFilmRepository>()
+            .execute(
+                Request::new(format!(r#"{{ film(id: "{}") {{ id }} }}"#, Uuid::new_v4()))
+                    .data(web::Data::new(MemoryFilmRepository::new())),
+            )
+            .await;
+
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        assert_eq!(
+            response.data.into_json().unwrap(),
+            serde_json::json!({ "film": null })
+        );
+    }
+
+    #[actix_rt::test]
+    async fn film_query_reports_failures() {
+        let mut repo = MockFilmRepository::default();
+        repo.expect_get_film()
+            .returning(|_| Err(FilmError::internal("connection refused")));
+
+        let response = schema::<MockFilmRepository>()
+            .execute(
+                Request::new(format!(r#"{{ film(id: "{}") {{ id }} }}"#, Uuid::new_v4()))
+                    .data(web::Data::new(repo)),
+            )
+            .await;
+
+        assert_eq!(response.errors.len(), 1, "{:?}", response.errors);
+    }
+
+    #[actix_rt::test]
+    async fn internal_errors_are_not_shown() {
+        let mut repo = MockFilmRepository::default();
+        repo.expect_get_films()
+            .returning(|| Err(FilmError::internal("connection refused")));
+        repo.expect_get_film()
+            .returning(|_| Err(FilmError::internal("connection refused")));
+        repo.expect_create_film()
+            .returning(|_| Err(FilmError::internal("connection refused")));
+        repo.expect_delete_film()
+            .returning(|_| Err(FilmError::internal("connection refused")));
+        let repo = web::Data::new(repo);
+        let id = Uuid::new_v4();
+
+        for query in [
+            "{ films { id } }".to_string(),
+            format!(r#"{{ film(id: "{}") {{ id }} }}"#, id),
+            r#"mutation { createFilm(input: { title: "t", director: "d", year: 2001, poster: "p" }) { id } }"#.to_string(),
+            format!(r#"mutation {{ updateFilm(input: {{ id: "{}", title: "t" }}) {{ id }} }}"#, id),
+            format!(r#"mutation {{ deleteFilm(id: "{}") }}"#, id),
+        ] {
+            let response = schema::<MockFilmRepository>()
+                .execute(Request::new(query.clone()).data(repo.clone()))
+                .await;
+
+            assert_eq!(response.errors.len(), 1, "{}: {:?}", query, response.errors);
+            assert_eq!(response.errors[0].message, "Internal server error");
+            let json = serde_json::to_string(&response).unwrap();
+            assert!(!json.contains("connection refused"), "{}", json);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn oversized_queries_are_refused() {
+        let deep = format!(
+            "{{ __schema {{ types {{ {}name{} }} }} }}",
+            "ofType { ".repeat(MAX_DEPTH),
+            " }".repeat(MAX_DEPTH)
+        );
+        let big = format!(
+            "{{ {} }}",
+            (0..MAX_COMPLEXITY)
+                .map(|i| format!("f{}: films {{ id title }}", i))
+                .collect::<Vec<_>>()
+                .join(" ")
+        );
+
+        for (query, error) in [
+            (deep, "Query is nested too deep."),
+            (big, "Query is too complex."),
+        ] {
+            let response = schema::<MemoryFilmRepository>()
+                .execute(Request::new(query).data(web::Data::new(MemoryFilmRepository::new())))
+                .await;
+
+            assert_eq!(response.errors.len(), 1, "{:?}", response.errors);
+            assert_eq!(response.errors[0].message, error);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn update_film_only_changes_given_fields() {
+        let repo = web::Data::new(MemoryFilmRepository::new());
+        let film = repo
+            .create_film(&create_test_create_film("1"))
+            .await
+            .unwrap();
+
+        let query = format!(
+            r#"mutation {{ updateFilm(input: {{ id: "{}", year: 1999 }}) {{ title year updatedAt }} }}"#,
+            film.id
+        );
+        let response = schema::<MemoryFilmRepository>()
+            .execute(Request::new(query).data(repo.clone()))
+            .await;
+
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        let updated = repo.get_film(&film.id).await.unwrap();
+        assert_eq!(updated.title, film.title);
+        assert_eq!(updated.year, 1999);
+        assert!(updated.updated_at.is_some());
+    }
+
+    #[actix_rt::test]
+    async fn subscription_receives_filtered_changes() {
+        let events = FilmEvents::new();
+        let repo = web::Data::new(PublishingFilmRepository::new(
+            MemoryFilmRepository::new(),
+            events.clone(),
+        ));
+        let schema = schema::<PublishingFilmRepository<MemoryFilmRepository>>();
+
+        let mut changes = schema.execute_stream(
+            Request::new(
+                "subscription { filmChanged(changes: [DELETED]) { change filmId film { id } } }",
+            )
+            .data(events.clone()),
+        );
+        // the subscription is only registered once the stream is polled
+        let (response, film) = tokio::join!(changes.next(), async {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            let film = repo
+                .create_film(&create_test_create_film("1"))
+                .await
+                .unwrap();
+            repo.delete_film(&film.id).await.unwrap();
+            film
+        });
+
+        let response = response.unwrap();
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        assert_eq!(
+            response.data.into_json().unwrap(),
+            serde_json::json!({
+                "filmChanged": { "change": "DELETED", "filmId": film.id.to_string(), "film": null }
+            })
+        );
+    }
+
+    #[actix_rt::test]
+    async fn subscription_fails_without_events() {
+        let schema = schema::<MemoryFilmRepository>();
+        let mut changes =
+            schema.execute_stream(Request::new("subscription { filmChanged { filmId } }"));
+
+        let response = changes.next().await.unwrap();
+
+        assert!(!response.errors.is_empty());
+    }
+}
@@ -0,0 +1,301 @@
+pub mod proto;
+
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use futures_util::stream::{self, BoxStream, StreamExt};
+use shared::models::{CreateFilm, Film};
+use tokio::{net::TcpListener, sync::broadcast};
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::{Request, Response, Status};
+use uuid::Uuid;
+
+use crate::{
+    events::{FilmEvent, FilmEvents},
+    film_repository::{FilmError, FilmRepository},
+    supervisor::Shutdown,
+};
+use proto::{
+    film_event::Kind,
+    film_service_server::{FilmService, FilmServiceServer},
+};
+
+/// gRPC `films.v1.FilmService`, backed by a [`FilmRepository`].
+pub struct FilmGrpcService<R> {
+    repo: Arc<R>,
+    events: Option<FilmEvents>,
+}
+
+impl<R: FilmRepository> FilmGrpcService<R> {
+    pub fn new(repo: Arc<R>) -> Self {
+        Self { repo, events: None }
+    }
+
+    /// Enables `WatchFilms`, which streams the changes published to `events`.
+    pub fn with_events(mut self, events: FilmEvents) -> Self {
+        self.events = Some(events);
+        self
+    }
+}
+
+/// Serves `service` on `listener` until `shutdown` fires or the server fails.
+pub async fn serve<R: FilmRepository>(
+    service: FilmGrpcService<R>,
+    listener: TcpListener,
+    mut shutdown: Shutdown,
+) -> Result<(), tonic::transport::Error> {
+    tonic::transport::Server::builder()
+        .add_service(FilmServiceServer::new(service))
+        .serve_with_incoming_shutdown(TcpListenerStream::new(listener), async move {
+            shutdown.wait().await
+        })
+        .await
+}
+
+#[tonic::async_trait]
+impl<R: FilmRepository> FilmService for FilmGrpcService<R> {
+    async fn list_films(
+        &self,
+        _request: Request<proto::ListFilmsRequest>,
+    ) -> Result<Response<proto::ListFilmsResponse>, Status> {
+        let films = self.repo.get_films().await.map_err(status)?;
+        Ok(Response::new(proto::ListFilmsResponse {
+            films: films.into_iter().map(proto::Film::from).collect(),
+        }))
+    }
+
+    async fn get_film(
+        &self,
+        request: Request<proto::GetFilmRequest>,
+    ) -> Result<Response<proto::Film>, Status> {
+        let id = parse_id(&request.get_ref().id).map_err(Status::invalid_argument)?;
+        let film = self.repo.get_film(&id).await.map_err(status)?;
+        Ok(Response::new(film.into()))
+    }
+
+    async fn create_film(
+        &self,
+        request: Request<proto::CreateFilmRequest>,
+    ) -> Result<Response<proto::Film>, Status> {
+        let request = request.into_inner();
+        let create_film = CreateFilm {
+            title: request.title,
+            director: request.director,
+            year: parse_year(request.year).map_err(Status::invalid_argument)?,
+            poster: request.poster,
+        };
+        let film = self.repo.create_film(&create_film).await.map_err(status)?;
+        Ok(Response::new(film.into()))
+    }
+
+    async fn update_film(
+        &self,
+        request: Request<proto::UpdateFilmRequest>,
+    ) -> Result<Response<proto::Film>, Status> {
+        let film = request
+            .into_inner()
+            .film
+            .ok_or_else(|| Status::invalid_argument("The film is required"))?;
+        let film = Film::try_from(film)?;
+        let film = self.repo.update_film(&film).await.map_err(status)?;
+        Ok(Response::new(film.into()))
+    }
+
+    async fn delete_film(
+        &self,
+        request: Request<proto::DeleteFilmRequest>,
+    ) -> Result<Response<proto::DeleteFilmResponse>, Status> {
+        let id = parse_id(&request.get_ref().id).map_err(Status::invalid_argument)?;
+        let id = self.repo.delete_film(&id).await.map_err(status)?;
+        Ok(Response::new(proto::DeleteFilmResponse {
+            id: id.to_string(),
+        }))
+    }
+
+    type WatchFilmsStream = BoxStream<'static, Result<proto::FilmEvent, Status>>;
+
+    async fn watch_films(
+        &self,
+        _request: Request<proto::WatchFilmsRequest>,
+    ) -> Result<Response<Self::WatchFilmsStream>, Status> {
+        let receiver = self
+            .events
+            .as_ref()
+            .ok_or_else(|| Status::unavailable("Film events are not available"))?
+            .subscribe();
+        let events = stream::unfold(receiver, |mut receiver| async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(event) => return Some((Ok(event.into()), receiver)),
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        });
+        Ok(Response::new(events.boxed()))
+    }
+}
+
+/// `NOT_FOUND` for the missing films, `INTERNAL` for everything else, which
+/// is logged rather than shown to clients.
+fn status(error: FilmError) -> Status {
+    match error {
+        FilmError::NotFound(_) => Status::not_found(error.to_string()),
+        error => {
+            tracing::error!("{}", error);
+            Status::internal("Internal server error")
+        }
+    }
+}
+
+fn parse_id(id: &str) -> Result<Uuid, String> {
+    id.parse().map_err(|_| format!("Invalid film id: {}", id))
+}
+
+fn parse_year(year: u32) -> Result<u16, String> {
+    u16::try_from(year).map_err(|_| format!("Invalid year: {}", year))
+}
+
+fn to_timestamp(date: DateTime<Utc>) -> prost_types::Timestamp {
+    prost_types::Timestamp {
+        seconds: date.timestamp(),
+        nanos: date.timestamp_subsec_nanos() as i32,
+    }
+}
+
+fn from_timestamp(timestamp: prost_types::Timestamp) -> Option<DateTime<Utc>> {
+    DateTime::from_timestamp(timestamp.seconds, timestamp.nanos.try_into().ok()?)
+}
+
+impl From<Film> for proto::Film {
+    fn from(film: Film) -> Self {
+        Self {
+            id: film.id.to_string(),
+            title: film.title,
+            director: film.director,
+            year: film.year.into(),
+            poster: film.poster,
+            created_at: film.created_at.map(to_timestamp),
+            updated_at: film.updated_at.map(to_timestamp),
+        }
+    }
+}
+
+impl TryFrom<proto::Film> for Film {
+    type Error = Status;
+
+    fn try_from(film: proto::Film) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: parse_id(&film.id).map_err(Status::invalid_argument)?,
+            title: film.title,
+            director: film.director,
+            year: parse_year(film.year).map_err(Status::invalid_argument)?,
+            poster: film.poster,
+            created_at: film.created_at.and_then(from_timestamp),
+            updated_at: film.updated_at.and_then(from_timestamp),
+        })
+    }
+}
+
+impl From<FilmEvent> for proto::FilmEvent {
+    fn from(event: FilmEvent) -> Self {
+        let film_id = event.film_id().to_string();
+        let (kind, film) = match event {
+            FilmEvent::Created(film) => (Kind::Created, Some(film.into())),
+            FilmEvent::Updated(film) => (Kind::Updated, Some(film.into())),
+            FilmEvent::Deleted(_) => (Kind::Deleted, None),
+        };
+        Self {
+            kind: kind.into(),
+            film_id,
+            film,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::film_repository::MockFilmRepository;
+
+    #[test]
+    fn film_roundtrips_through_proto() {
+        let film = Film {
+            id: Uuid::new_v4(),
+            title: "Alien".to_string(),
+            director: "Ridley Scott".to_string(),
+            year: 1979,
+            poster: "poster".to_string(),
+            created_at: Some(Utc::now()),
+            updated_at: None,
+        };
+
+        let converted = Film::try_from(proto::Film::from(film.clone())).unwrap();
+
+        assert_eq!(converted, film);
+    }
+
+    #[test]
+    fn invalid_film_is_rejected() {
+        let film = proto::Film {
+            id: "not-an-id".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            Film::try_from(film).unwrap_err().code(),
+            tonic::Code::InvalidArgument
+        );
+
+        let film = proto::Film {
+            id: Uuid::new_v4().to_string(),
+            year: 70_000,
+            ..Default::default()
+        };
+        assert_eq!(
+            Film::try_from(film).unwrap_err().code(),
+            tonic::Code::InvalidArgument
+        );
+    }
+
+    #[actix_rt::test]
+    async fn only_missing_films_are_not_found() {
+        let mut repo = MockFilmRepository::default();
+        repo.expect_get_film()
+            .returning(|film_id| Err(FilmError::NotFound(*film_id)));
+        repo.expect_update_film()
+            .returning(|_| Err(FilmError::internal("connection refused")));
+        let service = FilmGrpcService::new(Arc::new(repo));
+        let film_id = Uuid::new_v4().to_string();
+
+        let missing = service
+            .get_film(Request::new(proto::GetFilmRequest {
+                id: film_id.clone(),
+            }))
+            .await
+            .unwrap_err();
+        let failed = service
+            .update_film(Request::new(proto::UpdateFilmRequest {
+                film: Some(proto::Film {
+                    id: film_id,
+                    ..Default::default()
+                }),
+            }))
+            .await
+            .unwrap_err();
+
+        assert_eq!(missing.code(), tonic::Code::NotFound);
+        assert_eq!(failed.code(), tonic::Code::Internal);
+        assert_eq!(failed.message(), "Internal server error");
+    }
+
+    #[test]
+    fn deleted_event_has_no_film() {
+        let id = Uuid::new_v4();
+
+        let event = proto::FilmEvent::from(FilmEvent::Deleted(id));
+
+        assert_eq!(event.kind(), Kind::Deleted);
+        assert_eq!(event.film_id, id.to_string());
+        assert!(event.film.is_none());
+    }
+}
@@ -0,0 +1,4 @@
+//! Messages, client and server of `api/proto/films.proto`, generated by the
+//! build script.
+
+tonic::include_proto!("films.v1");
@@ -1,25 +1,175 @@
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    time::{Duration, Instant},
+};
+
 use actix_web::{
     web::{self, ServiceConfig},
     HttpResponse,
 };
+use serde::{Deserialize, Serialize};
+use utoipa::{OpenApi, ToSchema};
+
+use crate::film_repository::{FilmError, FilmRepository, FilmResult};
+
+pub const API_VERSION: &str = concat!("v", env!("CARGO_PKG_VERSION"));
+
+/// How long a dependency has to answer before it's reported down.
+pub const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
 
-pub const API_VERSION: &str = "v0.0.3";
+#[derive(OpenApi)]
+#[openapi(
+    paths(health_check, live, ready),
+    components(schemas(Health, DependencyHealth, HealthStatus))
+)]
+pub(crate) struct HealthApi;
 
 pub fn service(cfg: &mut ServiceConfig) {
-    cfg.route("/health", web::get().to(health_check));
+    cfg.route("/health", web::get().to(health_check))
+        .route("/health/live", web::get().to(live));
+}
+
+/// `/health/ready`, checking the `R` repository.
+pub fn ready_service<R: FilmRepository>(cfg: &mut ServiceConfig) {
+    cfg.route("/health/ready", web::get().to(ready::<R>));
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+    Up,
+    Down,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct DependencyHealth {
+    pub status: HealthStatus,
+    pub latency_ms: f64,
+    /// Why the dependency is down, in general terms.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Health {
+    /// Down when any of the dependencies is.
+    pub status: HealthStatus,
+    pub version: String,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub dependencies: BTreeMap<String, DependencyHealth>,
+}
+
+impl Health {
+    pub fn new(dependencies: BTreeMap<String, DependencyHealth>) -> Self {
+        let status = if dependencies
+            .values()
+            .all(|dependency| dependency.status == HealthStatus::Up)
+        {
+            HealthStatus::Up
+        } else {
+            HealthStatus::Down
+        };
+        Self {
+            status,
+            version: API_VERSION.to_string(),
+            dependencies,
+        }
+    }
 }
 
+/// Times `ping`, which is down when it fails or doesn't answer within
+/// `timeout`.
+pub async fn check(
+    ping: impl Future<Output = FilmResult<()>>,
+    timeout: Duration,
+) -> DependencyHealth {
+    let start = Instant::now();
+    let result = tokio::time::timeout(timeout, ping)
+        .await
+        .unwrap_or_else(|_| {
+            Err(FilmError::internal(format!(
+                "No answer within {:?}",
+                timeout
+            )))
+        });
+    let latency_ms = start.elapsed().as_secs_f64() * 1000.0;
+    match result {
+        Ok(()) => DependencyHealth {
+            status: HealthStatus::Up,
+            latency_ms,
+            error: None,
+        },
+        Err(error) => DependencyHealth {
+            status: HealthStatus::Down,
+            latency_ms,
+            error: Some(error.to_string()),
+        },
+    }
+}
+
+/// Checks that the API is up.
+#[utoipa::path(
+    get,
+    path = "/health",
+    tag = "health",
+    responses(
+        (status = 200, description = "The API is up", headers(
+            ("health-check" = String, description = "Version of the API")
+        )),
+    )
+)]
 async fn health_check() -> HttpResponse {
     HttpResponse::Ok()
         .append_header(("health-check", API_VERSION))
         .finish()
 }
 
+/// Checks that the API is running, without checking its dependencies.
+#[utoipa::path(
+    get,
+    path = "/health/live",
+    operation_id = "health_live",
+    tag = "health",
+    responses(
+        (status = 200, description = "The API is running", body = Health),
+    )
+)]
+async fn live() -> HttpResponse {
+    HttpResponse::Ok().json(Health::new(BTreeMap::new()))
+}
+
+/// Checks that the API can serve requests, i.e. its dependencies are up.
+#[utoipa::path(
+    get,
+    path = "/health/ready",
+    operation_id = "health_ready",
+    tag = "health",
+    responses(
+        (status = 200, description = "Every dependency is up", body = Health),
+        (status = 503, description = "A dependency is down", body = Health),
+    )
+)]
+async fn ready<R: FilmRepository>(repo: web::Data<R>) -> HttpResponse {
+    let mut films = check(repo.ping(), CHECK_TIMEOUT).await;
+    // the probe is public, the details are only for the logs
+    if let Some(error) = films.error.take() {
+        tracing::error!("The films repository isn't ready: {}", error);
+        films.error = Some("database unavailable".to_string());
+    }
+    let health = Health::new(BTreeMap::from([("films".to_string(), films)]));
+    match health.status {
+        HealthStatus::Up => HttpResponse::Ok().json(health),
+        HealthStatus::Down => HttpResponse::ServiceUnavailable().json(health),
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use actix_web::http::StatusCode;
+    use actix_web::{body::to_bytes, http::StatusCode};
 
     use super::*;
+    use crate::film_repository::MockFilmRepository;
 
     #[actix_rt::test]
     async fn health_check_works() {
@@ -32,4 +182,46 @@ mod tests {
             .and_then(|h| h.to_str().ok());
         assert_eq!(data, Some(API_VERSION));
     }
+
+    async fn ready_with(ping: FilmResult<()>) -> (StatusCode, Health) {
+        let mut repo = MockFilmRepository::default();
+        repo.expect_ping().returning(move || ping.clone());
+
+        let res = ready(web::Data::new(repo)).await;
+        let status = res.status();
+        let body = to_bytes(res.into_body()).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[actix_rt::test]
+    async fn ready_when_the_repository_is_up() {
+        let (status, health) = ready_with(Ok(())).await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(health.status, HealthStatus::Up);
+        assert_eq!(health.version, API_VERSION);
+        assert_eq!(health.dependencies["films"].status, HealthStatus::Up);
+        assert_eq!(health.dependencies["films"].error, None);
+    }
+
+    #[actix_rt::test]
+    async fn not_ready_when_the_repository_is_down() {
+        let (status, health) = ready_with(Err(FilmError::internal("connection refused"))).await;
+
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(health.status, HealthStatus::Down);
+        assert_eq!(
+            health.dependencies["films"].error.as_deref(),
+            Some("database unavailable")
+        );
+    }
+
+    #[actix_rt::test]
+    async fn checks_time_out() {
+        let health = check(std::future::pending(), Duration::from_millis(10)).await;
+
+        assert_eq!(health.status, HealthStatus::Down);
+        assert!(health.latency_ms >= 10.0);
+        assert_eq!(health.error.as_deref(), Some("No answer within 10ms"));
+    }
 }
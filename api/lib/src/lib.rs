@@ -1,3 +1,24 @@
+pub mod access_log;
+pub mod cors;
+pub mod database;
+pub mod deprecation;
+pub mod events;
 pub mod film_repository;
+pub mod graphql;
+pub mod grpc;
 pub mod health;
+pub mod metrics;
+pub mod openapi;
+pub mod presence;
+pub mod rate_limit;
+pub mod security_headers;
+pub mod seed;
+pub mod settings;
+pub mod static_files;
+pub mod supervisor;
+pub mod telemetry;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 pub mod v1;
+pub mod v2;
+pub mod webhooks;
@@ -0,0 +1,395 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use actix_web::{
+    body::MessageBody,
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    http::header,
+    middleware::{from_fn, Next},
+    web::{self, ServiceConfig},
+    Error, HttpRequest, HttpResponse,
+};
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGaugeVec, Opts, Registry, TextEncoder,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::rate_limit::token_hash;
+
+/// Route label of the requests that didn't match any route, so random paths
+/// can't blow up the number of series.
+const UNMATCHED: &str = "unmatched";
+
+type PoolStats = Box<dyn Fn() -> (u32, usize, u32) + Send + Sync>;
+
+/// The `[metrics]` section of the [`Settings`](crate::settings::Settings).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct MetricsSettings {
+    /// The `Bearer` token scrapers authenticate with. `/metrics` isn't served
+    /// without one.
+    pub token: Option<String>,
+}
+
+/// The API's Prometheus metrics: HTTP requests, repository operations, the
+/// film cache and database pools. Cloning is cheap, clones share the same metrics.
+#[derive(Clone)]
+pub struct Metrics {
+    registry: Registry,
+    http_requests: IntCounterVec,
+    http_request_duration: HistogramVec,
+    repository_operation_duration: HistogramVec,
+    cache_lookups: IntCounterVec,
+    pool_connections: IntGaugeVec,
+    pool_max_connections: IntGaugeVec,
+    pools: Arc<Mutex<Vec<(String, PoolStats)>>>,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let http_requests = IntCounterVec::new(
+            Opts::new("http_requests_total", "HTTP requests served"),
+            &["method", "route", "status"],
+        )
+        .unwrap();
+        let http_request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "http_request_duration_seconds",
+                "How long HTTP requests took to serve",
+            ),
+            &["method", "route", "status"],
+        )
+        .unwrap();
+        let repository_operation_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "repository_operation_duration_seconds",
+                "How long the film repository took to answer",
+            ),
+            &["operation", "outcome"],
+        )
+        .unwrap();
+        let cache_lookups = IntCounterVec::new(
+            Opts::new(
+                "film_cache_lookups_total",
+                "Film cache lookups, by whether the cache had the films",
+            ),
+            &["result"],
+        )
+        .unwrap();
+        let pool_connections = IntGaugeVec::new(
+            Opts::new("db_pool_connections", "Open database connections"),
+            &["pool", "state"],
+        )
+        .unwrap();
+        let pool_max_connections = IntGaugeVec::new(
+            Opts::new(
+                "db_pool_max_connections",
+                "How many connections a database pool can open",
+            ),
+            &["pool"],
+        )
+        .unwrap();
+
+        let registry = Registry::new();
+        for collector in [
+            Box::new(http_requests.clone()) as Box<dyn prometheus::core::Collector>,
+            Box::new(http_request_duration.clone()),
+            Box::new(repository_operation_duration.clone()),
+            Box::new(cache_lookups.clone()),
+            Box::new(pool_connections.clone()),
+            Box::new(pool_max_connections.clone()),
+        ] {
+            // the names above are all different
+            registry.register(collector).unwrap();
+        }
+
+        Self {
+            registry,
+            http_requests,
+            http_request_duration,
+            repository_operation_duration,
+            cache_lookups,
+            pool_connections,
+            pool_max_connections,
+            pools: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Reports the connections of `pool`, labelled `name`, on every scrape.
+    pub fn register_pool<DB: sqlx::Database>(&self, name: &str, pool: sqlx::Pool<DB>) {
+        let stats: PoolStats = Box::new(move || {
+            (
+                pool.size(),
+                pool.num_idle(),
+                pool.options().get_max_connections(),
+            )
+        });
+        if let Ok(mut pools) = self.pools.lock() {
+            pools.push((name.to_string(), stats));
+        }
+    }
+
+    pub fn observe_request(&self, method: &str, route: &str, status: u16, elapsed: Duration) {
+        let status = status.to_string();
+        let labels = [method, route, status.as_str()];
+        self.http_requests.with_label_values(&labels).inc();
+        self.http_request_duration
+            .with_label_values(&labels)
+            .observe(elapsed.as_secs_f64());
+    }
+
+    pub fn observe_operation(&self, operation: &str, ok: bool, elapsed: Duration) {
+        let outcome = if ok { "ok" } else { "error" };
+        self.repository_operation_duration
+            .with_label_values(&[operation, outcome])
+            .observe(elapsed.as_secs_f64());
+    }
+
+    pub fn observe_cache_lookup(&self, hit: bool) {
+        let result = if hit { "hit" } else { "miss" };
+        self.cache_lookups.with_label_values(&[result]).inc();
+    }
+
+    /// The middleware counting and timing every request of the services it
+    /// wraps, by method, route pattern and status.
+    pub fn middleware<S, B>(
+        &self,
+    ) -> impl Transform<S, ServiceRequest, Response = ServiceResponse<B>, Error = Error, InitError = ()>
+    where
+        S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+        B: MessageBody + 'static,
+    {
+        let metrics = self.clone();
+        from_fn(move |req, next| metrics.clone().observe(req, next))
+    }
+
+    async fn observe<B: MessageBody>(
+        self,
+        req: ServiceRequest,
+        next: Next<B>,
+    ) -> Result<ServiceResponse<B>, Error> {
+        let method = req.method().to_string();
+        let route = req.match_pattern().unwrap_or_else(|| UNMATCHED.to_string());
+        let start = Instant::now();
+
+        let res = next.call(req).await;
+        let status = match &res {
+            Ok(res) => res.status(),
+            Err(e) => e.as_response_error().status_code(),
+        };
+        self.observe_request(&method, &route, status.as_u16(), start.elapsed());
+        res
+    }
+
+    /// Everything in the Prometheus text format.
+    pub fn render(&self) -> Result<String, String> {
+        if let Ok(pools) = self.pools.lock() {
+            for (name, stats) in pools.iter() {
+                let (size, idle, max) = stats();
+                let idle = idle as i64;
+                self.pool_connections
+                    .with_label_values(&[name, "idle"])
+                    .set(idle);
+                self.pool_connections
+                    .with_label_values(&[name, "in_use"])
+                    .set(size as i64 - idle);
+                self.pool_max_connections
+                    .with_label_values(&[name])
+                    .set(max as i64);
+            }
+        }
+
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .map_err(|e| e.to_string())?;
+        String::from_utf8(buffer).map_err(|e| e.to_string())
+    }
+}
+
+/// The hash of the token `/metrics` is served to.
+struct ScrapeToken(String);
+
+/// Serves the metrics at `/metrics` to the holders of the settings' token,
+/// with the [`Metrics`] as app data. Nothing is served without a token.
+pub fn service(settings: &MetricsSettings) -> impl FnOnce(&mut ServiceConfig) {
+    let token = settings
+        .token
+        .as_deref()
+        .map(str::trim)
+        .filter(|token| !token.is_empty())
+        .map(|token| hex::encode(Sha256::digest(token)));
+    move |cfg| {
+        let Some(token) = token else {
+            tracing::info!("No metrics token is set, /metrics isn't served");
+            return;
+        };
+        cfg.service(
+            web::resource("/metrics")
+                .app_data(web::Data::new(ScrapeToken(token)))
+                .route(web::get().to(metrics)),
+        );
+    }
+}
+
+async fn metrics(
+    req: HttpRequest,
+    metrics: web::Data<Metrics>,
+    token: web::Data<ScrapeToken>,
+) -> HttpResponse {
+    if token_hash(req.headers()).as_ref() != Some(&token.0) {
+        return HttpResponse::Unauthorized()
+            .insert_header((header::WWW_AUTHENTICATE, "Bearer"))
+            .finish();
+    }
+
+    match metrics.render() {
+        Ok(body) => HttpResponse::Ok()
+            .content_type(prometheus::TEXT_FORMAT)
+            .body(body),
+        Err(e) => HttpResponse::InternalServerError().body(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{http::StatusCode, App};
+
+    fn settings(token: Option<&str>) -> MetricsSettings {
+        MetricsSettings {
+            token: token.map(str::to_string),
+        }
+    }
+
+    async fn scrape_with(
+        metrics: &Metrics,
+        settings: &MetricsSettings,
+        token: Option<&str>,
+    ) -> ServiceResponse {
+        let app = actix_web::test::init_service(
+            App::new()
+                .app_data(web::Data::new(metrics.clone()))
+                .configure(service(settings)),
+        )
+        .await;
+        let mut req = actix_web::test::TestRequest::get().uri("/metrics");
+        if let Some(token) = token {
+            req = req.insert_header((header::AUTHORIZATION, format!("Bearer {}", token)));
+        }
+        actix_web::test::call_service(&app, req.to_request()).await
+    }
+
+    async fn scrape(metrics: &Metrics) -> String {
+        let res = scrape_with(metrics, &settings(Some("secret")), Some("secret")).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(
+            res.headers().get("content-type").unwrap(),
+            prometheus::TEXT_FORMAT
+        );
+        String::from_utf8(actix_web::test::read_body(res).await.to_vec()).unwrap()
+    }
+
+    #[actix_rt::test]
+    async fn requests_are_counted_by_route() {
+        let metrics = Metrics::new();
+        let app = actix_web::test::init_service(
+            App::new().service(
+                web::scope("/api")
+                    .wrap(metrics.middleware())
+                    .route("/films/{film_id}", web::get().to(HttpResponse::Ok)),
+            ),
+        )
+        .await;
+
+        for uri in ["/api/films/1", "/api/films/2", "/api/nothing-here"] {
+            let req = actix_web::test::TestRequest::get().uri(uri).to_request();
+            actix_web::test::call_service(&app, req).await;
+        }
+
+        let body = scrape(&metrics).await;
+        assert!(body.contains(
+            r#"http_requests_total{method="GET",route="/api/films/{film_id}",status="200"} 2"#
+        ));
+        assert!(
+            body.contains(r#"http_requests_total{method="GET",route="unmatched",status="404"} 1"#)
+        );
+        assert!(body.contains(
+            r#"http_request_duration_seconds_count{method="GET",route="/api/films/{film_id}",status="200"} 2"#
+        ));
+    }
+
+    #[actix_rt::test]
+    async fn operations_are_timed_by_outcome() {
+        let metrics = Metrics::new();
+
+        metrics.observe_operation("get_film", true, Duration::from_millis(5));
+        metrics.observe_operation("get_film", false, Duration::from_millis(5));
+        metrics.observe_operation("get_film", false, Duration::from_millis(5));
+
+        let body = scrape(&metrics).await;
+        assert!(body.contains(
+            r#"repository_operation_duration_seconds_count{operation="get_film",outcome="ok"} 1"#
+        ));
+        assert!(body.contains(
+            r#"repository_operation_duration_seconds_count{operation="get_film",outcome="error"} 2"#
+        ));
+    }
+
+    #[actix_rt::test]
+    async fn cache_lookups_are_counted() {
+        let metrics = Metrics::new();
+
+        metrics.observe_cache_lookup(true);
+        metrics.observe_cache_lookup(true);
+        metrics.observe_cache_lookup(false);
+
+        let body = scrape(&metrics).await;
+        assert!(body.contains(r#"film_cache_lookups_total{result="hit"} 2"#));
+        assert!(body.contains(r#"film_cache_lookups_total{result="miss"} 1"#));
+    }
+
+    #[actix_rt::test]
+    async fn scrapers_need_the_token() {
+        let metrics = Metrics::new();
+
+        for (settings, token, status) in [
+            (settings(Some("secret")), None, StatusCode::UNAUTHORIZED),
+            (
+                settings(Some("secret")),
+                Some("guess"),
+                StatusCode::UNAUTHORIZED,
+            ),
+            (settings(None), Some("secret"), StatusCode::NOT_FOUND),
+            (settings(Some(" ")), Some(""), StatusCode::NOT_FOUND),
+        ] {
+            let res = scrape_with(&metrics, &settings, token).await;
+            assert_eq!(res.status(), status);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn pools_are_reported_on_scrape() {
+        let metrics = Metrics::new();
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(3)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        metrics.register_pool("primary", pool.clone());
+        let _connection = pool.acquire().await.unwrap();
+
+        let body = scrape(&metrics).await;
+        assert!(body.contains(r#"db_pool_connections{pool="primary",state="in_use"} 1"#));
+        assert!(body.contains(r#"db_pool_max_connections{pool="primary"} 3"#));
+    }
+}
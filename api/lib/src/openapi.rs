@@ -0,0 +1,163 @@
+use actix_web::{
+    http::header,
+    web::{self, ServiceConfig},
+    HttpResponse,
+};
+use shared::models::{CreateFilm, Film};
+use utoipa::OpenApi;
+
+use crate::{health, v1, v2};
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Films API",
+        description = "Catalogue of films",
+        license(name = "MIT")
+    ),
+    servers((url = "/api")),
+    components(schemas(Film, CreateFilm))
+)]
+struct ApiDoc;
+
+/// OpenAPI document of the REST API, generated from the handlers.
+pub fn openapi() -> utoipa::openapi::OpenApi {
+    let mut doc = ApiDoc::openapi();
+    doc.info.version = health::API_VERSION.to_string();
+    doc.merge(health::HealthApi::openapi());
+    doc.merge(v1::V1Api::openapi());
+    doc.merge(v2::V2Api::openapi());
+    doc
+}
+
+pub fn service(cfg: &mut ServiceConfig) {
+    cfg.app_data(web::Data::new(openapi()))
+        // GET
+        .route("/openapi.json", web::get().to(spec))
+        .route("/docs", web::get().to(swagger_ui))
+        .route("/docs/{asset}", web::get().to(swagger_ui_asset));
+}
+
+async fn spec(doc: web::Data<utoipa::openapi::OpenApi>) -> HttpResponse {
+    HttpResponse::Ok().json(doc.get_ref())
+}
+
+/// Swagger UI for the spec, served with the API rather than from a CDN.
+async fn swagger_ui() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .insert_header((header::CONTENT_SECURITY_POLICY, SWAGGER_UI_CSP))
+        .body(SWAGGER_UI)
+}
+
+async fn swagger_ui_asset(asset: web::Path<String>) -> HttpResponse {
+    match SWAGGER_UI_ASSETS
+        .iter()
+        .find(|(name, _, _)| *name == asset.as_str())
+    {
+        Some((_, content_type, body)) => HttpResponse::Ok().content_type(*content_type).body(*body),
+        None => HttpResponse::NotFound().body("Not found"),
+    }
+}
+
+/// Only lets the page load its own scripts and styles. Swagger UI draws its
+/// icons with `data:` images.
+const SWAGGER_UI_CSP: &str = "default-src 'self'; img-src 'self' data:; frame-ancestors 'none'";
+
+/// The vendored Swagger UI, see `swagger-ui/README.md`.
+const SWAGGER_UI_ASSETS: &[(&str, &str, &str)] = &[
+    (
+        "swagger-ui.css",
+        "text/css; charset=utf-8",
+        include_str!("../swagger-ui/swagger-ui.css"),
+    ),
+    (
+        "swagger-ui-bundle.js",
+        "text/javascript; charset=utf-8",
+        include_str!("../swagger-ui/swagger-ui-bundle.js"),
+    ),
+    (
+        "swagger-initializer.js",
+        "text/javascript; charset=utf-8",
+        include_str!("../swagger-ui/swagger-initializer.js"),
+    ),
+];
+
+const SWAGGER_UI: &str = r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+  <meta charset="utf-8" />
+  <title>Films API</title>
+  <link rel="stylesheet" href="docs/swagger-ui.css" />
+</head>
+<body>
+  <div id="swagger-ui"></div>
+  <script src="docs/swagger-ui-bundle.js"></script>
+  <script src="docs/swagger-initializer.js"></script>
+</body>
+</html>
+"##;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use actix_web::http::StatusCode;
+
+    #[test]
+    fn spec_documents_every_route() {
+        let doc = openapi();
+
+        let paths: Vec<&str> = doc.paths.paths.keys().map(String::as_str).collect();
+
+        assert_eq!(
+            paths,
+            vec![
+                "/health",
+                "/health/live",
+                "/health/ready",
+                "/v1/films",
+                "/v1/films/{film_id}",
+                "/v2/films",
+                "/v2/films/{film_id}"
+            ]
+        );
+        assert_eq!(doc.info.version, health::API_VERSION);
+    }
+
+    #[actix_rt::test]
+    async fn swagger_ui_is_served_from_the_api() {
+        let app = testing::app(service).await;
+
+        let req = actix_web::test::TestRequest::get()
+            .uri("/docs")
+            .to_request();
+        let res = actix_web::test::call_service(&app, req).await;
+        let csp = res.headers().get(header::CONTENT_SECURITY_POLICY).unwrap();
+        assert!(!csp.to_str().unwrap().contains("unsafe-inline"));
+        let page = actix_web::test::read_body(res).await;
+        assert!(!String::from_utf8_lossy(&page).contains("https://"));
+
+        for (asset, content_type) in [
+            ("swagger-ui.css", "text/css; charset=utf-8"),
+            ("swagger-ui-bundle.js", "text/javascript; charset=utf-8"),
+            ("swagger-initializer.js", "text/javascript; charset=utf-8"),
+        ] {
+            let req = actix_web::test::TestRequest::get()
+                .uri(&format!("/docs/{}", asset))
+                .to_request();
+            let res = actix_web::test::call_service(&app, req).await;
+            assert_eq!(res.status(), StatusCode::OK, "{}", asset);
+            assert_eq!(
+                res.headers().get(header::CONTENT_TYPE).unwrap(),
+                content_type
+            );
+        }
+
+        let req = actix_web::test::TestRequest::get()
+            .uri("/docs/index.html")
+            .to_request();
+        let res = actix_web::test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,386 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::{RwLock, RwLockReadGuard, RwLockWriteGuard},
+};
+
+use actix_web::{
+    web::{self, ServiceConfig},
+    HttpRequest, HttpResponse,
+};
+use actix_ws::Message;
+use futures_util::StreamExt;
+use shared::presence::{FieldLock, FilmPresence, PresenceEvent, PresenceRequest, Viewer};
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+const EVENTS_CAPACITY: usize = 256;
+
+pub fn service(cfg: &mut ServiceConfig) {
+    cfg.route("/presence", web::get().to(connect));
+}
+
+/// Keeps track of which users are looking at or editing which film and
+/// broadcasts a [`FilmPresence`] snapshot every time that changes.
+pub struct PresenceHub {
+    connections: RwLock<HashMap<Uuid, Connection>>,
+    events: broadcast::Sender<PresenceEvent>,
+}
+
+struct Connection {
+    user: String,
+    film_id: Uuid,
+    editing: bool,
+    locks: BTreeSet<String>,
+}
+
+impl PresenceHub {
+    pub fn new() -> Self {
+        let (events, _) = broadcast::channel(EVENTS_CAPACITY);
+        Self {
+            connections: RwLock::new(HashMap::new()),
+            events,
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<PresenceEvent> {
+        self.events.subscribe()
+    }
+
+    /// Presence of every film that currently has at least one viewer.
+    pub fn snapshots(&self) -> Vec<FilmPresence> {
+        let connections = self.read();
+        let films = connections
+            .values()
+            .map(|connection| connection.film_id)
+            .collect::<BTreeSet<_>>();
+        films
+            .into_iter()
+            .map(|film_id| Self::presence_of(&connections, film_id))
+            .collect()
+    }
+
+    pub fn snapshot(&self, film_id: Uuid) -> FilmPresence {
+        Self::presence_of(&self.read(), film_id)
+    }
+
+    /// Applies a request coming from `connection`. Returns an event that must
+    /// only be sent back to that connection, if any.
+    pub fn handle(&self, connection: Uuid, request: PresenceRequest) -> Option<PresenceEvent> {
+        match request {
+            PresenceRequest::Join {
+                film_id,
+                user,
+                editing,
+            } => {
+                let previous = self.write().insert(
+                    connection,
+                    Connection {
+                        user,
+                        film_id,
+                        editing,
+                        locks: BTreeSet::new(),
+                    },
+                );
+                if let Some(previous) = previous.filter(|p| p.film_id != film_id) {
+                    self.broadcast(previous.film_id);
+                }
+                self.broadcast(film_id);
+                None
+            }
+            PresenceRequest::Leave => {
+                self.disconnect(connection);
+                None
+            }
+            PresenceRequest::Lock { field } => {
+                let film_id = {
+                    let mut connections = self.write();
+                    let (film_id, user) = match connections.get(&connection) {
+                        Some(c) => (c.film_id, c.user.clone()),
+                        None => return None,
+                    };
+                    let holder = connections
+                        .iter()
+                        .find(|(id, c)| {
+                            **id != connection && c.film_id == film_id && c.locks.contains(&field)
+                        })
+                        .map(|(_, c)| c.user.clone());
+                    if let Some(holder) = holder {
+                        tracing::debug!("{} couldn't lock {} of film {}", user, field, film_id);
+                        return Some(PresenceEvent::LockDenied {
+                            film_id,
+                            field,
+                            holder,
+                        });
+                    }
+                    if let Some(c) = connections.get_mut(&connection) {
+                        c.locks.insert(field);
+                    }
+                    film_id
+                };
+                self.broadcast(film_id);
+                None
+            }
+            PresenceRequest::Unlock { field } => {
+                let film_id = self
+                    .write()
+                    .get_mut(&connection)
+                    .and_then(|c| c.locks.remove(&field).then_some(c.film_id));
+                if let Some(film_id) = film_id {
+                    self.broadcast(film_id);
+                }
+                None
+            }
+        }
+    }
+
+    /// Forgets everything about `connection`, releasing its locks.
+    pub fn disconnect(&self, connection: Uuid) {
+        let removed = self.write().remove(&connection);
+        if let Some(removed) = removed {
+            self.broadcast(removed.film_id);
+        }
+    }
+
+    fn broadcast(&self, film_id: Uuid) {
+        // an error only means nobody is listening right now
+        let _ = self
+            .events
+            .send(PresenceEvent::Snapshot(self.snapshot(film_id)));
+    }
+
+    fn presence_of(connections: &HashMap<Uuid, Connection>, film_id: Uuid) -> FilmPresence {
+        let in_film = connections.values().filter(|c| c.film_id == film_id);
+        let mut viewers = in_film
+            .clone()
+            .map(|c| Viewer {
+                user: c.user.clone(),
+                editing: c.editing,
+            })
+            .collect::<Vec<_>>();
+        viewers.sort();
+        viewers.dedup();
+        let mut locks = in_film
+            .flat_map(|c| {
+                c.locks.iter().map(|field| FieldLock {
+                    field: field.clone(),
+                    user: c.user.clone(),
+                })
+            })
+            .collect::<Vec<_>>();
+        locks.sort();
+        FilmPresence {
+            film_id,
+            viewers,
+            locks,
+        }
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, HashMap<Uuid, Connection>> {
+        self.connections.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, HashMap<Uuid, Connection>> {
+        self.connections.write().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Default for PresenceHub {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+async fn connect(
+    req: HttpRequest,
+    body: web::Payload,
+    hub: web::Data<PresenceHub>,
+) -> actix_web::Result<HttpResponse> {
+    let (response, mut session, mut stream) = actix_ws::handle(&req, body)?;
+    let hub = hub.into_inner();
+    let mut events = hub.subscribe();
+    let connection = Uuid::new_v4();
+    tracing::debug!("Presence connection {} opened", connection);
+
+    actix_web::rt::spawn(async move {
+        for snapshot in hub.snapshots() {
+            if send(&mut session, &PresenceEvent::Snapshot(snapshot))
+                .await
+                .is_err()
+            {
+                hub.disconnect(connection);
+                return;
+            }
+        }
+
+        loop {
+            tokio::select! {
+                message = stream.next() => match message {
+                    Some(Ok(Message::Text(text))) => {
+                        match serde_json::from_str::<PresenceRequest>(&text) {
+                            Ok(request) => {
+                                if let Some(reply) = hub.handle(connection, request) {
+                                    if send(&mut session, &reply).await.is_err() {
+                                        break;
+                                    }
+                                }
+                            }
+                            Err(e) => tracing::warn!("Invalid presence message: {}", e),
+                        }
+                    }
+                    Some(Ok(Message::Ping(bytes))) => {
+                        if session.pong(&bytes).await.is_err() {
+                            break;
+                        }
+                    }
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => {}
+                },
+                event = events.recv() => match event {
+                    Ok(event) => {
+                        if send(&mut session, &event).await.is_err() {
+                            break;
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        tracing::warn!("Presence connection {} skipped {} events", connection, skipped);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+            }
+        }
+
+        hub.disconnect(connection);
+        let _ = session.close(None).await;
+        tracing::debug!("Presence connection {} closed", connection);
+    });
+
+    Ok(response)
+}
+
+async fn send(session: &mut actix_ws::Session, event: &PresenceEvent) -> Result<(), ()> {
+    let text = serde_json::to_string(event).map_err(|e| {
+        tracing::error!("Couldn't serialize presence event: {}", e);
+    })?;
+    session.text(text).await.map_err(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn join(hub: &PresenceHub, connection: Uuid, film_id: Uuid, user: &str, editing: bool) {
+        hub.handle(
+            connection,
+            PresenceRequest::Join {
+                film_id,
+                user: user.to_string(),
+                editing,
+            },
+        );
+    }
+
+    #[test]
+    fn join_adds_viewer_and_broadcasts() {
+        let hub = PresenceHub::new();
+        let mut events = hub.subscribe();
+        let film_id = Uuid::new_v4();
+
+        join(&hub, Uuid::new_v4(), film_id, "alice", true);
+
+        let event = events.try_recv().unwrap();
+        let PresenceEvent::Snapshot(presence) = event else {
+            panic!("unexpected event {:?}", event);
+        };
+        assert_eq!(presence.film_id, film_id);
+        assert_eq!(
+            presence.viewers,
+            vec![Viewer {
+                user: "alice".to_string(),
+                editing: true
+            }]
+        );
+        assert_eq!(presence.editors_except("bob"), vec!["alice".to_string()]);
+        assert!(presence.editors_except("alice").is_empty());
+    }
+
+    #[test]
+    fn joining_another_film_leaves_the_previous_one() {
+        let hub = PresenceHub::new();
+        let connection = Uuid::new_v4();
+        let film1 = Uuid::new_v4();
+        let film2 = Uuid::new_v4();
+
+        join(&hub, connection, film1, "alice", false);
+        join(&hub, connection, film2, "alice", false);
+
+        assert!(hub.snapshot(film1).viewers.is_empty());
+        assert_eq!(hub.snapshot(film2).viewers.len(), 1);
+        assert_eq!(hub.snapshots().len(), 1);
+    }
+
+    #[test]
+    fn lock_is_denied_while_someone_else_holds_it() {
+        let hub = PresenceHub::new();
+        let film_id = Uuid::new_v4();
+        let alice = Uuid::new_v4();
+        let bob = Uuid::new_v4();
+        join(&hub, alice, film_id, "alice", true);
+        join(&hub, bob, film_id, "bob", true);
+
+        let lock = |field: &str| PresenceRequest::Lock {
+            field: field.to_string(),
+        };
+        assert_eq!(hub.handle(alice, lock("title")), None);
+        assert_eq!(
+            hub.handle(bob, lock("title")),
+            Some(PresenceEvent::LockDenied {
+                film_id,
+                field: "title".to_string(),
+                holder: "alice".to_string()
+            })
+        );
+        assert_eq!(hub.snapshot(film_id).lock_holder("title"), Some("alice"));
+
+        hub.handle(
+            alice,
+            PresenceRequest::Unlock {
+                field: "title".to_string(),
+            },
+        );
+        assert_eq!(hub.handle(bob, lock("title")), None);
+        assert_eq!(hub.snapshot(film_id).lock_holder("title"), Some("bob"));
+    }
+
+    #[test]
+    fn disconnect_releases_locks() {
+        let hub = PresenceHub::new();
+        let film_id = Uuid::new_v4();
+        let alice = Uuid::new_v4();
+        join(&hub, alice, film_id, "alice", true);
+        hub.handle(
+            alice,
+            PresenceRequest::Lock {
+                field: "year".to_string(),
+            },
+        );
+
+        hub.disconnect(alice);
+
+        let presence = hub.snapshot(film_id);
+        assert!(presence.viewers.is_empty());
+        assert!(presence.locks.is_empty());
+    }
+
+    #[test]
+    fn lock_without_joining_is_ignored() {
+        let hub = PresenceHub::new();
+        let reply = hub.handle(
+            Uuid::new_v4(),
+            PresenceRequest::Lock {
+                field: "title".to_string(),
+            },
+        );
+        assert_eq!(reply, None);
+        assert!(hub.snapshots().is_empty());
+    }
+}
@@ -0,0 +1,216 @@
+use std::{
+    collections::HashMap,
+    num::NonZeroUsize,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use lru::LruCache;
+
+use super::{Decision, Quota, RateLimitStore};
+
+/// How many clients get a bucket of their own. Past this many, the least
+/// recently used bucket makes room once it has refilled, and until then new
+/// clients share one, so nobody's bucket can be reset by making up keys.
+const MAX_BUCKETS: usize = 10_000;
+
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+    /// When it's back to `burst`, no different from a new bucket.
+    full_at: Instant,
+}
+
+impl Bucket {
+    fn new(quota: &Quota, now: Instant) -> Self {
+        Self {
+            tokens: quota.burst as f64,
+            updated_at: now,
+            full_at: now,
+        }
+    }
+
+    fn refill(&mut self, quota: &Quota, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated_at);
+        let refilled = elapsed.as_secs_f64() * quota.per_minute as f64 / 60.0;
+        self.tokens = (self.tokens + refilled).min(quota.burst as f64);
+        self.updated_at = now;
+    }
+
+    fn acquire(&mut self, quota: &Quota, now: Instant) -> Decision {
+        self.refill(quota, now);
+
+        let allowed = self.tokens >= 1.0;
+        if allowed {
+            self.tokens -= 1.0;
+        }
+        let reset = quota.refill_time(quota.burst as f64 - self.tokens);
+        self.full_at = now + reset;
+        Decision {
+            allowed,
+            limit: quota.burst,
+            remaining: self.tokens.floor() as u32,
+            reset,
+            retry_after: if allowed {
+                Duration::ZERO
+            } else {
+                quota.refill_time(1.0 - self.tokens)
+            },
+        }
+    }
+}
+
+struct Buckets {
+    clients: LruCache<String, Bucket>,
+    /// Shared by the clients that didn't get a bucket, one per quota.
+    overflow: HashMap<(u32, u32), Bucket>,
+}
+
+/// Token buckets kept in this process.
+pub struct MemoryRateLimitStore {
+    buckets: Mutex<Buckets>,
+}
+
+impl Default for MemoryRateLimitStore {
+    fn default() -> Self {
+        Self::with_capacity(MAX_BUCKETS)
+    }
+}
+
+impl MemoryRateLimitStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn with_capacity(capacity: usize) -> Self {
+        Self {
+            buckets: Mutex::new(Buckets {
+                clients: LruCache::new(NonZeroUsize::new(capacity).unwrap_or(NonZeroUsize::MIN)),
+                overflow: HashMap::new(),
+            }),
+        }
+    }
+
+    fn acquire_at(&self, key: &str, quota: &Quota, now: Instant) -> Result<Decision, String> {
+        let mut buckets = self.buckets.lock().map_err(|e| e.to_string())?;
+        let Buckets { clients, overflow } = &mut *buckets;
+        if !clients.contains(key) && clients.len() == clients.cap().get() {
+            let refilled = clients
+                .peek_lru()
+                .is_some_and(|(_, bucket)| bucket.full_at <= now);
+            if !refilled {
+                let bucket = overflow
+                    .entry((quota.burst, quota.per_minute))
+                    .or_insert_with(|| Bucket::new(quota, now));
+                return Ok(bucket.acquire(quota, now));
+            }
+            clients.pop_lru();
+        }
+        let bucket = clients.get_or_insert_mut(key.to_string(), || Bucket::new(quota, now));
+        Ok(bucket.acquire(quota, now))
+    }
+}
+
+#[async_trait]
+impl RateLimitStore for MemoryRateLimitStore {
+    async fn acquire(&self, key: &str, quota: &Quota) -> Result<Decision, String> {
+        self.acquire_at(key, quota, Instant::now())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const QUOTA: Quota = Quota {
+        burst: 2,
+        per_minute: 60,
+    };
+
+    #[test]
+    fn buckets_start_full() {
+        let store = MemoryRateLimitStore::new();
+        let now = Instant::now();
+
+        let first = store.acquire_at("a", &QUOTA, now).unwrap();
+        let second = store.acquire_at("a", &QUOTA, now).unwrap();
+        let third = store.acquire_at("a", &QUOTA, now).unwrap();
+
+        assert!(first.allowed && second.allowed);
+        assert_eq!(second.remaining, 0);
+        assert_eq!(second.reset, Duration::from_secs(2));
+        assert!(!third.allowed);
+        assert_eq!(third.retry_after, Duration::from_secs(1));
+    }
+
+    #[test]
+    fn buckets_refill_over_time() {
+        let store = MemoryRateLimitStore::new();
+        let now = Instant::now();
+        store.acquire_at("a", &QUOTA, now).unwrap();
+        store.acquire_at("a", &QUOTA, now).unwrap();
+
+        let later = store
+            .acquire_at("a", &QUOTA, now + Duration::from_millis(1500))
+            .unwrap();
+        let much_later = store
+            .acquire_at("a", &QUOTA, now + Duration::from_secs(60))
+            .unwrap();
+
+        assert!(later.allowed);
+        assert_eq!(later.remaining, 0);
+        assert!(much_later.allowed);
+        assert_eq!(much_later.remaining, 1);
+    }
+
+    #[test]
+    fn keys_have_their_own_buckets() {
+        let store = MemoryRateLimitStore::new();
+        let now = Instant::now();
+        store.acquire_at("a", &QUOTA, now).unwrap();
+        store.acquire_at("a", &QUOTA, now).unwrap();
+
+        assert!(!store.acquire_at("a", &QUOTA, now).unwrap().allowed);
+        assert!(store.acquire_at("b", &QUOTA, now).unwrap().allowed);
+    }
+
+    #[test]
+    fn refilled_buckets_make_room() {
+        let store = MemoryRateLimitStore::with_capacity(2);
+        let now = Instant::now();
+        for key in ["a", "b", "a"] {
+            store.acquire_at(key, &QUOTA, now).unwrap();
+        }
+
+        store
+            .acquire_at("c", &QUOTA, now + Duration::from_secs(2))
+            .unwrap();
+
+        let buckets = store.buckets.lock().unwrap();
+        assert_eq!(buckets.clients.len(), 2);
+        assert!(buckets.clients.contains("a") && buckets.clients.contains("c"));
+    }
+
+    #[test]
+    fn new_keys_dont_reset_throttled_clients() {
+        let store = MemoryRateLimitStore::with_capacity(2);
+        let now = Instant::now();
+        store.acquire_at("a", &QUOTA, now).unwrap();
+        store.acquire_at("a", &QUOTA, now).unwrap();
+        assert!(!store.acquire_at("a", &QUOTA, now).unwrap().allowed);
+
+        let flood: Vec<bool> = (0..100)
+            .map(|i| {
+                store
+                    .acquire_at(&format!("flood-{}", i), &QUOTA, now)
+                    .unwrap()
+                    .allowed
+            })
+            .collect();
+
+        assert!(!store.acquire_at("a", &QUOTA, now).unwrap().allowed);
+        // one key got the last bucket, the others shared the 2 of another
+        assert_eq!(flood.iter().filter(|allowed| **allowed).count(), 3);
+    }
+}
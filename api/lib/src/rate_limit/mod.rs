@@ -0,0 +1,518 @@
+mod memory_store;
+
+pub use memory_store::MemoryRateLimitStore;
+
+use std::{
+    net::{IpAddr, Ipv6Addr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+
+use actix_web::{
+    body::{EitherBody, MessageBody},
+    dev::{Service, ServiceRequest, ServiceResponse, Transform},
+    http::{
+        header::{self, HeaderMap, HeaderName},
+        Method,
+    },
+    middleware::{from_fn, Next},
+    Error, HttpResponse,
+};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::settings::Validate;
+
+pub const RATELIMIT_LIMIT: HeaderName = HeaderName::from_static("ratelimit-limit");
+pub const RATELIMIT_REMAINING: HeaderName = HeaderName::from_static("ratelimit-remaining");
+pub const RATELIMIT_RESET: HeaderName = HeaderName::from_static("ratelimit-reset");
+
+/// How many requests a client can make: `burst` at once, refilled at
+/// `per_minute`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Quota {
+    pub burst: u32,
+    pub per_minute: u32,
+}
+
+impl Quota {
+    /// How long it takes to get `tokens` requests back.
+    pub fn refill_time(&self, tokens: f64) -> Duration {
+        Duration::from_secs_f64((tokens * 60.0 / self.per_minute.max(1) as f64).max(0.0))
+    }
+}
+
+/// Who `ip` is as far as limits go: its /64 network for IPv6, which is what
+/// a single subscriber usually gets to pick addresses from.
+fn client(ip: IpAddr) -> String {
+    match ip {
+        IpAddr::V6(ip) if ip.to_ipv4_mapped().is_none() => {
+            let [a, b, c, d, ..] = ip.segments();
+            format!("{}/64", Ipv6Addr::new(a, b, c, d, 0, 0, 0, 0))
+        }
+        IpAddr::V6(ip) => ip.to_canonical().to_string(),
+        ip => ip.to_string(),
+    }
+}
+
+/// The `[rate_limit]` section of the [`Settings`](crate::settings::Settings).
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RateLimitSettings {
+    pub enabled: bool,
+    /// `GET`, `HEAD` and `OPTIONS` requests.
+    pub reads: Quota,
+    /// Every other request.
+    pub writes: Quota,
+    /// Whether clients are told apart by the `Forwarded` or `X-Forwarded-For`
+    /// headers, which only a proxy in front of the API can be trusted to set.
+    pub trust_forwarded_for: bool,
+}
+
+impl Default for RateLimitSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            reads: Quota {
+                burst: 100,
+                per_minute: 600,
+            },
+            writes: Quota {
+                burst: 10,
+                per_minute: 60,
+            },
+            trust_forwarded_for: false,
+        }
+    }
+}
+
+impl Validate for RateLimitSettings {
+    fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        for (name, quota) in [("reads", &self.reads), ("writes", &self.writes)] {
+            let env = name.to_uppercase();
+            if quota.burst == 0 {
+                problems.push(format!(
+                    "rate_limit.{}.burst (RATE_LIMIT_{}_BURST) must be at least 1",
+                    name, env
+                ));
+            }
+            if quota.per_minute == 0 {
+                problems.push(format!(
+                    "rate_limit.{}.per_minute (RATE_LIMIT_{}_PER_MINUTE) must be at least 1",
+                    name, env
+                ));
+            }
+        }
+        problems
+    }
+}
+
+/// The outcome of taking a request from a client's bucket.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Decision {
+    pub allowed: bool,
+    pub limit: u32,
+    pub remaining: u32,
+    /// Until the bucket is full again.
+    pub reset: Duration,
+    /// Until the next request is allowed, zero when this one was.
+    pub retry_after: Duration,
+}
+
+impl Decision {
+    fn insert_headers(&self, headers: &mut HeaderMap) {
+        headers.insert(RATELIMIT_LIMIT, self.limit.into());
+        headers.insert(RATELIMIT_REMAINING, self.remaining.into());
+        headers.insert(RATELIMIT_RESET, ceil_seconds(self.reset).into());
+    }
+}
+
+fn ceil_seconds(duration: Duration) -> u64 {
+    duration.as_secs() + u64::from(duration.subsec_nanos() > 0)
+}
+
+/// The hash of the request's `Bearer` token, which tells clients apart
+/// without keeping the tokens themselves around.
+pub(crate) fn token_hash(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(|token| hex::encode(Sha256::digest(token.trim())))
+}
+
+/// Where the token buckets live. The in-process [`MemoryRateLimitStore`] only
+/// limits a single instance of the API, a shared store limits all of them.
+#[async_trait]
+pub trait RateLimitStore: Send + Sync + 'static {
+    /// Takes a request from the `key` bucket, if there's one left.
+    async fn acquire(&self, key: &str, quota: &Quota) -> Result<Decision, String>;
+}
+
+/// Limits how many requests each client address, and each API
+/// token, can make. Reads and writes are limited separately.
+#[derive(Clone)]
+pub struct RateLimit {
+    store: Arc<dyn RateLimitStore>,
+    settings: Arc<RateLimitSettings>,
+}
+
+impl RateLimit {
+    pub fn new(store: impl RateLimitStore, settings: RateLimitSettings) -> Self {
+        Self {
+            store: Arc::new(store),
+            settings: Arc::new(settings),
+        }
+    }
+
+    /// Limits with a [`MemoryRateLimitStore`].
+    pub fn in_memory(settings: RateLimitSettings) -> Self {
+        Self::new(MemoryRateLimitStore::new(), settings)
+    }
+
+    /// Whether the request is a read or a write, and how big its buckets are.
+    fn quota(&self, req: &ServiceRequest) -> (&'static str, &Quota) {
+        match *req.method() {
+            Method::GET | Method::HEAD | Method::OPTIONS => ("reads", &self.settings.reads),
+            _ => ("writes", &self.settings.writes),
+        }
+    }
+
+    fn address(&self, req: &ServiceRequest) -> String {
+        let address = if self.settings.trust_forwarded_for {
+            req.connection_info().realip_remote_addr().map(|address| {
+                match address.parse::<SocketAddr>() {
+                    Ok(address) => client(address.ip()),
+                    Err(_) => address
+                        .parse()
+                        .map(client)
+                        .unwrap_or_else(|_| address.to_string()),
+                }
+            })
+        } else {
+            req.peer_addr().map(|address| client(address.ip()))
+        };
+        address.unwrap_or_else(|| "unknown".to_string())
+    }
+
+    /// Takes the request from its address's bucket, and from its token's
+    /// when it has one. Tokens aren't checked here, so both are charged: a
+    /// client can't get around its address's limit by making up new tokens.
+    /// The decision is the tighter of the two.
+    async fn acquire(&self, req: &ServiceRequest) -> Result<Decision, String> {
+        let (kind, quota) = self.quota(req);
+        let key = format!("{}:ip:{}", kind, self.address(req));
+        let decision = self.acquire_bucket(&key, quota).await?;
+        let Some(token) = token_hash(req.headers()).filter(|_| decision.allowed) else {
+            return Ok(decision);
+        };
+
+        let key = format!("{}:token:{}", kind, token);
+        let token_decision = self.acquire_bucket(&key, quota).await?;
+        Ok(
+            if token_decision.remaining < decision.remaining || !token_decision.allowed {
+                token_decision
+            } else {
+                decision
+            },
+        )
+    }
+
+    async fn acquire_bucket(&self, key: &str, quota: &Quota) -> Result<Decision, String> {
+        self.store
+            .acquire(key, quota)
+            .await
+            .map_err(|e| format!("{}: {}", key, e))
+    }
+
+    /// The middleware limiting the requests of the services it wraps.
+    pub fn middleware<S, B>(
+        &self,
+    ) -> impl Transform<
+        S,
+        ServiceRequest,
+        Response = ServiceResponse<EitherBody<B>>,
+        Error = Error,
+        InitError = (),
+    >
+    where
+        S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+        B: MessageBody + 'static,
+    {
+        let limit = self.clone();
+        from_fn(move |req, next| limit.clone().limit(req, next))
+    }
+
+    async fn limit<B: MessageBody>(
+        self,
+        req: ServiceRequest,
+        next: Next<B>,
+    ) -> Result<ServiceResponse<EitherBody<B>>, Error> {
+        if !self.settings.enabled {
+            return next.call(req).await.map(|res| res.map_into_left_body());
+        }
+
+        let decision = match self.acquire(&req).await {
+            Ok(decision) => decision,
+            Err(e) => {
+                // better to serve everyone than no one
+                tracing::warn!("Couldn't check the rate limit for {}", e);
+                return next.call(req).await.map(|res| res.map_into_left_body());
+            }
+        };
+
+        if !decision.allowed {
+            let retry_after = ceil_seconds(decision.retry_after);
+            let mut res = HttpResponse::TooManyRequests()
+                .insert_header((header::RETRY_AFTER, retry_after))
+                .body(format!(
+                    "Too many requests, try again in {} second(s)",
+                    retry_after
+                ));
+            decision.insert_headers(res.headers_mut());
+            return Ok(req.into_response(res).map_into_right_body());
+        }
+
+        let mut res = next.call(req).await?;
+        decision.insert_headers(res.headers_mut());
+        Ok(res.map_into_left_body())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use actix_web::{
+        http::StatusCode,
+        web::{self, ServiceConfig},
+    };
+
+    fn settings(reads: u32, writes: u32) -> RateLimitSettings {
+        RateLimitSettings {
+            reads: Quota {
+                burst: reads,
+                per_minute: 1,
+            },
+            writes: Quota {
+                burst: writes,
+                per_minute: 1,
+            },
+            ..Default::default()
+        }
+    }
+
+    fn films(settings: RateLimitSettings) -> impl FnOnce(&mut ServiceConfig) {
+        move |cfg| {
+            cfg.service(
+                web::scope("")
+                    .wrap(RateLimit::in_memory(settings).middleware())
+                    .route("/films", web::get().to(HttpResponse::Ok))
+                    .route("/films", web::post().to(HttpResponse::Ok)),
+            );
+        }
+    }
+
+    fn request(method: Method, ip: &str, token: Option<&str>) -> actix_web::test::TestRequest {
+        let req = actix_web::test::TestRequest::default()
+            .method(method)
+            .uri("/films")
+            .peer_addr(format!("{}:12345", ip).parse().unwrap());
+        match token {
+            Some(token) => req.insert_header((header::AUTHORIZATION, format!("Bearer {}", token))),
+            None => req,
+        }
+    }
+
+    #[actix_rt::test]
+    async fn requests_over_the_limit_are_rejected() {
+        let app = testing::app(films(settings(10, 2))).await;
+
+        for remaining in ["1", "0"] {
+            let res = actix_web::test::call_service(
+                &app,
+                request(Method::POST, "10.0.0.1", None).to_request(),
+            )
+            .await;
+            assert_eq!(res.status(), StatusCode::OK);
+            assert_eq!(res.headers().get(RATELIMIT_LIMIT).unwrap(), "2");
+            assert_eq!(res.headers().get(RATELIMIT_REMAINING).unwrap(), remaining);
+        }
+
+        let res = actix_web::test::call_service(
+            &app,
+            request(Method::POST, "10.0.0.1", None).to_request(),
+        )
+        .await;
+
+        assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(res.headers().get(header::RETRY_AFTER).unwrap(), "60");
+        assert_eq!(res.headers().get(RATELIMIT_REMAINING).unwrap(), "0");
+        assert_eq!(res.headers().get(RATELIMIT_RESET).unwrap(), "120");
+    }
+
+    #[actix_rt::test]
+    async fn reads_and_writes_are_limited_separately() {
+        let app = testing::app(films(settings(1, 1))).await;
+
+        let write = actix_web::test::call_service(
+            &app,
+            request(Method::POST, "10.0.0.1", None).to_request(),
+        )
+        .await;
+        let read = actix_web::test::call_service(
+            &app,
+            request(Method::GET, "10.0.0.1", None).to_request(),
+        )
+        .await;
+
+        assert_eq!(write.status(), StatusCode::OK);
+        assert_eq!(read.status(), StatusCode::OK);
+    }
+
+    #[actix_rt::test]
+    async fn clients_are_limited_by_address_and_token() {
+        let app = testing::app(films(settings(2, 1))).await;
+
+        let requests = [
+            (
+                request(Method::GET, "10.0.0.1", None).to_request(),
+                StatusCode::OK,
+            ),
+            (
+                request(Method::GET, "10.0.0.2", Some("a")).to_request(),
+                StatusCode::OK,
+            ),
+            // a token has its own bucket, wherever it's used from
+            (
+                request(Method::GET, "10.0.0.3", Some("a")).to_request(),
+                StatusCode::OK,
+            ),
+            (
+                request(Method::GET, "10.0.0.4", Some("a")).to_request(),
+                StatusCode::TOO_MANY_REQUESTS,
+            ),
+            // and still takes from its address's
+            (
+                request(Method::GET, "10.0.0.1", Some("b")).to_request(),
+                StatusCode::OK,
+            ),
+            (
+                request(Method::GET, "10.0.0.1", None).to_request(),
+                StatusCode::TOO_MANY_REQUESTS,
+            ),
+        ];
+        for (req, status) in requests {
+            assert_eq!(
+                actix_web::test::call_service(&app, req).await.status(),
+                status
+            );
+        }
+    }
+
+    #[actix_rt::test]
+    async fn rotating_tokens_from_one_address_are_limited() {
+        let app = testing::app(films(settings(1, 2))).await;
+
+        let statuses = [
+            ("a", StatusCode::OK),
+            ("b", StatusCode::OK),
+            ("c", StatusCode::TOO_MANY_REQUESTS),
+        ];
+        for (token, status) in statuses {
+            let res = actix_web::test::call_service(
+                &app,
+                request(Method::POST, "10.0.0.1", Some(token)).to_request(),
+            )
+            .await;
+            assert_eq!(res.status(), status);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn ipv6_clients_are_limited_by_network() {
+        let app = testing::app(films(settings(1, 1))).await;
+
+        for (ip, status) in [
+            ("[2001:db8:0:1::1]", StatusCode::OK),
+            ("[2001:db8:0:1:ffff::2]", StatusCode::TOO_MANY_REQUESTS),
+            ("[2001:db8:0:2::1]", StatusCode::OK),
+        ] {
+            let res =
+                actix_web::test::call_service(&app, request(Method::POST, ip, None).to_request())
+                    .await;
+            assert_eq!(res.status(), status, "{}", ip);
+        }
+    }
+
+    #[test]
+    fn clients_are_ipv4_addresses_and_ipv6_networks() {
+        for (ip, client_key) in [
+            ("10.0.0.1", "10.0.0.1"),
+            ("2001:db8:0:1:2:3:4:5", "2001:db8:0:1::/64"),
+            ("::ffff:10.0.0.1", "10.0.0.1"),
+        ] {
+            assert_eq!(client(ip.parse().unwrap()), client_key);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn nothing_is_limited_when_disabled() {
+        let app = testing::app(films(RateLimitSettings {
+            enabled: false,
+            ..settings(1, 1)
+        }))
+        .await;
+
+        for _ in 0..3 {
+            let res = actix_web::test::call_service(
+                &app,
+                request(Method::POST, "10.0.0.1", None).to_request(),
+            )
+            .await;
+            assert_eq!(res.status(), StatusCode::OK);
+            assert!(res.headers().get(RATELIMIT_LIMIT).is_none());
+        }
+    }
+
+    struct FailingStore;
+
+    #[async_trait]
+    impl RateLimitStore for FailingStore {
+        async fn acquire(&self, _key: &str, _quota: &Quota) -> Result<Decision, String> {
+            Err("store is down".to_string())
+        }
+    }
+
+    #[actix_rt::test]
+    async fn requests_are_let_through_when_the_store_fails() {
+        let app = testing::app(|cfg| {
+            cfg.service(
+                web::scope("")
+                    .wrap(RateLimit::new(FailingStore, settings(1, 1)).middleware())
+                    .route("/films", web::post().to(HttpResponse::Ok)),
+            );
+        })
+        .await;
+
+        let res = actix_web::test::call_service(
+            &app,
+            request(Method::POST, "10.0.0.1", None).to_request(),
+        )
+        .await;
+
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn empty_quotas_are_reported() {
+        let problems = settings(0, 1).problems();
+
+        assert_eq!(
+            problems,
+            vec!["rate_limit.reads.burst (RATE_LIMIT_READS_BURST) must be at least 1"]
+        );
+    }
+}
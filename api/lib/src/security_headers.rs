@@ -0,0 +1,352 @@
+use std::path::Path;
+
+use actix_web::{
+    http::header::{
+        HeaderValue, CONTENT_SECURITY_POLICY, CROSS_ORIGIN_OPENER_POLICY, REFERRER_POLICY,
+        STRICT_TRANSPORT_SECURITY, X_CONTENT_TYPE_OPTIONS, X_FRAME_OPTIONS,
+    },
+    middleware::DefaultHeaders,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{cors::is_origin, settings::Validate};
+
+/// The `[security_headers]` section of the
+/// [`Settings`](crate::settings::Settings).
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SecurityHeadersSettings {
+    /// Where the front end may load images, the film posters, from, on top
+    /// of the API's own origin.
+    pub image_origins: Vec<String>,
+    /// The `script-src` of the front end. The wasm bundle needs
+    /// `'wasm-unsafe-eval'`, the inline script loading it is allowed by
+    /// [`allow_inline_scripts`](Self::allow_inline_scripts).
+    pub script_sources: Vec<String>,
+    /// Who may show the pages in a frame, nobody when empty.
+    pub frame_ancestors: Vec<String>,
+    /// How long browsers only use HTTPS once they got the API over HTTPS,
+    /// `Strict-Transport-Security` isn't sent when 0.
+    pub hsts_max_age_seconds: u64,
+}
+
+impl Default for SecurityHeadersSettings {
+    fn default() -> Self {
+        Self {
+            // where the bundled fixtures take their posters from
+            image_origins: vec!["https://placehold.co".to_string()],
+            script_sources: ["'self'", "'wasm-unsafe-eval'"].map(String::from).to_vec(),
+            frame_ancestors: Vec::new(),
+            hsts_max_age_seconds: 365 * 24 * 60 * 60,
+        }
+    }
+}
+
+impl Validate for SecurityHeadersSettings {
+    fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        for (setting, sources) in [
+            (
+                "security_headers.image_origins (CSP_IMAGE_ORIGINS)",
+                &self.image_origins,
+            ),
+            (
+                "security_headers.script_sources (CSP_SCRIPT_SOURCES)",
+                &self.script_sources,
+            ),
+            (
+                "security_headers.frame_ancestors (CSP_FRAME_ANCESTORS)",
+                &self.frame_ancestors,
+            ),
+        ] {
+            for source in sources {
+                if !is_source(source) {
+                    problems.push(format!(
+                        "{}: `{}` isn't an origin like https://images.example.com, a scheme like https: or a quoted keyword like 'self'",
+                        setting, source
+                    ));
+                }
+            }
+        }
+        problems
+    }
+}
+
+impl SecurityHeadersSettings {
+    /// Allows the inline scripts of the front end's `index.html`, like the
+    /// one loading the wasm bundle, by their hash. They're left out when the
+    /// file can't be read, the API is then served without its front end.
+    pub fn allow_inline_scripts(mut self, index: &Path) -> Self {
+        match std::fs::read_to_string(index) {
+            Ok(html) => self.script_sources.extend(inline_script_hashes(&html)),
+            Err(e) => tracing::warn!("Couldn't read {}: {}", index.display(), e),
+        }
+        self
+    }
+
+    /// The `Content-Security-Policy` of the front end and the API.
+    pub fn content_security_policy(&self) -> String {
+        let frame_ancestors = if self.frame_ancestors.is_empty() {
+            "'none'".to_string()
+        } else {
+            self.frame_ancestors.join(" ")
+        };
+        let directives = [
+            "default-src 'self'".to_string(),
+            format!("script-src {}", self.script_sources.join(" ")),
+            // Dioxus sets inline styles
+            "style-src 'self' 'unsafe-inline'".to_string(),
+            format!("img-src 'self' data: {}", self.image_origins.join(" ")),
+            // the API, and its WebSockets
+            "connect-src 'self'".to_string(),
+            "object-src 'none'".to_string(),
+            "base-uri 'self'".to_string(),
+            "form-action 'self'".to_string(),
+            format!("frame-ancestors {}", frame_ancestors),
+        ];
+        directives
+            .iter()
+            .map(|directive| directive.trim_end())
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+}
+
+/// The `'sha256-...'` sources of the `<script>`s of `html` without a `src`.
+fn inline_script_hashes(html: &str) -> Vec<String> {
+    let mut hashes = Vec::new();
+    let mut rest = html;
+    while let Some(start) = rest.find("<script") {
+        rest = &rest[start..];
+        let Some(tag_end) = rest.find('>') else {
+            break;
+        };
+        let tag = &rest[..tag_end];
+        rest = &rest[tag_end + 1..];
+        let Some(end) = rest.find("</script>") else {
+            break;
+        };
+        if !tag.contains(" src=") {
+            let digest = Sha256::digest(&rest[..end]);
+            hashes.push(format!("'sha256-{}'", STANDARD.encode(digest)));
+        }
+        rest = &rest[end..];
+    }
+    hashes
+}
+
+/// An origin, a scheme like `https:` or a quoted keyword or hash like
+/// `'self'`, without anything that would end the directive.
+fn is_source(source: &str) -> bool {
+    let is_scheme = source.strip_suffix(':').is_some_and(|scheme| {
+        !scheme.is_empty() && scheme.chars().all(|c| c.is_ascii_alphabetic())
+    });
+    let is_quoted = source.len() > 2 && source.starts_with('\'') && source.ends_with('\'');
+    source
+        .chars()
+        .all(|c| c.is_ascii_graphic() && c != ';' && c != ',')
+        && (is_origin(source) || is_scheme || is_quoted)
+}
+
+/// Middleware adding the security headers to the responses that don't have
+/// them already, like the documentation pages loading their scripts from a
+/// CDN, for the given, already validated, settings.
+///
+/// Both API binaries wrap the API and the front end with it.
+pub fn security_headers(settings: &SecurityHeadersSettings) -> DefaultHeaders {
+    let mut headers = vec![
+        (CONTENT_SECURITY_POLICY, settings.content_security_policy()),
+        (X_CONTENT_TYPE_OPTIONS, "nosniff".to_string()),
+        (
+            REFERRER_POLICY,
+            "strict-origin-when-cross-origin".to_string(),
+        ),
+        (CROSS_ORIGIN_OPENER_POLICY, "same-origin".to_string()),
+    ];
+    if settings.frame_ancestors.is_empty() {
+        // for the browsers ignoring `frame-ancestors`
+        headers.push((X_FRAME_OPTIONS, "DENY".to_string()));
+    }
+    if settings.hsts_max_age_seconds > 0 {
+        headers.push((
+            STRICT_TRANSPORT_SECURITY,
+            format!("max-age={}", settings.hsts_max_age_seconds),
+        ));
+    }
+    headers
+        .into_iter()
+        .fold(
+            DefaultHeaders::new(),
+            |default_headers, (name, value)| match HeaderValue::from_str(&value) {
+                Ok(value) => default_headers.add((name, value)),
+                Err(_) => {
+                    tracing::warn!("Skipping the invalid {} header: {}", name, value);
+                    default_headers
+                }
+            },
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use actix_web::{
+        web::{self, ServiceConfig},
+        HttpResponse,
+    };
+
+    fn routes(settings: SecurityHeadersSettings) -> impl FnOnce(&mut ServiceConfig) {
+        move |cfg| {
+            cfg.service(
+                web::scope("")
+                    .wrap(security_headers(&settings))
+                    .route("/films", web::get().to(HttpResponse::Ok))
+                    .route(
+                        "/docs",
+                        web::get().to(|| async {
+                            HttpResponse::Ok()
+                                .insert_header((CONTENT_SECURITY_POLICY, "default-src *"))
+                                .finish()
+                        }),
+                    ),
+            );
+        }
+    }
+
+    async fn headers(
+        settings: SecurityHeadersSettings,
+        uri: &str,
+    ) -> actix_web::http::header::HeaderMap {
+        let app = testing::app(routes(settings)).await;
+        let req = actix_web::test::TestRequest::get().uri(uri).to_request();
+        actix_web::test::call_service(&app, req)
+            .await
+            .headers()
+            .clone()
+    }
+
+    #[actix_rt::test]
+    async fn responses_get_the_security_headers() {
+        let headers = headers(SecurityHeadersSettings::default(), "/films").await;
+
+        let csp = headers
+            .get(CONTENT_SECURITY_POLICY)
+            .unwrap()
+            .to_str()
+            .unwrap();
+        assert!(
+            csp.contains("script-src 'self' 'wasm-unsafe-eval'"),
+            "{}",
+            csp
+        );
+        assert!(
+            csp.contains("img-src 'self' data: https://placehold.co;"),
+            "{}",
+            csp
+        );
+        assert!(csp.contains("frame-ancestors 'none'"), "{}", csp);
+        assert_eq!(headers.get(X_CONTENT_TYPE_OPTIONS).unwrap(), "nosniff");
+        assert_eq!(headers.get(X_FRAME_OPTIONS).unwrap(), "DENY");
+        assert_eq!(
+            headers.get(STRICT_TRANSPORT_SECURITY).unwrap(),
+            "max-age=31536000"
+        );
+        assert_eq!(
+            headers.get(REFERRER_POLICY).unwrap(),
+            "strict-origin-when-cross-origin"
+        );
+    }
+
+    #[actix_rt::test]
+    async fn the_policy_follows_the_settings() {
+        let settings = SecurityHeadersSettings {
+            image_origins: vec![
+                "https://image.tmdb.org".to_string(),
+                "https://*.media-amazon.com".to_string(),
+            ],
+            frame_ancestors: vec!["'self'".to_string()],
+            hsts_max_age_seconds: 0,
+            ..Default::default()
+        };
+
+        let headers = headers(settings, "/films").await;
+
+        let csp = headers
+            .get(CONTENT_SECURITY_POLICY)
+            .unwrap()
+            .to_str()
+            .unwrap();
+        assert!(
+            csp.contains("img-src 'self' data: https://image.tmdb.org https://*.media-amazon.com;"),
+            "{}",
+            csp
+        );
+        assert!(csp.contains("frame-ancestors 'self'"), "{}", csp);
+        assert!(!headers.contains_key(X_FRAME_OPTIONS));
+        assert!(!headers.contains_key(STRICT_TRANSPORT_SECURITY));
+    }
+
+    #[actix_rt::test]
+    async fn headers_set_by_the_handler_are_kept() {
+        let headers = headers(SecurityHeadersSettings::default(), "/docs").await;
+
+        assert_eq!(
+            headers.get(CONTENT_SECURITY_POLICY).unwrap(),
+            "default-src *"
+        );
+        assert_eq!(headers.get(X_CONTENT_TYPE_OPTIONS).unwrap(), "nosniff");
+    }
+
+    #[actix_rt::test]
+    async fn inline_scripts_of_the_index_are_allowed_by_hash() {
+        let index = std::env::temp_dir().join(format!("index-{}.html", uuid::Uuid::new_v4()));
+        std::fs::write(
+            &index,
+            r#"<html><head><script src="/app.js"></script></head>
+<body><script type="module">import init from "/app.js"; init();</script></body></html>"#,
+        )
+        .unwrap();
+        let settings = SecurityHeadersSettings::default().allow_inline_scripts(&index);
+
+        let headers = headers(settings, "/films").await;
+
+        let csp = headers
+            .get(CONTENT_SECURITY_POLICY)
+            .unwrap()
+            .to_str()
+            .unwrap();
+        let hash = STANDARD.encode(Sha256::digest(r#"import init from "/app.js"; init();"#));
+        assert!(
+            csp.contains(&format!(
+                "script-src 'self' 'wasm-unsafe-eval' 'sha256-{}';",
+                hash
+            )),
+            "{}",
+            csp
+        );
+        assert!(!csp.contains("script-src 'self' 'wasm-unsafe-eval' 'unsafe-inline'"));
+    }
+
+    #[test]
+    fn invalid_sources_are_reported() {
+        let settings = SecurityHeadersSettings {
+            image_origins: vec![
+                "https:".to_string(),
+                "images.example.com".to_string(),
+                "https://a.example.com; script-src *".to_string(),
+            ],
+            script_sources: vec!["'self'".to_string(), "self".to_string()],
+            ..Default::default()
+        };
+
+        let problems = settings.problems();
+
+        assert_eq!(problems.len(), 3, "{:?}", problems);
+        assert!(problems[0].starts_with("security_headers.image_origins"));
+        assert!(problems[1].starts_with("security_headers.image_origins"));
+        assert!(problems[2].starts_with("security_headers.script_sources"));
+    }
+}
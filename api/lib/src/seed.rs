@@ -0,0 +1,68 @@
+use shared::models::{CreateFilm, Film};
+
+use crate::film_repository::{FilmRepository, FilmResult};
+
+/// Films bundled with the API to seed new databases and demos.
+const FIXTURES: &str = include_str!("../../db/fixtures/films.json");
+
+pub fn fixtures() -> Vec<CreateFilm> {
+    serde_json::from_str(FIXTURES).expect("the bundled fixtures should be valid")
+}
+
+/// Creates the fixture films that aren't in the repository yet, so it can be
+/// run on every start. A film is considered to exist when there's one with
+/// the same title, director and year. Returns the created films.
+pub async fn seed<R: FilmRepository>(repo: &R) -> FilmResult<Vec<Film>> {
+    let existing = repo.get_films().await?;
+    let mut created = vec![];
+    for fixture in fixtures() {
+        let exists = existing.iter().any(|film| {
+            film.title == fixture.title
+                && film.director == fixture.director
+                && film.year == fixture.year
+        });
+        if !exists {
+            created.push(repo.create_film(&fixture).await?);
+        }
+    }
+    tracing::info!("Seeded {} films", created.len());
+    Ok(created)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::film_repository::MemoryFilmRepository;
+
+    #[test]
+    fn fixtures_have_posters() {
+        let fixtures = fixtures();
+
+        assert!(!fixtures.is_empty());
+        assert!(fixtures
+            .iter()
+            .all(|film| film.poster.starts_with("https://")));
+    }
+
+    #[actix_rt::test]
+    async fn seed_is_idempotent() {
+        let repo = MemoryFilmRepository::new();
+
+        let first = seed(&repo).await.unwrap();
+        let second = seed(&repo).await.unwrap();
+
+        assert_eq!(first.len(), fixtures().len());
+        assert!(second.is_empty());
+        assert_eq!(repo.get_films().await.unwrap().len(), fixtures().len());
+    }
+
+    #[actix_rt::test]
+    async fn seed_only_adds_missing_films() {
+        let repo = MemoryFilmRepository::new();
+        repo.create_film(&fixtures()[0]).await.unwrap();
+
+        let created = seed(&repo).await.unwrap();
+
+        assert_eq!(created.len(), fixtures().len() - 1);
+    }
+}
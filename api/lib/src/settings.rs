@@ -0,0 +1,653 @@
+use std::{
+    fmt,
+    net::IpAddr,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use config::{Config, File, FileFormat};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cors::CorsSettings,
+    database::{self, DatabaseError, DatabaseSettings},
+    deprecation::DeprecationSettings,
+    film_repository::RepositoryKind,
+    metrics::MetricsSettings,
+    rate_limit::RateLimitSettings,
+    security_headers::SecurityHeadersSettings,
+    telemetry::TelemetrySettings,
+    webhooks::WebhooksSettings,
+};
+
+/// The optional file the settings are read from, unless `SETTINGS_FILE`
+/// points somewhere else.
+pub const DEFAULT_SETTINGS_FILE: &str = "settings.toml";
+
+/// A section of the [`Settings`], checked by [`Settings::validate`].
+pub trait Validate {
+    /// Everything that doesn't make sense, as `setting: problem`.
+    fn problems(&self) -> Vec<String>;
+}
+
+/// Environment variables overriding a single setting.
+const ENV_OVERRIDES: &[(&str, &str)] = &[
+    ("HOST", "server.host"),
+    ("PORT", "server.port"),
+    ("GRPC_PORT", "server.grpc_port"),
+    ("STATIC_FOLDER", "server.static_folder"),
+    (
+        "SHUTDOWN_TIMEOUT_SECONDS",
+        "server.shutdown_timeout_seconds",
+    ),
+    ("CORS_ORIGINS", "cors.allowed_origins"),
+    ("CORS_METHODS", "cors.allowed_methods"),
+    ("CORS_HEADERS", "cors.allowed_headers"),
+    ("CORS_EXPOSED_HEADERS", "cors.exposed_headers"),
+    ("CORS_ALLOW_CREDENTIALS", "cors.allow_credentials"),
+    ("CORS_MAX_AGE_SECONDS", "cors.max_age_seconds"),
+    ("RATE_LIMIT_ENABLED", "rate_limit.enabled"),
+    ("RATE_LIMIT_READS_BURST", "rate_limit.reads.burst"),
+    ("RATE_LIMIT_READS_PER_MINUTE", "rate_limit.reads.per_minute"),
+    ("RATE_LIMIT_WRITES_BURST", "rate_limit.writes.burst"),
+    (
+        "RATE_LIMIT_WRITES_PER_MINUTE",
+        "rate_limit.writes.per_minute",
+    ),
+    (
+        "RATE_LIMIT_TRUST_FORWARDED_FOR",
+        "rate_limit.trust_forwarded_for",
+    ),
+    ("CSP_IMAGE_ORIGINS", "security_headers.image_origins"),
+    ("CSP_SCRIPT_SOURCES", "security_headers.script_sources"),
+    ("CSP_FRAME_ANCESTORS", "security_headers.frame_ancestors"),
+    (
+        "HSTS_MAX_AGE_SECONDS",
+        "security_headers.hsts_max_age_seconds",
+    ),
+    ("DEPRECATED_AT", "deprecation.deprecated_at"),
+    ("METRICS_TOKEN", "metrics.token"),
+    (
+        "WEBHOOKS_ALLOW_PRIVATE_ADDRESSES",
+        "webhooks.allow_private_addresses",
+    ),
+    ("WEBHOOKS_SECRET_KEY", "webhooks.secret_key"),
+    ("LOG_FORMAT", "log.format"),
+    ("OTEL_EXPORTER_OTLP_ENDPOINT", "telemetry.otlp_endpoint"),
+    ("OTEL_SERVICE_NAME", "telemetry.service_name"),
+    ("REPOSITORY", "repository.kind"),
+    ("SQLITE_URL", "repository.sqlite_url"),
+    ("MEMORY_FILE", "repository.memory_file"),
+    ("SEED_DATABASE", "repository.seed"),
+    ("CACHE_CAPACITY", "repository.cache_capacity"),
+    ("CACHE_TTL_SECONDS", "repository.cache_ttl_seconds"),
+    (
+        "CACHE_REPLICA_LAG_SECONDS",
+        "repository.cache_replica_lag_seconds",
+    ),
+];
+
+/// Settings that are comma separated lists in the environment.
+const LIST_SETTINGS: &[&str] = &[
+    "cors.allowed_origins",
+    "cors.allowed_methods",
+    "cors.allowed_headers",
+    "cors.exposed_headers",
+    "security_headers.image_origins",
+    "security_headers.script_sources",
+    "security_headers.frame_ancestors",
+];
+
+/// Everything the API binaries can be configured with.
+///
+/// Every setting has a default, which the TOML file in `SETTINGS_FILE`
+/// overrides, which the environment overrides in turn: the variables in
+/// [`ENV_OVERRIDES`]. The [`DatabaseSettings`] are then layered on top, from
+/// their own file in `DATABASE_CONFIG` and the `DATABASE_*` variables.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Settings {
+    pub server: ServerSettings,
+    pub cors: CorsSettings,
+    pub rate_limit: RateLimitSettings,
+    pub security_headers: SecurityHeadersSettings,
+    pub deprecation: DeprecationSettings,
+    pub metrics: MetricsSettings,
+    pub webhooks: WebhooksSettings,
+    pub log: LogSettings,
+    pub telemetry: TelemetrySettings,
+    pub repository: RepositorySettings,
+    pub database: DatabaseSettings,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ServerSettings {
+    pub host: String,
+    pub port: u16,
+    pub grpc_port: u16,
+    pub static_folder: PathBuf,
+    /// How long in-flight requests and background workers get to finish
+    /// once the API is asked to stop.
+    pub shutdown_timeout_seconds: u64,
+}
+
+impl Default for ServerSettings {
+    fn default() -> Self {
+        Self {
+            host: "127.0.0.1".to_string(),
+            port: 8080,
+            grpc_port: 50051,
+            static_folder: PathBuf::from("./front/dist"),
+            shutdown_timeout_seconds: 30,
+        }
+    }
+}
+
+impl ServerSettings {
+    pub fn address(&self) -> String {
+        format!("{}:{}", self.host, self.port)
+    }
+
+    pub fn grpc_address(&self) -> String {
+        format!("{}:{}", self.host, self.grpc_port)
+    }
+
+    pub fn shutdown_timeout(&self) -> Duration {
+        Duration::from_secs(self.shutdown_timeout_seconds)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct LogSettings {
+    pub format: LogFormat,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human readable, the default in debug builds.
+    Pretty,
+    /// One JSON object per line, the default in release builds.
+    Json,
+}
+
+impl Default for LogFormat {
+    fn default() -> Self {
+        if cfg!(debug_assertions) {
+            LogFormat::Pretty
+        } else {
+            LogFormat::Json
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RepositorySettings {
+    pub kind: RepositoryKind,
+    pub sqlite_url: String,
+    /// The memory repository only keeps the films in memory when unset.
+    pub memory_file: Option<PathBuf>,
+    /// Whether to load the bundled fixtures on startup.
+    pub seed: bool,
+    pub cache_capacity: usize,
+    pub cache_ttl_seconds: u64,
+    /// How far the read replicas may lag behind the primary. What's read that
+    /// soon after a change isn't cached.
+    pub cache_replica_lag_seconds: u64,
+}
+
+impl Default for RepositorySettings {
+    fn default() -> Self {
+        Self {
+            kind: RepositoryKind::default(),
+            sqlite_url: "sqlite://films.db".to_string(),
+            memory_file: None,
+            seed: false,
+            cache_capacity: 1000,
+            cache_ttl_seconds: 30,
+            cache_replica_lag_seconds: 5,
+        }
+    }
+}
+
+impl RepositorySettings {
+    pub fn cache_ttl(&self) -> Duration {
+        Duration::from_secs(self.cache_ttl_seconds)
+    }
+
+    pub fn cache_replica_lag(&self) -> Duration {
+        Duration::from_secs(self.cache_replica_lag_seconds)
+    }
+}
+
+impl Settings {
+    /// Loads the settings from `SETTINGS_FILE` (or [`DEFAULT_SETTINGS_FILE`])
+    /// and the environment, and checks they make sense.
+    pub fn load() -> Result<Self, SettingsError> {
+        let settings = Self::read()?;
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    /// Like [`Settings::load`], without the validation, for callers that fill
+    /// in some settings themselves.
+    pub fn read() -> Result<Self, SettingsError> {
+        Self::read_with_env(std::env::vars())
+    }
+
+    /// Like [`Settings::read`], with the secrets `secret` finds by variable
+    /// name, like `WEBHOOKS_SECRET_KEY`, overriding the environment. For the
+    /// deployments that can't set variables, like Shuttle.
+    pub fn read_with_secrets(
+        secret: impl Fn(&str) -> Option<String>,
+    ) -> Result<Self, SettingsError> {
+        Self::read_with_env(std::env::vars().chain(secrets(secret)))
+    }
+
+    fn read_with_env(
+        env: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<Self, SettingsError> {
+        let file =
+            std::env::var("SETTINGS_FILE").unwrap_or_else(|_| DEFAULT_SETTINGS_FILE.to_string());
+        Self::from_sources(Some(Path::new(&file)), Some(&database::config_file()), env)
+    }
+
+    /// Reads `file`, when it exists, and then the overrides found in `env`,
+    /// before layering `database_file` and the `DATABASE_*` variables on the
+    /// database settings. Empty variables are ignored.
+    pub fn from_sources(
+        file: Option<&Path>,
+        database_file: Option<&Path>,
+        env: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<Self, SettingsError> {
+        let env: Vec<(String, String)> = env.into_iter().collect();
+        // nested sections are merged key by key, so a file or variable setting
+        // a single key keeps the defaults of the others
+        let defaults = Config::try_from(&Settings::default()).map_err(SettingsError::Load)?;
+        let mut builder = Config::builder().add_source(defaults);
+        if let Some(file) = file {
+            builder = builder.add_source(File::from(file).format(FileFormat::Toml).required(false));
+        }
+        for (name, value) in &env {
+            let Some(key) = env_key(name) else {
+                continue;
+            };
+            let value = value.trim();
+            if value.is_empty() {
+                continue;
+            }
+            builder = if LIST_SETTINGS.contains(&key.as_str()) {
+                let values: Vec<&str> = value.split(',').map(str::trim).collect();
+                builder.set_override(key, values)
+            } else {
+                builder.set_override(key, value)
+            }
+            .map_err(SettingsError::Load)?;
+        }
+        let mut settings: Settings = builder
+            .build()
+            .and_then(Config::try_deserialize)
+            .map_err(SettingsError::Load)?;
+        settings.database = settings
+            .database
+            .layered(database_file, Some(env.into_iter().collect()))
+            .map_err(SettingsError::Database)?;
+        Ok(settings)
+    }
+
+    /// Checks the settings that would otherwise only fail once in use.
+    pub fn validate(&self) -> Result<(), SettingsError> {
+        let mut problems = Vec::new();
+
+        let host = &self.server.host;
+        let is_hostname = !host.is_empty()
+            && host
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-');
+        if host.parse::<IpAddr>().is_err() && !is_hostname {
+            problems.push(format!(
+                "server.host (HOST): `{}` isn't an IP address or a host name",
+                host
+            ));
+        }
+        if self.server.port != 0 && self.server.port == self.server.grpc_port {
+            problems.push(format!(
+                "server.grpc_port (GRPC_PORT): {} is already used by server.port",
+                self.server.grpc_port
+            ));
+        }
+
+        problems.extend(self.cors.problems());
+        problems.extend(self.rate_limit.problems());
+        problems.extend(self.security_headers.problems());
+        problems.extend(self.deprecation.problems());
+        problems.extend(self.telemetry.problems());
+        problems.extend(self.webhooks.problems());
+
+        match self.repository.kind {
+            RepositoryKind::Postgres if self.database.url.is_none() => problems.push(
+                "database.url (DATABASE_URL) is required by the postgres repository".to_string(),
+            ),
+            RepositoryKind::Sqlite if !self.repository.sqlite_url.starts_with("sqlite:") => {
+                problems.push(format!(
+                    "repository.sqlite_url (SQLITE_URL): `{}` should start with sqlite:",
+                    self.repository.sqlite_url
+                ))
+            }
+            _ => {}
+        }
+        if self.repository.kind == RepositoryKind::Postgres && self.webhooks.secret_key.is_none() {
+            problems.push(
+                "webhooks.secret_key (WEBHOOKS_SECRET_KEY) is required by the postgres repository"
+                    .to_string(),
+            );
+        }
+        if self.repository.cache_capacity == 0 {
+            problems
+                .push("repository.cache_capacity (CACHE_CAPACITY) must be at least 1".to_string());
+        }
+
+        if self.database.max_connections == 0 {
+            problems.push(
+                "database.max_connections (DATABASE_MAX_CONNECTIONS) must be at least 1"
+                    .to_string(),
+            );
+        } else if self.database.min_connections > self.database.max_connections {
+            problems.push(format!(
+                "database.min_connections (DATABASE_MIN_CONNECTIONS): {} is more than database.max_connections ({})",
+                self.database.min_connections, self.database.max_connections
+            ));
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(SettingsError::Invalid(problems))
+        }
+    }
+}
+
+/// The setting an environment variable overrides, if any. The `DATABASE_*`
+/// ones are left to [`DatabaseSettings::layered`].
+fn env_key(name: &str) -> Option<String> {
+    ENV_OVERRIDES
+        .iter()
+        .find(|(env, _)| *env == name)
+        .map(|(_, key)| key.to_string())
+}
+
+/// The variables of [`ENV_OVERRIDES`] `secret` has a value for.
+fn secrets(secret: impl Fn(&str) -> Option<String>) -> Vec<(String, String)> {
+    ENV_OVERRIDES
+        .iter()
+        .filter_map(|(env, _)| secret(env).map(|value| (env.to_string(), value)))
+        .collect()
+}
+
+#[derive(Debug)]
+pub enum SettingsError {
+    /// The settings file or environment couldn't be read.
+    Load(config::ConfigError),
+    /// The database settings file or `DATABASE_*` variables couldn't be read.
+    Database(DatabaseError),
+    /// Every setting that doesn't make sense.
+    Invalid(Vec<String>),
+}
+
+impl fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SettingsError::Load(e) => write!(f, "Couldn't read the settings: {}", e),
+            SettingsError::Database(e) => write!(f, "Couldn't read the settings: {}", e),
+            SettingsError::Invalid(problems) => {
+                write!(f, "Invalid settings:")?;
+                for problem in problems {
+                    write!(f, "\n  - {}", problem)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for SettingsError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SettingsError::Load(e) => Some(e),
+            SettingsError::Database(e) => Some(e),
+            SettingsError::Invalid(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::SslMode;
+
+    fn env(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    fn settings_file(contents: &str) -> PathBuf {
+        let file = std::env::temp_dir().join(format!("{}.toml", uuid::Uuid::new_v4()));
+        std::fs::write(&file, contents).unwrap();
+        file
+    }
+
+    #[test]
+    fn defaults_are_used_without_a_file_or_env() {
+        let settings = Settings::from_sources(None, None, env(&[])).unwrap();
+
+        assert_eq!(settings, Settings::default());
+        assert_eq!(settings.server.address(), "127.0.0.1:8080");
+    }
+
+    #[test]
+    fn a_missing_file_is_ignored() {
+        let file = std::env::temp_dir().join("no-such-settings.toml");
+
+        let settings = Settings::from_sources(Some(&file), None, env(&[])).unwrap();
+
+        assert_eq!(settings, Settings::default());
+    }
+
+    #[test]
+    fn env_overrides_the_file() {
+        let file = settings_file(
+            r#"
+[server]
+host = "0.0.0.0"
+port = 3000
+
+[repository]
+kind = "sqlite"
+cache_ttl_seconds = 5
+
+[database]
+url = "postgres://file/films"
+max_connections = 20
+ssl_mode = "require"
+"#,
+        );
+
+        let settings = Settings::from_sources(
+            Some(&file),
+            None,
+            env(&[
+                ("PORT", "4000"),
+                ("REPOSITORY", "Memory"),
+                ("MEMORY_FILE", ""),
+                ("SEED_DATABASE", "true"),
+                (
+                    "CORS_ORIGINS",
+                    "https://a.example.com, https://b.example.com",
+                ),
+                ("DATABASE_MAX_CONNECTIONS", "5"),
+                (
+                    "DATABASE_REPLICA_URLS",
+                    "postgres://a/films,postgres://b/films",
+                ),
+                ("UNRELATED", "ignored"),
+            ]),
+        )
+        .unwrap();
+        std::fs::remove_file(file).unwrap();
+
+        assert_eq!(settings.server.address(), "0.0.0.0:4000");
+        assert_eq!(settings.repository.kind, RepositoryKind::Memory);
+        assert_eq!(settings.repository.memory_file, None);
+        assert!(settings.repository.seed);
+        assert_eq!(settings.repository.cache_ttl(), Duration::from_secs(5));
+        assert_eq!(
+            settings.cors.allowed_origins,
+            vec!["https://a.example.com", "https://b.example.com"]
+        );
+        assert_eq!(
+            settings.database.url.as_deref(),
+            Some("postgres://file/films")
+        );
+        assert_eq!(settings.database.max_connections, 5);
+        assert_eq!(settings.database.ssl_mode, SslMode::Require);
+        assert_eq!(
+            settings.database.replica_urls,
+            vec!["postgres://a/films", "postgres://b/films"]
+        );
+    }
+
+    #[test]
+    fn secrets_override_the_env() {
+        let secrets = secrets(|name| match name {
+            "WEBHOOKS_SECRET_KEY" => Some("from-the-secrets".to_string()),
+            "SEED_DATABASE" => Some("true".to_string()),
+            _ => None,
+        });
+        let vars = env(&[("WEBHOOKS_SECRET_KEY", "from-the-env"), ("PORT", "3000")]);
+
+        let settings = Settings::from_sources(None, None, vars.into_iter().chain(secrets)).unwrap();
+
+        assert_eq!(
+            settings.webhooks.secret_key.as_deref(),
+            Some("from-the-secrets")
+        );
+        assert!(settings.repository.seed);
+        assert_eq!(settings.server.port, 3000);
+    }
+
+    #[test]
+    fn nested_sections_keep_the_defaults_of_unset_keys() {
+        let settings =
+            Settings::from_sources(None, None, env(&[("RATE_LIMIT_WRITES_BURST", "2")])).unwrap();
+
+        assert_eq!(settings.rate_limit.writes.burst, 2);
+        assert_eq!(
+            settings.rate_limit.writes.per_minute,
+            RateLimitSettings::default().writes.per_minute
+        );
+    }
+
+    #[test]
+    fn the_database_file_sits_between_the_settings_file_and_the_env() {
+        let file = settings_file(
+            r#"
+[database]
+url = "postgres://settings/films"
+max_connections = 20
+min_connections = 2
+"#,
+        );
+        let database_file = settings_file(
+            r#"
+url = "postgres://database/films"
+max_connections = 15
+"#,
+        );
+
+        let settings = Settings::from_sources(
+            Some(&file),
+            Some(&database_file),
+            env(&[("DATABASE_MAX_CONNECTIONS", "5")]),
+        )
+        .unwrap();
+        std::fs::remove_file(file).unwrap();
+        std::fs::remove_file(database_file).unwrap();
+
+        assert_eq!(
+            settings.database.url.as_deref(),
+            Some("postgres://database/films")
+        );
+        assert_eq!(settings.database.min_connections, 2);
+        assert_eq!(settings.database.max_connections, 5);
+    }
+
+    #[test]
+    fn values_of_the_wrong_type_are_reported() {
+        let result = Settings::from_sources(None, None, env(&[("PORT", "eighty")]));
+
+        assert!(matches!(result, Err(SettingsError::Load(_))));
+    }
+
+    #[test]
+    fn unknown_repositories_are_reported() {
+        let error =
+            Settings::from_sources(None, None, env(&[("REPOSITORY", "mongo")])).unwrap_err();
+
+        assert!(error.to_string().contains("Unknown repository: mongo"));
+    }
+
+    #[test]
+    fn valid_settings_pass_validation() {
+        let settings = Settings::from_sources(
+            None,
+            None,
+            env(&[
+                ("DATABASE_URL", "postgres://localhost/films"),
+                (
+                    "WEBHOOKS_SECRET_KEY",
+                    "MDEyMzQ1Njc4OWFiY2RlZjAxMjM0NTY3ODlhYmNkZWY=",
+                ),
+                (
+                    "CORS_ORIGINS",
+                    "https://films.example.com,http://localhost:8080",
+                ),
+            ]),
+        )
+        .unwrap();
+
+        assert!(settings.validate().is_ok());
+    }
+
+    #[test]
+    fn validation_reports_every_problem() {
+        let settings = Settings::from_sources(
+            None,
+            None,
+            env(&[
+                ("HOST", "not a host"),
+                ("PORT", "9000"),
+                ("GRPC_PORT", "9000"),
+                ("CORS_ORIGINS", "films.example.com"),
+                ("DATABASE_MIN_CONNECTIONS", "20"),
+            ]),
+        )
+        .unwrap();
+
+        match settings.validate() {
+            Err(SettingsError::Invalid(problems)) => {
+                assert_eq!(problems.len(), 6, "{:?}", problems);
+                assert!(problems[0].starts_with("server.host (HOST)"));
+                assert!(problems[1].starts_with("server.grpc_port (GRPC_PORT)"));
+                assert!(problems[2].starts_with("cors.allowed_origins (CORS_ORIGINS)"));
+                assert!(problems[3].starts_with("database.url (DATABASE_URL)"));
+                assert!(problems[4].starts_with("webhooks.secret_key (WEBHOOKS_SECRET_KEY)"));
+                assert!(problems[5].starts_with("database.min_connections"));
+            }
+            result => panic!("expected invalid settings, got {:?}", result),
+        }
+    }
+}
@@ -0,0 +1,440 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use actix_files::{Files, NamedFile};
+use actix_web::{
+    dev::{fn_service, Service, ServiceRequest, ServiceResponse},
+    http::{
+        header::{
+            AcceptEncoding, CacheControl, CacheDirective, ContentEncoding, Encoding, HeaderValue,
+            TryIntoHeaderValue, CACHE_CONTROL, VARY,
+        },
+        Method,
+    },
+    middleware::Compress,
+    web::{self, ServiceConfig},
+    HttpMessage, HttpResponse,
+};
+use futures_util::future::LocalBoxFuture;
+
+const INDEX: &str = "index.html";
+
+/// A year, as long as a file with its hash in its name can be cached.
+const IMMUTABLE_MAX_AGE: u32 = 365 * 24 * 60 * 60;
+
+/// What the dx CLI puts between a file's name and its content hash, like
+/// `main-dxh3f2a9c1b0d4e5f6a.css`.
+const HASH_PREFIX: &str = "-dxh";
+
+/// The extensions of the files the front end is made of. The other paths
+/// that aren't files are routes of the front end, even with a dot in them.
+const ASSET_EXTENSIONS: &[&str] = &[
+    "js",
+    "mjs",
+    "wasm",
+    "css",
+    "map",
+    "json",
+    "html",
+    "txt",
+    "xml",
+    "webmanifest",
+    "ico",
+    "png",
+    "jpg",
+    "jpeg",
+    "gif",
+    "svg",
+    "webp",
+    "avif",
+    "woff",
+    "woff2",
+    "ttf",
+    "otf",
+    "br",
+    "gz",
+];
+
+/// The precompressed variants looked for next to every file, best first.
+const PRECOMPRESSED: [(&str, ContentEncoding); 2] = [
+    ("br", ContentEncoding::Brotli),
+    ("gz", ContentEncoding::Gzip),
+];
+
+/// Serves the front end in `folder`, without listing its directories:
+///
+/// - `file.br` or `file.gz` instead of `file` when the client accepts it,
+///   and the other files compressed on the fly. The variants are looked for
+///   once, when the service is configured,
+/// - with files whose name has a content hash cached for good, and the
+///   others revalidated every time,
+/// - and `index.html` for the paths that aren't files and don't look like
+///   one, which are routes of the front end.
+///
+/// Register it last, it answers every path.
+pub fn service(folder: impl Into<PathBuf>) -> impl FnOnce(&mut ServiceConfig) {
+    let folder = folder.into();
+    let variants = Arc::new(precompressed_variants(&folder));
+    move |cfg| {
+        let files = Files::new("/", &folder)
+            .index_file(INDEX)
+            .default_handler(spa_fallback(folder.join(INDEX)));
+        cfg.service(
+            web::scope("")
+                .wrap_fn(move |req, srv| {
+                    let res: LocalBoxFuture<_> = match precompressed(&folder, &variants, &req) {
+                        Some((path, encoding)) => {
+                            Box::pin(serve_precompressed(req, path, encoding))
+                        }
+                        None => Box::pin(srv.call(req)),
+                    };
+                    async move {
+                        let mut res = res.await?;
+                        if res.status().is_success() {
+                            let cache_control = cache_control(res.request().path());
+                            if let Ok(value) = cache_control.try_into_value() {
+                                res.headers_mut().insert(CACHE_CONTROL, value);
+                            }
+                        }
+                        Ok(res)
+                    }
+                })
+                // leaves the precompressed files alone
+                .wrap(Compress::default())
+                .service(files),
+        );
+    }
+}
+
+/// `index.html` for the routes of the front end, a 404 for missing assets.
+fn spa_fallback(
+    index: PathBuf,
+) -> impl actix_web::dev::ServiceFactory<
+    ServiceRequest,
+    Config = (),
+    Response = ServiceResponse,
+    Error = actix_web::Error,
+    InitError = (),
+> {
+    fn_service(move |req: ServiceRequest| {
+        let index = index.clone();
+        async move {
+            let (req, _) = req.into_parts();
+            let is_route = matches!(*req.method(), Method::GET | Method::HEAD)
+                && !is_asset(file_name(req.path()));
+            let res = if is_route {
+                NamedFile::open_async(index).await?.into_response(&req)
+            } else {
+                HttpResponse::NotFound().finish()
+            };
+            Ok(ServiceResponse::new(req, res))
+        }
+    })
+}
+
+async fn serve_precompressed(
+    req: ServiceRequest,
+    path: PathBuf,
+    encoding: ContentEncoding,
+) -> Result<ServiceResponse, actix_web::Error> {
+    let (req, _) = req.into_parts();
+    // typed after the original file, not the `.br` or `.gz` one
+    let ext = Path::new(req.path())
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or_default();
+    let mut res = NamedFile::open_async(path)
+        .await?
+        .set_content_type(actix_files::file_extension_to_mime(ext))
+        .set_content_encoding(encoding)
+        .disable_content_disposition()
+        .into_response(&req);
+    res.headers_mut()
+        .insert(VARY, HeaderValue::from_static("accept-encoding"));
+    Ok(ServiceResponse::new(req, res))
+}
+
+/// The paths of the precompressed files in `folder`, relative to it.
+fn precompressed_variants(folder: &Path) -> HashSet<PathBuf> {
+    let mut variants = HashSet::new();
+    let mut dirs = vec![folder.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            match entry.file_type() {
+                Ok(file_type) if file_type.is_dir() => dirs.push(path),
+                Ok(_)
+                    if PRECOMPRESSED
+                        .iter()
+                        .any(|(ext, _)| path.extension() == Some(ext.as_ref())) =>
+                {
+                    if let Ok(relative) = path.strip_prefix(folder) {
+                        variants.insert(relative.to_path_buf());
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    variants
+}
+
+/// The precompressed variant of the requested file the client prefers, if
+/// there's one among `variants`.
+fn precompressed(
+    folder: &Path,
+    variants: &HashSet<PathBuf>,
+    req: &ServiceRequest,
+) -> Option<(PathBuf, ContentEncoding)> {
+    if !matches!(*req.method(), Method::GET | Method::HEAD) {
+        return None;
+    }
+    let relative = relative_path(req.path())?;
+    let available: Vec<_> = PRECOMPRESSED
+        .iter()
+        .filter_map(|(ext, encoding)| {
+            let variant = PathBuf::from(format!("{}.{}", relative.display(), ext));
+            variants
+                .contains(&variant)
+                .then(|| (folder.join(variant), *encoding))
+        })
+        .collect();
+    if available.is_empty() {
+        return None;
+    }
+
+    let accepted = req.get_header::<AcceptEncoding>()?;
+    let supported: Vec<Encoding> = available
+        .iter()
+        .map(|(_, encoding)| Encoding::Known(*encoding))
+        .chain(std::iter::once(Encoding::identity()))
+        .collect();
+    let chosen = accepted.negotiate(supported.iter())?;
+    available
+        .into_iter()
+        .find(|(_, encoding)| chosen == Encoding::Known(*encoding))
+}
+
+/// The path of the requested file in the front end folder, unless it could
+/// lead out of it. Escaped paths are left to [`Files`].
+fn relative_path(path: &str) -> Option<PathBuf> {
+    let path = path.trim_start_matches('/');
+    let path = if path.is_empty() { INDEX } else { path };
+    let safe = !path.ends_with('/')
+        && !path.contains(['%', '\\'])
+        && path
+            .split('/')
+            .all(|segment| !segment.is_empty() && !segment.starts_with('.'));
+    safe.then(|| PathBuf::from(path))
+}
+
+fn file_name(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or_default()
+}
+
+/// Whether the file name has a known asset extension.
+fn is_asset(file_name: &str) -> bool {
+    Path::new(file_name)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| {
+            ASSET_EXTENSIONS
+                .iter()
+                .any(|known| ext.eq_ignore_ascii_case(known))
+        })
+}
+
+/// Whether the dx CLI put a content hash in the file name, like
+/// `main-dxh3f2a9c1b0d4e5f6a.css`, so its content never changes.
+fn is_hashed(file_name: &str) -> bool {
+    let Some((stem, _)) = file_name.split_once('.') else {
+        return false;
+    };
+    stem.rsplit_once(HASH_PREFIX).is_some_and(|(name, hash)| {
+        !name.is_empty()
+            && (1..=16).contains(&hash.len())
+            && hash
+                .chars()
+                .all(|c| c.is_ascii_digit() || ('a'..='f').contains(&c))
+    })
+}
+
+fn cache_control(path: &str) -> CacheControl {
+    if is_hashed(file_name(path)) {
+        CacheControl(vec![
+            CacheDirective::Public,
+            CacheDirective::MaxAge(IMMUTABLE_MAX_AGE),
+            CacheDirective::Extension("immutable".to_string(), None),
+        ])
+    } else {
+        // cached, but checked with the server before every use
+        CacheControl(vec![CacheDirective::NoCache])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use actix_web::http::{header, StatusCode};
+
+    fn front_end() -> PathBuf {
+        let folder = std::env::temp_dir().join(format!("front-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(folder.join("assets")).unwrap();
+        std::fs::write(folder.join(INDEX), "<html>films</html>").unwrap();
+        std::fs::write(
+            folder.join("assets/app-dxh3f2a9c1b0d4e5f6a.js"),
+            "console.log(1)",
+        )
+        .unwrap();
+        std::fs::write(
+            folder.join("assets/app-dxh3f2a9c1b0d4e5f6a.js.br"),
+            "brotli",
+        )
+        .unwrap();
+        std::fs::write(folder.join("assets/app-dxh3f2a9c1b0d4e5f6a.js.gz"), "gzip").unwrap();
+        std::fs::write(folder.join("assets/style.css"), "body {}").unwrap();
+        folder
+    }
+
+    macro_rules! get {
+        ($app:expr, $uri:expr) => {
+            get!($app, $uri, None::<&str>)
+        };
+        ($app:expr, $uri:expr, $accept_encoding:expr) => {{
+            let mut req = actix_web::test::TestRequest::get().uri($uri);
+            if let Some(encoding) = $accept_encoding {
+                req = req.insert_header((header::ACCEPT_ENCODING, encoding));
+            }
+            actix_web::test::call_service(&$app, req.to_request()).await
+        }};
+    }
+
+    #[actix_rt::test]
+    async fn precompressed_files_are_served_when_accepted() {
+        let app = testing::app(service(front_end())).await;
+
+        for (accept_encoding, encoding, body) in [
+            (Some("gzip, br"), Some("br"), "brotli"),
+            (Some("gzip"), Some("gzip"), "gzip"),
+            (None, None, "console.log(1)"),
+        ] {
+            let res = get!(app, "/assets/app-dxh3f2a9c1b0d4e5f6a.js", accept_encoding);
+
+            assert_eq!(res.status(), StatusCode::OK);
+            assert_eq!(
+                res.headers()
+                    .get(header::CONTENT_ENCODING)
+                    .map(|value| value.to_str().unwrap()),
+                encoding
+            );
+            let content_type = res.headers().get(header::CONTENT_TYPE).unwrap();
+            assert!(content_type
+                .to_str()
+                .unwrap()
+                .starts_with("text/javascript"));
+            assert_eq!(actix_web::test::read_body(res).await, body);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn hashed_files_are_cached_for_good() {
+        let app = testing::app(service(front_end())).await;
+
+        let hashed = get!(app, "/assets/app-dxh3f2a9c1b0d4e5f6a.js");
+        let not_hashed = get!(app, "/assets/style.css");
+
+        assert_eq!(
+            hashed.headers().get(header::CACHE_CONTROL).unwrap(),
+            "public, max-age=31536000, immutable"
+        );
+        assert_eq!(
+            not_hashed.headers().get(header::CACHE_CONTROL).unwrap(),
+            "no-cache"
+        );
+    }
+
+    #[actix_rt::test]
+    async fn routes_of_the_front_end_get_the_index() {
+        let app = testing::app(service(front_end())).await;
+
+        let route = get!(app, "/films/42");
+        let dotted_route = get!(app, "/directors/j.r.r.tolkien");
+        let missing_file = get!(app, "/assets/missing.js");
+        // a directory without an index isn't listed
+        let directory = get!(app, "/assets/");
+
+        assert_eq!(route.status(), StatusCode::OK);
+        assert_eq!(
+            actix_web::test::read_body(route).await,
+            "<html>films</html>"
+        );
+        assert_eq!(
+            actix_web::test::read_body(dotted_route).await,
+            "<html>films</html>"
+        );
+        assert_eq!(missing_file.status(), StatusCode::NOT_FOUND);
+        assert_eq!(
+            actix_web::test::read_body(directory).await,
+            "<html>films</html>"
+        );
+    }
+
+    #[test]
+    fn hashes_are_recognized() {
+        assert!(is_hashed("app-dxh3f2a9c1b0d4e5f6a.js"));
+        assert!(is_hashed("rusty-films_bg-dxh0123abcd.wasm"));
+        assert!(is_hashed("tailwind-dxh3f2a9c1b0d4e5f6a.css.br"));
+        assert!(!is_hashed("rusty-films_bg.wasm"));
+        assert!(!is_hashed("index.html"));
+        assert!(!is_hashed("-dxh3f2a9c1b.js"));
+        // not the dx CLI's, or not a hash at all
+        assert!(!is_hashed("poster-2024-12345678.png"));
+        assert!(!is_hashed("main.3f2a9c1be.css"));
+        assert!(!is_hashed("app-dxhcoffee.js"));
+        assert!(!is_hashed("app-dxh3F2A9C1B.js"));
+    }
+
+    #[test]
+    fn assets_are_told_from_routes() {
+        assert!(is_asset("app.js"));
+        assert!(is_asset("FERRIS.PNG"));
+        assert!(!is_asset("42"));
+        assert!(!is_asset("j.r.r.tolkien"));
+    }
+
+    #[test]
+    fn precompressed_variants_are_listed() {
+        let folder = front_end();
+
+        let variants = precompressed_variants(&folder);
+
+        assert_eq!(
+            variants,
+            HashSet::from([
+                PathBuf::from("assets/app-dxh3f2a9c1b0d4e5f6a.js.br"),
+                PathBuf::from("assets/app-dxh3f2a9c1b0d4e5f6a.js.gz"),
+            ])
+        );
+        assert!(precompressed_variants(&folder.join("missing")).is_empty());
+    }
+
+    #[test]
+    fn paths_out_of_the_folder_are_rejected() {
+        assert_eq!(relative_path("/"), Some(PathBuf::from(INDEX)));
+        assert_eq!(
+            relative_path("/assets/app.js"),
+            Some(PathBuf::from("assets/app.js"))
+        );
+        assert_eq!(relative_path("/../secret"), None);
+        assert_eq!(relative_path("/assets/%2e%2e/secret"), None);
+        assert_eq!(relative_path("/.env"), None);
+        assert_eq!(relative_path("/assets/"), None);
+    }
+}
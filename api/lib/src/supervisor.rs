@@ -0,0 +1,189 @@
+use std::{future::Future, time::Duration};
+
+use futures_util::future::BoxFuture;
+use tokio::{sync::watch, task::JoinHandle, time::Instant};
+
+type Close = Box<dyn FnOnce() -> BoxFuture<'static, ()> + Send>;
+
+/// Tells the background workers when to stop.
+#[derive(Clone)]
+pub struct Shutdown(watch::Receiver<bool>);
+
+impl Shutdown {
+    /// A signal that never fires, for workers running until the process
+    /// exits.
+    pub fn never() -> Self {
+        Self(watch::channel(false).1)
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        *self.0.borrow()
+    }
+
+    /// Waits until the [`Supervisor`] shuts down. Never returns when it was
+    /// dropped instead, its workers keep running on their own.
+    pub async fn wait(&mut self) {
+        if self.0.wait_for(|shutdown| *shutdown).await.is_err() {
+            std::future::pending::<()>().await;
+        }
+    }
+}
+
+/// Starts the background workers (webhooks, gRPC, ...) and stops them, then
+/// closes the database pools, once the API shuts down.
+pub struct Supervisor {
+    shutdown: watch::Sender<bool>,
+    tasks: Vec<(&'static str, JoinHandle<()>)>,
+    pools: Vec<(String, Close)>,
+}
+
+impl Default for Supervisor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Supervisor {
+    pub fn new() -> Self {
+        Self {
+            shutdown: watch::channel(false).0,
+            tasks: Vec::new(),
+            pools: Vec::new(),
+        }
+    }
+
+    pub fn subscribe(&self) -> Shutdown {
+        Shutdown(self.shutdown.subscribe())
+    }
+
+    /// Runs `worker` in its own task. It's expected to return soon after
+    /// its [`Shutdown`] fires.
+    pub fn spawn<F, Fut>(&mut self, name: &'static str, worker: F)
+    where
+        F: FnOnce(Shutdown) -> Fut,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let task = tokio::spawn(worker(self.subscribe()));
+        tracing::debug!("Started the {} worker", name);
+        self.tasks.push((name, task));
+    }
+
+    /// Closes `pool` once every worker stopped.
+    pub fn close_on_shutdown<DB: sqlx::Database>(&mut self, name: &str, pool: sqlx::Pool<DB>) {
+        let close: Close = Box::new(move || Box::pin(async move { pool.close().await }));
+        self.pools.push((name.to_string(), close));
+    }
+
+    /// Tells every worker to stop and waits for them, aborting the ones still
+    /// running after `timeout`. Then closes the pools.
+    pub async fn shutdown(self, timeout: Duration) {
+        let _ = self.shutdown.send(true);
+        let deadline = Instant::now() + timeout;
+        for (name, mut task) in self.tasks {
+            match tokio::time::timeout_at(deadline, &mut task).await {
+                Ok(Ok(())) => tracing::info!("Stopped the {} worker", name),
+                Ok(Err(e)) => tracing::error!("The {} worker failed: {}", name, e),
+                Err(_) => {
+                    tracing::warn!(
+                        "The {} worker didn't stop within {:?}, aborting it",
+                        name,
+                        timeout
+                    );
+                    task.abort();
+                }
+            }
+        }
+        for (name, close) in self.pools {
+            close().await;
+            tracing::info!("Closed the {} database pool", name);
+        }
+    }
+}
+
+/// Waits for SIGTERM, as sent by orchestrators, or Ctrl-C.
+pub async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = terminate.recv() => {}
+                    _ = tokio::signal::ctrl_c() => {}
+                }
+                return;
+            }
+            Err(e) => tracing::warn!("Couldn't listen for SIGTERM: {}", e),
+        }
+    }
+    if let Err(e) = tokio::signal::ctrl_c().await {
+        tracing::error!("Couldn't listen for Ctrl-C: {}", e);
+        std::future::pending::<()>().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    };
+
+    use super::*;
+
+    #[actix_rt::test]
+    async fn workers_stop_on_shutdown() {
+        let mut supervisor = Supervisor::new();
+        let stopped = Arc::new(AtomicBool::new(false));
+        let worker_stopped = stopped.clone();
+        supervisor.spawn("test", |mut shutdown| async move {
+            shutdown.wait().await;
+            worker_stopped.store(true, Ordering::SeqCst);
+        });
+
+        supervisor.shutdown(Duration::from_secs(1)).await;
+
+        assert!(stopped.load(Ordering::SeqCst));
+    }
+
+    #[actix_rt::test]
+    async fn stuck_workers_are_aborted() {
+        let mut supervisor = Supervisor::new();
+        supervisor.spawn("stuck", |_| std::future::pending());
+        let start = std::time::Instant::now();
+
+        supervisor.shutdown(Duration::from_millis(50)).await;
+
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[actix_rt::test]
+    async fn pools_are_closed_after_the_workers() {
+        let mut supervisor = Supervisor::new();
+        let pool = sqlx::SqlitePool::connect("sqlite::memory:").await.unwrap();
+        let worker_pool = pool.clone();
+        supervisor.spawn("queries", |mut shutdown| async move {
+            shutdown.wait().await;
+            // still open while the workers drain
+            sqlx::query("SELECT 1").execute(&worker_pool).await.unwrap();
+        });
+        supervisor.close_on_shutdown("sqlite", pool.clone());
+
+        supervisor.shutdown(Duration::from_secs(1)).await;
+
+        assert!(pool.is_closed());
+    }
+
+    #[actix_rt::test]
+    async fn dropping_the_supervisor_detaches_its_workers() {
+        let supervisor = Supervisor::new();
+        let mut shutdown = supervisor.subscribe();
+        drop(supervisor);
+
+        let waited = tokio::time::timeout(Duration::from_millis(20), shutdown.wait()).await;
+
+        assert!(waited.is_err());
+        assert!(!shutdown.is_shutting_down());
+    }
+}
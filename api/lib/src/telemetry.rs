@@ -0,0 +1,237 @@
+use std::collections::HashMap;
+
+use actix_web::{
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    http::header::HeaderMap,
+    middleware::Next,
+    Error,
+};
+use opentelemetry::{
+    propagation::{Extractor, TextMapPropagator},
+    trace::{TraceError, TracerProvider as _},
+    KeyValue,
+};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{
+    propagation::TraceContextPropagator,
+    runtime,
+    trace::{Tracer, TracerProvider},
+    Resource,
+};
+use serde::{Deserialize, Serialize};
+use tracing::{field::Empty, Instrument, Span};
+use tracing_opentelemetry::{OpenTelemetryLayer, OpenTelemetrySpanExt};
+use tracing_subscriber::registry::LookupSpan;
+
+use crate::settings::Validate;
+
+/// The `[telemetry]` section of the [`Settings`](crate::settings::Settings).
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct TelemetrySettings {
+    /// Where the spans are exported with OTLP over gRPC, e.g. a local
+    /// collector at `http://localhost:4317`. Nothing is exported when unset.
+    pub otlp_endpoint: Option<String>,
+    /// The `service.name` of the exported spans.
+    pub service_name: String,
+}
+
+impl Default for TelemetrySettings {
+    fn default() -> Self {
+        Self {
+            otlp_endpoint: None,
+            service_name: "api".to_string(),
+        }
+    }
+}
+
+impl Validate for TelemetrySettings {
+    fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if let Some(endpoint) = &self.otlp_endpoint {
+            if !endpoint.starts_with("http://") && !endpoint.starts_with("https://") {
+                problems.push(format!(
+                    "telemetry.otlp_endpoint (OTEL_EXPORTER_OTLP_ENDPOINT): `{}` should start with http:// or https://",
+                    endpoint
+                ));
+            }
+        }
+        if self.service_name.trim().is_empty() {
+            problems.push("telemetry.service_name (OTEL_SERVICE_NAME) can't be empty".to_string());
+        }
+        problems
+    }
+}
+
+/// Exports spans in batches to the OTLP endpoint, if there's one. Shut it
+/// down before exiting so the last batch isn't lost.
+pub fn tracer_provider(settings: &TelemetrySettings) -> Result<Option<TracerProvider>, TraceError> {
+    let Some(endpoint) = &settings.otlp_endpoint else {
+        return Ok(None);
+    };
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_tonic()
+        .with_endpoint(endpoint)
+        .build()?;
+    let provider = TracerProvider::builder()
+        .with_batch_exporter(exporter, runtime::TokioCurrentThread)
+        .with_resource(Resource::new([KeyValue::new(
+            "service.name",
+            settings.service_name.clone(),
+        )]))
+        .build();
+    Ok(Some(provider))
+}
+
+/// The `tracing` layer sending spans to `provider`.
+pub fn layer<S>(provider: &TracerProvider) -> OpenTelemetryLayer<S, Tracer>
+where
+    S: tracing::Subscriber + for<'span> LookupSpan<'span>,
+{
+    tracing_opentelemetry::layer().with_tracer(provider.tracer(env!("CARGO_PKG_NAME")))
+}
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|name| name.as_str()).collect()
+    }
+}
+
+/// The W3C `traceparent` and `tracestate` headers continuing `span` in
+/// another service. Empty unless the spans are exported.
+pub fn trace_headers(span: &Span) -> HashMap<String, String> {
+    let mut headers = HashMap::new();
+    TraceContextPropagator::new().inject_context(&span.context(), &mut headers);
+    headers
+}
+
+/// Middleware running every request in a span, continuing the trace of the
+/// caller when it sends a W3C `traceparent` header.
+pub async fn trace_requests<B: MessageBody>(
+    req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<B>, Error> {
+    let method = req.method().to_string();
+    // raw paths would make a span name per film
+    let route = req.match_pattern();
+    let name = match &route {
+        Some(route) => format!("{} {}", method, route),
+        None => method.clone(),
+    };
+    let span = tracing::info_span!(
+        "HTTP request",
+        otel.name = %name,
+        otel.kind = "server",
+        otel.status_code = Empty,
+        http.request.method = %method,
+        http.route = route.as_deref(),
+        url.path = %req.path(),
+        http.response.status_code = Empty,
+        // filled in by the access log
+        request_id = Empty,
+    );
+    span.set_parent(TraceContextPropagator::new().extract(&HeaderExtractor(req.headers())));
+
+    async move {
+        let res = next.call(req).await;
+        let status = match &res {
+            Ok(res) => res.status(),
+            Err(e) => e.as_response_error().status_code(),
+        };
+        let span = Span::current();
+        span.record("http.response.status_code", status.as_u16());
+        if status.is_server_error() {
+            span.record("otel.status_code", "ERROR");
+        }
+        res
+    }
+    .instrument(span)
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{middleware::from_fn, web, App, HttpResponse};
+    use opentelemetry::trace::TraceContextExt;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+
+    fn provider() -> TracerProvider {
+        TracerProvider::builder().build()
+    }
+
+    #[actix_rt::test]
+    async fn requests_continue_the_callers_trace() {
+        let provider = provider();
+        let subscriber = tracing_subscriber::registry().with(layer(&provider));
+        let _guard = tracing::subscriber::set_default(subscriber);
+        let app = actix_web::test::init_service(App::new().wrap(from_fn(trace_requests)).route(
+            "/films",
+            web::get().to(|| async {
+                // what a handler would send along to another service
+                let headers = trace_headers(&Span::current());
+                HttpResponse::Ok().body(headers["traceparent"].clone())
+            }),
+        ))
+        .await;
+        let req = actix_web::test::TestRequest::get()
+            .uri("/films")
+            .insert_header((
+                "traceparent",
+                format!("00-{}-00f067aa0ba902b7-01", TRACE_ID),
+            ))
+            .to_request();
+
+        let body = actix_web::test::call_and_read_body(&app, req).await;
+
+        let traceparent = String::from_utf8(body.to_vec()).unwrap();
+        assert!(
+            traceparent.starts_with(&format!("00-{}-", TRACE_ID)),
+            "{}",
+            traceparent
+        );
+        assert!(!traceparent.contains("00f067aa0ba902b7"));
+    }
+
+    #[actix_rt::test]
+    async fn requests_without_a_traceparent_start_a_trace() {
+        let provider = provider();
+        let subscriber = tracing_subscriber::registry().with(layer(&provider));
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let span = tracing::info_span!("request");
+
+        assert!(span.context().span().span_context().is_valid());
+        assert!(trace_headers(&span).contains_key("traceparent"));
+    }
+
+    #[test]
+    fn nothing_is_exported_without_an_endpoint() {
+        assert!(tracer_provider(&TelemetrySettings::default())
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn invalid_settings_are_reported() {
+        let settings = TelemetrySettings {
+            otlp_endpoint: Some("localhost:4317".to_string()),
+            service_name: " ".to_string(),
+        };
+
+        let problems = settings.problems();
+
+        assert_eq!(problems.len(), 2, "{:?}", problems);
+        assert!(problems[0].starts_with("telemetry.otlp_endpoint"));
+        assert!(problems[1].starts_with("telemetry.service_name"));
+    }
+}
@@ -0,0 +1,14 @@
+use actix_http::Request;
+use actix_web::{
+    dev::{Service, ServiceResponse},
+    web::ServiceConfig,
+    App, Error,
+};
+
+/// Starts an app serving what `configure` registers, for the unit and the
+/// integration tests. Middlewares go on a `web::scope("")` around the routes.
+pub async fn app(
+    configure: impl FnOnce(&mut ServiceConfig),
+) -> impl Service<Request, Response = ServiceResponse, Error = Error> {
+    actix_web::test::init_service(App::new().configure(configure)).await
+}
@@ -1,11 +1,20 @@
+use std::fmt;
+
 use actix_web::{
+    error::{InternalError, JsonPayloadError, PathError},
+    http::StatusCode,
     web::{self, ServiceConfig},
-    HttpResponse,
+    FromRequest, HttpRequest, HttpResponse, ResponseError,
 };
 use shared::models::{CreateFilm, Film};
+use utoipa::OpenApi;
 use uuid::Uuid;
 
-use crate::film_repository::FilmRepository;
+use crate::{access_log::RequestId, film_repository::FilmRepository};
+
+#[derive(OpenApi)]
+#[openapi(paths(get_all, get, post, put, delete))]
+pub(crate) struct FilmsApi;
 
 pub fn service<R: FilmRepository>(cfg: &mut ServiceConfig) {
     cfg.service(
@@ -22,53 +31,166 @@ pub fn service<R: FilmRepository>(cfg: &mut ServiceConfig) {
     );
 }
 
-async fn get_all<R: FilmRepository>(repo: web::Data<R>) -> HttpResponse {
+/// Lists every film.
+#[utoipa::path(
+    get,
+    path = "",
+    operation_id = "list_films",
+    tag = "films",
+    responses(
+        (status = 200, description = "All the films", body = Vec<Film>),
+        (status = 404, description = "The films couldn't be retrieved", body = String),
+    )
+)]
+#[tracing::instrument(skip_all)]
+async fn get_all<R: FilmRepository>(repo: web::Data<R>, request_id: RequestId) -> HttpResponse {
     match repo.get_films().await {
         Ok(films) => HttpResponse::Ok().json(films),
-        Err(e) => HttpResponse::NotFound().body(format!("Internal server error: {:?}", e)),
+        Err(e) => HttpResponse::NotFound().body(error_body(
+            format!("Internal server error: {}", e),
+            &request_id,
+        )),
     }
 }
 
-async fn get<R: FilmRepository>(film_id: web::Path<Uuid>, repo: web::Data<R>) -> HttpResponse {
+/// Gets a film by its id.
+#[utoipa::path(
+    get,
+    path = "/{film_id}",
+    operation_id = "get_film",
+    tag = "films",
+    params(("film_id" = Uuid, Path, description = "Id of the film")),
+    responses(
+        (status = 200, description = "The film", body = Film),
+        (status = 404, description = "The film doesn't exist", body = String),
+    )
+)]
+#[tracing::instrument(skip_all, fields(film.id = %film_id))]
+async fn get<R: FilmRepository>(
+    film_id: web::Path<Uuid>,
+    repo: web::Data<R>,
+    request_id: RequestId,
+) -> HttpResponse {
     match repo.get_film(&film_id).await {
         Ok(film) => HttpResponse::Ok().json(film),
-        Err(_) => HttpResponse::NotFound().body("Not found"),
+        Err(_) => HttpResponse::NotFound().body(error_body("Not found", &request_id)),
     }
 }
 
+/// Creates a film.
+#[utoipa::path(
+    post,
+    path = "",
+    operation_id = "create_film",
+    tag = "films",
+    request_body = CreateFilm,
+    responses(
+        (status = 200, description = "The created film", body = Film),
+        (status = 500, description = "The film couldn't be created", body = String),
+    )
+)]
+#[tracing::instrument(skip_all)]
 async fn post<R: FilmRepository>(
     create_film: web::Json<CreateFilm>,
     repo: web::Data<R>,
+    request_id: RequestId,
 ) -> HttpResponse {
     match repo.create_film(&create_film).await {
         Ok(film) => HttpResponse::Ok().json(film),
-        Err(e) => {
-            HttpResponse::InternalServerError().body(format!("Internal server error: {:?}", e))
-        }
+        Err(e) => HttpResponse::InternalServerError().body(error_body(
+            format!("Internal server error: {}", e),
+            &request_id,
+        )),
     }
 }
 
-async fn put<R: FilmRepository>(film: web::Json<Film>, repo: web::Data<R>) -> HttpResponse {
+/// Updates a film.
+#[utoipa::path(
+    put,
+    path = "",
+    operation_id = "update_film",
+    tag = "films",
+    request_body = Film,
+    responses(
+        (status = 200, description = "The updated film", body = Film),
+        (status = 404, description = "The film doesn't exist", body = String),
+    )
+)]
+#[tracing::instrument(skip_all, fields(film.id = %film.id))]
+async fn put<R: FilmRepository>(
+    film: web::Json<Film>,
+    repo: web::Data<R>,
+    request_id: RequestId,
+) -> HttpResponse {
     match repo.update_film(&film).await {
         Ok(film) => HttpResponse::Ok().json(film),
-        Err(e) => HttpResponse::NotFound().body(format!("Internal server error: {:?}", e)),
+        Err(e) => HttpResponse::NotFound().body(error_body(
+            format!("Internal server error: {}", e),
+            &request_id,
+        )),
     }
 }
 
-async fn delete<R: FilmRepository>(film_id: web::Path<Uuid>, repo: web::Data<R>) -> HttpResponse {
+/// Deletes a film and returns its id.
+#[utoipa::path(
+    delete,
+    path = "/{film_id}",
+    operation_id = "delete_film",
+    tag = "films",
+    params(("film_id" = Uuid, Path, description = "Id of the film")),
+    responses(
+        (status = 200, description = "The id of the deleted film", body = Uuid, content_type = "application/json"),
+        (status = 500, description = "The film couldn't be deleted", body = String),
+    )
+)]
+#[tracing::instrument(skip_all, fields(film.id = %film_id))]
+async fn delete<R: FilmRepository>(
+    film_id: web::Path<Uuid>,
+    repo: web::Data<R>,
+    request_id: RequestId,
+) -> HttpResponse {
     match repo.delete_film(&film_id).await {
         Ok(film) => HttpResponse::Ok().json(film),
-        Err(e) => {
-            HttpResponse::InternalServerError().body(format!("Internal server error: {:?}", e))
-        }
+        Err(e) => HttpResponse::InternalServerError().body(error_body(
+            format!("Internal server error: {}", e),
+            &request_id,
+        )),
     }
 }
 
+/// Error bodies carry the request id, so a reported error can be found in
+/// the logs.
+fn error_body(message: impl fmt::Display, request_id: &RequestId) -> String {
+    format!("{} (request id: {})", message, request_id)
+}
+
+/// Answers the bodies that can't be read with the usual status, and an
+/// [`error_body`].
+pub(super) fn json_error(error: JsonPayloadError, req: &HttpRequest) -> actix_web::Error {
+    let status = error.status_code();
+    extractor_error(error, status, req)
+}
+
+/// Answers the paths that can't be read, like a film id that isn't a UUID,
+/// with a 404 as before, and an [`error_body`].
+pub(super) fn path_error(error: PathError, req: &HttpRequest) -> actix_web::Error {
+    extractor_error(error, StatusCode::NOT_FOUND, req)
+}
+
+fn extractor_error<E>(error: E, status: StatusCode, req: &HttpRequest) -> actix_web::Error
+where
+    E: fmt::Debug + fmt::Display + 'static,
+{
+    let request_id = RequestId::extract(req).into_inner().unwrap_or_default();
+    let res = HttpResponse::build(status).body(error_body(&error, &request_id));
+    InternalError::from_response(error, res).into()
+}
+
 #[cfg(test)]
 mod tests {
 
     use super::*;
-    use crate::film_repository::MockFilmRepository;
+    use crate::film_repository::{FilmError, MockFilmRepository};
     use actix_web::body::to_bytes;
     use chrono::Utc;
 
@@ -97,7 +219,7 @@ mod tests {
             Ok(vec![film, film2])
         });
 
-        let result = get_all(web::Data::new(repo)).await;
+        let result = get_all(web::Data::new(repo), RequestId::new()).await;
 
         let body = to_bytes(result.into_body()).await.unwrap();
         let films = serde_json::from_slice::<'_, Vec<Film>>(&body).unwrap();
@@ -118,7 +240,12 @@ mod tests {
             Ok(film)
         });
 
-        let result = get(web::Path::from(film_id), web::Data::new(repo)).await;
+        let result = get(
+            web::Path::from(film_id),
+            web::Data::new(repo),
+            RequestId::new(),
+        )
+        .await;
 
         let body = to_bytes(result.into_body()).await.unwrap();
         let film = serde_json::from_slice::<'_, Film>(&body).unwrap();
@@ -151,7 +278,12 @@ mod tests {
             })
         });
 
-        let result = post(web::Json(create_film), web::Data::new(repo)).await;
+        let result = post(
+            web::Json(create_film),
+            web::Data::new(repo),
+            RequestId::new(),
+        )
+        .await;
 
         let body = to_bytes(result.into_body()).await.unwrap();
         let film = serde_json::from_slice::<'_, Film>(&body).unwrap();
@@ -170,7 +302,7 @@ mod tests {
         repo.expect_update_film()
             .returning(|film| Ok(film.to_owned()));
 
-        let result = put(web::Json(new_film), web::Data::new(repo)).await;
+        let result = put(web::Json(new_film), web::Data::new(repo), RequestId::new()).await;
 
         let body = to_bytes(result.into_body()).await.unwrap();
         let film = serde_json::from_slice::<'_, Film>(&body).unwrap();
@@ -186,11 +318,34 @@ mod tests {
         let mut repo = MockFilmRepository::default();
         repo.expect_delete_film().returning(|id| Ok(id.to_owned()));
 
-        let result = delete(web::Path::from(film_id), web::Data::new(repo)).await;
+        let result = delete(
+            web::Path::from(film_id),
+            web::Data::new(repo),
+            RequestId::new(),
+        )
+        .await;
 
         let body = to_bytes(result.into_body()).await.unwrap();
         let uuid = serde_json::from_slice::<'_, Uuid>(&body).unwrap();
 
         assert_eq!(uuid, film_id);
     }
+
+    #[actix_rt::test]
+    async fn errors_carry_the_request_id() {
+        let mut repo = MockFilmRepository::default();
+        repo.expect_get_film()
+            .returning(|film_id| Err(FilmError::NotFound(*film_id)));
+        let request_id = RequestId::new();
+
+        let result = get(
+            web::Path::from(Uuid::new_v4()),
+            web::Data::new(repo),
+            request_id.clone(),
+        )
+        .await;
+
+        let body = to_bytes(result.into_body()).await.unwrap();
+        assert_eq!(body, format!("Not found (request id: {})", request_id));
+    }
 }
@@ -1,9 +1,20 @@
 use actix_web::web::{self, ServiceConfig};
+use utoipa::OpenApi;
 
 use crate::film_repository::FilmRepository;
 
 mod films;
 
+#[derive(OpenApi)]
+#[openapi(nest((path = "/v1/films", api = films::FilmsApi)))]
+pub(crate) struct V1Api;
+
 pub fn service<R: FilmRepository>(cfg: &mut ServiceConfig) {
-    cfg.service(web::scope("/v1").configure(films::service::<R>));
+    cfg.service(
+        web::scope("/v1")
+            // request ids in the errors of the requests that can't even be read
+            .app_data(web::JsonConfig::default().error_handler(films::json_error))
+            .app_data(web::PathConfig::default().error_handler(films::path_error))
+            .configure(films::service::<R>),
+    );
 }
@@ -0,0 +1,123 @@
+use std::fmt;
+
+use actix_web::{
+    error::{JsonPayloadError, PathError, QueryPayloadError},
+    http::StatusCode,
+    FromRequest, HttpRequest, HttpResponse, ResponseError,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::{access_log::RequestId, film_repository::FilmError};
+
+/// What went wrong, for clients to act on without parsing the message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// The request can't be understood or its content isn't valid.
+    InvalidRequest,
+    NotFound,
+    Internal,
+}
+
+/// The body of every v2 error.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct ErrorBody {
+    pub code: ErrorCode,
+    pub message: String,
+    /// Finds the request in the logs.
+    pub request_id: String,
+}
+
+/// An error answered with an [`ErrorBody`] and the status of its code.
+#[derive(Debug)]
+pub struct ApiError {
+    code: ErrorCode,
+    message: String,
+    request_id: RequestId,
+}
+
+impl ApiError {
+    pub fn invalid_request(message: impl fmt::Display, request_id: &RequestId) -> Self {
+        Self {
+            code: ErrorCode::InvalidRequest,
+            message: message.to_string(),
+            request_id: request_id.clone(),
+        }
+    }
+
+    pub fn film_not_found(film_id: &Uuid, request_id: &RequestId) -> Self {
+        Self {
+            code: ErrorCode::NotFound,
+            message: format!("Film {} doesn't exist", film_id),
+            request_id: request_id.clone(),
+        }
+    }
+
+    /// A 404 for the missing films, a 500 for everything else.
+    pub fn from_film_error(error: FilmError, request_id: &RequestId) -> Self {
+        match error {
+            FilmError::NotFound(film_id) => Self::film_not_found(&film_id, request_id),
+            error => Self::internal(error, request_id),
+        }
+    }
+
+    /// Logs the repository error, which clients don't get to see.
+    pub fn internal(error: FilmError, request_id: &RequestId) -> Self {
+        tracing::error!(request_id = request_id.as_str(), "{}", error);
+        Self {
+            code: ErrorCode::Internal,
+            message: "Internal server error".to_string(),
+            request_id: request_id.clone(),
+        }
+    }
+
+    pub fn code(&self) -> ErrorCode {
+        self.code
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self.code {
+            ErrorCode::InvalidRequest => StatusCode::BAD_REQUEST,
+            ErrorCode::NotFound => StatusCode::NOT_FOUND,
+            ErrorCode::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code()).json(ErrorBody {
+            code: self.code,
+            message: self.message.clone(),
+            request_id: self.request_id.to_string(),
+        })
+    }
+}
+
+fn request_id(req: &HttpRequest) -> RequestId {
+    RequestId::extract(req).into_inner().unwrap_or_default()
+}
+
+/// Turns the bodies that can't be read into [`ApiError`]s.
+pub(crate) fn json_error(error: JsonPayloadError, req: &HttpRequest) -> actix_web::Error {
+    ApiError::invalid_request(error, &request_id(req)).into()
+}
+
+/// Turns the query strings that can't be read into [`ApiError`]s.
+pub(crate) fn query_error(error: QueryPayloadError, req: &HttpRequest) -> actix_web::Error {
+    ApiError::invalid_request(error, &request_id(req)).into()
+}
+
+/// Turns the paths that can't be read, like a film id that isn't a UUID,
+/// into [`ApiError`]s.
+pub(crate) fn path_error(error: PathError, req: &HttpRequest) -> actix_web::Error {
+    ApiError::invalid_request(error, &request_id(req)).into()
+}
@@ -0,0 +1,509 @@
+use actix_web::{
+    http::header,
+    web::{self, ServiceConfig},
+    HttpRequest, HttpResponse,
+};
+use serde::{Deserialize, Serialize};
+use shared::models::{CreateFilm, Film};
+use utoipa::{IntoParams, OpenApi, ToSchema};
+use uuid::Uuid;
+
+use super::error::{ApiError, ErrorBody, ErrorCode};
+use crate::{access_log::RequestId, film_repository::FilmRepository};
+
+const DEFAULT_PER_PAGE: u32 = 20;
+const MAX_PER_PAGE: u32 = 100;
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(list, get, create, replace, patch, delete),
+    components(schemas(FilmPage, FilmPatch, ErrorBody, ErrorCode))
+)]
+pub(crate) struct FilmsApi;
+
+pub fn service<R: FilmRepository>(cfg: &mut ServiceConfig) {
+    cfg.service(
+        web::scope("/films")
+            // GET
+            .route("", web::get().to(list::<R>))
+            .route("/{film_id}", web::get().to(get::<R>))
+            // POST
+            .route("", web::post().to(create::<R>))
+            // PUT
+            .route("/{film_id}", web::put().to(replace::<R>))
+            // PATCH
+            .route("/{film_id}", web::patch().to(patch::<R>))
+            // DELETE
+            .route("/{film_id}", web::delete().to(delete::<R>)),
+    );
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PageQuery {
+    /// Starts at 1.
+    #[param(minimum = 1, default = 1)]
+    page: Option<u32>,
+    #[param(minimum = 1, maximum = 100, default = 20)]
+    per_page: Option<u32>,
+}
+
+/// A page of films, oldest first, with what's needed to get the others.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct FilmPage {
+    pub data: Vec<Film>,
+    pub page: u32,
+    pub per_page: u32,
+    /// How many films there are across every page.
+    pub total: u64,
+    pub total_pages: u64,
+}
+
+/// The fields of a film to change, the others are kept.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct FilmPatch {
+    pub title: Option<String>,
+    pub director: Option<String>,
+    pub year: Option<u16>,
+    pub poster: Option<String>,
+}
+
+impl FilmPatch {
+    fn apply(self, film: &mut Film) {
+        if let Some(title) = self.title {
+            film.title = title;
+        }
+        if let Some(director) = self.director {
+            film.director = director;
+        }
+        if let Some(year) = self.year {
+            film.year = year;
+        }
+        if let Some(poster) = self.poster {
+            film.poster = poster;
+        }
+    }
+}
+
+/// Rejects the films without a title or a director.
+fn validate(title: &str, director: &str, request_id: &RequestId) -> Result<(), ApiError> {
+    if title.trim().is_empty() {
+        return Err(ApiError::invalid_request(
+            "title can't be empty",
+            request_id,
+        ));
+    }
+    if director.trim().is_empty() {
+        return Err(ApiError::invalid_request(
+            "director can't be empty",
+            request_id,
+        ));
+    }
+    Ok(())
+}
+
+/// Gets the film, a 404 when it doesn't exist.
+async fn existing_film<R: FilmRepository>(
+    repo: &R,
+    film_id: &Uuid,
+    request_id: &RequestId,
+) -> Result<Film, ApiError> {
+    repo.get_film(film_id)
+        .await
+        .map_err(|e| ApiError::from_film_error(e, request_id))
+}
+
+/// Lists the films, a page at a time.
+#[utoipa::path(
+    get,
+    path = "",
+    operation_id = "list_films_v2",
+    tag = "films v2",
+    params(PageQuery),
+    responses(
+        (status = 200, description = "A page of films", body = FilmPage),
+        (status = 400, description = "The page doesn't make sense", body = ErrorBody),
+        (status = 500, description = "The films couldn't be retrieved", body = ErrorBody),
+    )
+)]
+#[tracing::instrument(skip_all)]
+async fn list<R: FilmRepository>(
+    query: web::Query<PageQuery>,
+    repo: web::Data<R>,
+    request_id: RequestId,
+) -> Result<HttpResponse, ApiError> {
+    let page = query.page.unwrap_or(1);
+    let per_page = query.per_page.unwrap_or(DEFAULT_PER_PAGE);
+    if page == 0 {
+        return Err(ApiError::invalid_request("page starts at 1", &request_id));
+    }
+    if per_page == 0 || per_page > MAX_PER_PAGE {
+        return Err(ApiError::invalid_request(
+            format!("per_page must be between 1 and {}", MAX_PER_PAGE),
+            &request_id,
+        ));
+    }
+
+    let offset = u64::from(page - 1) * u64::from(per_page);
+    let data = repo
+        .get_films_page(offset, per_page.into())
+        .await
+        .map_err(|e| ApiError::from_film_error(e, &request_id))?;
+    let total = repo
+        .count_films()
+        .await
+        .map_err(|e| ApiError::from_film_error(e, &request_id))?;
+
+    Ok(HttpResponse::Ok().json(FilmPage {
+        data,
+        page,
+        per_page,
+        total,
+        total_pages: total.div_ceil(u64::from(per_page)),
+    }))
+}
+
+/// Gets a film by its id.
+#[utoipa::path(
+    get,
+    path = "/{film_id}",
+    operation_id = "get_film_v2",
+    tag = "films v2",
+    params(("film_id" = Uuid, Path, description = "Id of the film")),
+    responses(
+        (status = 200, description = "The film", body = Film),
+        (status = 404, description = "The film doesn't exist", body = ErrorBody),
+        (status = 500, description = "The film couldn't be retrieved", body = ErrorBody),
+    )
+)]
+#[tracing::instrument(skip_all, fields(film.id = %film_id))]
+async fn get<R: FilmRepository>(
+    film_id: web::Path<Uuid>,
+    repo: web::Data<R>,
+    request_id: RequestId,
+) -> Result<HttpResponse, ApiError> {
+    let film = existing_film(repo.get_ref(), &film_id, &request_id).await?;
+    Ok(HttpResponse::Ok().json(film))
+}
+
+/// Creates a film.
+#[utoipa::path(
+    post,
+    path = "",
+    operation_id = "create_film_v2",
+    tag = "films v2",
+    request_body = CreateFilm,
+    responses(
+        (status = 201, description = "The created film, at the `Location` URL", body = Film),
+        (status = 400, description = "The film isn't valid", body = ErrorBody),
+        (status = 500, description = "The film couldn't be created", body = ErrorBody),
+    )
+)]
+#[tracing::instrument(skip_all)]
+async fn create<R: FilmRepository>(
+    req: HttpRequest,
+    create_film: web::Json<CreateFilm>,
+    repo: web::Data<R>,
+    request_id: RequestId,
+) -> Result<HttpResponse, ApiError> {
+    validate(&create_film.title, &create_film.director, &request_id)?;
+    let film = repo
+        .create_film(&create_film)
+        .await
+        .map_err(|e| ApiError::from_film_error(e, &request_id))?;
+    let location = format!("{}/{}", req.path().trim_end_matches('/'), film.id);
+    Ok(HttpResponse::Created()
+        .insert_header((header::LOCATION, location))
+        .json(film))
+}
+
+/// Replaces every field of a film.
+#[utoipa::path(
+    put,
+    path = "/{film_id}",
+    operation_id = "replace_film_v2",
+    tag = "films v2",
+    params(("film_id" = Uuid, Path, description = "Id of the film")),
+    request_body = CreateFilm,
+    responses(
+        (status = 200, description = "The updated film", body = Film),
+        (status = 400, description = "The film isn't valid", body = ErrorBody),
+        (status = 404, description = "The film doesn't exist", body = ErrorBody),
+        (status = 500, description = "The film couldn't be updated", body = ErrorBody),
+    )
+)]
+#[tracing::instrument(skip_all, fields(film.id = %film_id))]
+async fn replace<R: FilmRepository>(
+    film_id: web::Path<Uuid>,
+    create_film: web::Json<CreateFilm>,
+    repo: web::Data<R>,
+    request_id: RequestId,
+) -> Result<HttpResponse, ApiError> {
+    validate(&create_film.title, &create_film.director, &request_id)?;
+    let CreateFilm {
+        title,
+        director,
+        year,
+        poster,
+    } = create_film.into_inner();
+    let film = Film {
+        title,
+        director,
+        year,
+        poster,
+        ..existing_film(repo.get_ref(), &film_id, &request_id).await?
+    };
+    let film = repo
+        .update_film(&film)
+        .await
+        .map_err(|e| ApiError::from_film_error(e, &request_id))?;
+    Ok(HttpResponse::Ok().json(film))
+}
+
+/// Changes some fields of a film.
+#[utoipa::path(
+    patch,
+    path = "/{film_id}",
+    operation_id = "patch_film_v2",
+    tag = "films v2",
+    params(("film_id" = Uuid, Path, description = "Id of the film")),
+    request_body = FilmPatch,
+    responses(
+        (status = 200, description = "The updated film", body = Film),
+        (status = 400, description = "The changes aren't valid", body = ErrorBody),
+        (status = 404, description = "The film doesn't exist", body = ErrorBody),
+        (status = 500, description = "The film couldn't be updated", body = ErrorBody),
+    )
+)]
+#[tracing::instrument(skip_all, fields(film.id = %film_id))]
+async fn patch<R: FilmRepository>(
+    film_id: web::Path<Uuid>,
+    film_patch: web::Json<FilmPatch>,
+    repo: web::Data<R>,
+    request_id: RequestId,
+) -> Result<HttpResponse, ApiError> {
+    let mut film = existing_film(repo.get_ref(), &film_id, &request_id).await?;
+    film_patch.into_inner().apply(&mut film);
+    validate(&film.title, &film.director, &request_id)?;
+    let film = repo
+        .update_film(&film)
+        .await
+        .map_err(|e| ApiError::from_film_error(e, &request_id))?;
+    Ok(HttpResponse::Ok().json(film))
+}
+
+/// Deletes a film.
+#[utoipa::path(
+    delete,
+    path = "/{film_id}",
+    operation_id = "delete_film_v2",
+    tag = "films v2",
+    params(("film_id" = Uuid, Path, description = "Id of the film")),
+    responses(
+        (status = 204, description = "The film is gone"),
+        (status = 404, description = "The film doesn't exist", body = ErrorBody),
+        (status = 500, description = "The film couldn't be deleted", body = ErrorBody),
+    )
+)]
+#[tracing::instrument(skip_all, fields(film.id = %film_id))]
+async fn delete<R: FilmRepository>(
+    film_id: web::Path<Uuid>,
+    repo: web::Data<R>,
+    request_id: RequestId,
+) -> Result<HttpResponse, ApiError> {
+    repo.delete_film(&film_id)
+        .await
+        .map_err(|e| ApiError::from_film_error(e, &request_id))?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::film_repository::{FilmError, MockFilmRepository};
+    use actix_web::{body::to_bytes, http::StatusCode, ResponseError};
+    use chrono::{Duration, Utc};
+
+    fn film(title: &str, age_in_days: i64) -> Film {
+        Film {
+            id: Uuid::new_v4(),
+            title: title.to_string(),
+            director: "Director test name".to_string(),
+            year: 2001,
+            poster: "Poster test name".to_string(),
+            created_at: Some(Utc::now() - Duration::days(age_in_days)),
+            updated_at: None,
+        }
+    }
+
+    async fn error_body(error: ApiError) -> (StatusCode, ErrorBody) {
+        let res = error.error_response();
+        let status = res.status();
+        let body = to_bytes(res.into_body()).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[actix_rt::test]
+    async fn films_are_listed_a_page_at_a_time() {
+        let mut repo = MockFilmRepository::default();
+        repo.expect_get_films_page()
+            .withf(|offset, limit| (*offset, *limit) == (3, 3))
+            .returning(|_, _| Ok(vec![film("newest", 0)]));
+        repo.expect_count_films().returning(|| Ok(4));
+        let query = PageQuery {
+            page: Some(2),
+            per_page: Some(3),
+        };
+
+        let result = list(web::Query(query), web::Data::new(repo), RequestId::new())
+            .await
+            .unwrap();
+
+        let body = to_bytes(result.into_body()).await.unwrap();
+        let page: FilmPage = serde_json::from_slice(&body).unwrap();
+        let titles: Vec<&str> = page.data.iter().map(|film| film.title.as_str()).collect();
+        assert_eq!(titles, vec!["newest"]);
+        assert_eq!((page.page, page.per_page), (2, 3));
+        assert_eq!((page.total, page.total_pages), (4, 2));
+    }
+
+    #[actix_rt::test]
+    async fn pages_that_dont_make_sense_are_rejected() {
+        for (page, per_page) in [(Some(0), None), (None, Some(0)), (None, Some(101))] {
+            let query = PageQuery { page, per_page };
+
+            let error = list(
+                web::Query(query),
+                web::Data::new(MockFilmRepository::default()),
+                RequestId::new(),
+            )
+            .await
+            .unwrap_err();
+
+            assert_eq!(error.code(), ErrorCode::InvalidRequest);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn missing_films_are_typed_errors() {
+        let mut repo = MockFilmRepository::default();
+        repo.expect_get_film()
+            .returning(|film_id| Err(FilmError::NotFound(*film_id)));
+        let film_id = Uuid::new_v4();
+        let request_id = RequestId::new();
+
+        let error = get(
+            web::Path::from(film_id),
+            web::Data::new(repo),
+            request_id.clone(),
+        )
+        .await
+        .unwrap_err();
+
+        let (status, body) = error_body(error).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(
+            body,
+            ErrorBody {
+                code: ErrorCode::NotFound,
+                message: format!("Film {} doesn't exist", film_id),
+                request_id: request_id.to_string(),
+            }
+        );
+    }
+
+    #[actix_rt::test]
+    async fn failing_repositories_arent_missing_films() {
+        let mut repo = MockFilmRepository::default();
+        repo.expect_get_film()
+            .returning(|_| Err(FilmError::internal("connection refused")));
+
+        let error = get(
+            web::Path::from(Uuid::new_v4()),
+            web::Data::new(repo),
+            RequestId::new(),
+        )
+        .await
+        .unwrap_err();
+
+        assert_eq!(error.code(), ErrorCode::Internal);
+    }
+
+    #[actix_rt::test]
+    async fn repository_errors_arent_shown() {
+        let mut repo = MockFilmRepository::default();
+        repo.expect_get_films_page()
+            .returning(|_, _| Err(FilmError::internal("password authentication failed")));
+
+        let error = list(
+            web::Query(PageQuery {
+                page: None,
+                per_page: None,
+            }),
+            web::Data::new(repo),
+            RequestId::new(),
+        )
+        .await
+        .unwrap_err();
+
+        let (status, body) = error_body(error).await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(body.code, ErrorCode::Internal);
+        assert!(!body.message.contains("password"));
+    }
+
+    #[actix_rt::test]
+    async fn patches_only_change_the_given_fields() {
+        let existing = film("Old title", 1);
+        let film_id = existing.id;
+        let mut repo = MockFilmRepository::default();
+        repo.expect_get_film()
+            .returning(move |_| Ok(existing.clone()));
+        repo.expect_update_film()
+            .returning(|film| Ok(film.to_owned()));
+        let film_patch = FilmPatch {
+            title: Some("New title".to_string()),
+            year: Some(1999),
+            ..Default::default()
+        };
+
+        let result = patch(
+            web::Path::from(film_id),
+            web::Json(film_patch),
+            web::Data::new(repo),
+            RequestId::new(),
+        )
+        .await
+        .unwrap();
+
+        let body = to_bytes(result.into_body()).await.unwrap();
+        let film: Film = serde_json::from_slice(&body).unwrap();
+        assert_eq!(film.id, film_id);
+        assert_eq!(film.title, "New title");
+        assert_eq!(film.year, 1999);
+        assert_eq!(film.director, "Director test name");
+    }
+
+    #[actix_rt::test]
+    async fn films_without_a_title_are_rejected() {
+        let mut repo = MockFilmRepository::default();
+        repo.expect_get_film().returning(|_| Ok(film("Title", 1)));
+        let film_patch = FilmPatch {
+            title: Some(" ".to_string()),
+            ..Default::default()
+        };
+
+        let error = patch(
+            web::Path::from(Uuid::new_v4()),
+            web::Json(film_patch),
+            web::Data::new(repo),
+            RequestId::new(),
+        )
+        .await
+        .unwrap_err();
+
+        assert_eq!(error.code(), ErrorCode::InvalidRequest);
+        assert_eq!(error.to_string(), "title can't be empty");
+    }
+}
@@ -0,0 +1,27 @@
+use actix_web::web::{self, ServiceConfig};
+use utoipa::OpenApi;
+
+use crate::film_repository::FilmRepository;
+
+mod error;
+mod films;
+
+pub use error::{ApiError, ErrorBody, ErrorCode};
+pub use films::{FilmPage, FilmPatch};
+
+#[derive(OpenApi)]
+#[openapi(nest((path = "/v2/films", api = films::FilmsApi)))]
+pub(crate) struct V2Api;
+
+/// The films API, with typed errors, pagination and partial updates. Served
+/// next to v1, on the same repository.
+pub fn service<R: FilmRepository>(cfg: &mut ServiceConfig) {
+    cfg.service(
+        web::scope("/v2")
+            // typed errors for the requests that can't even be read
+            .app_data(web::JsonConfig::default().error_handler(error::json_error))
+            .app_data(web::QueryConfig::default().error_handler(error::query_error))
+            .app_data(web::PathConfig::default().error_handler(error::path_error))
+            .configure(films::service::<R>),
+    );
+}
@@ -0,0 +1,71 @@
+use async_trait::async_trait;
+use uuid::Uuid;
+
+use super::{
+    CreateWebhook, Delivery, MemoryWebhookRepository, PostgresWebhookRepository, Webhook,
+    WebhookRepository, WebhookResult,
+};
+
+/// Any of the available webhook repositories, picked at runtime alongside
+/// the film repository.
+pub enum AnyWebhookRepository {
+    Memory(MemoryWebhookRepository),
+    Postgres(PostgresWebhookRepository),
+}
+
+impl From<MemoryWebhookRepository> for AnyWebhookRepository {
+    fn from(repo: MemoryWebhookRepository) -> Self {
+        AnyWebhookRepository::Memory(repo)
+    }
+}
+
+impl From<PostgresWebhookRepository> for AnyWebhookRepository {
+    fn from(repo: PostgresWebhookRepository) -> Self {
+        AnyWebhookRepository::Postgres(repo)
+    }
+}
+
+#[async_trait]
+impl WebhookRepository for AnyWebhookRepository {
+    async fn get_webhooks(&self) -> WebhookResult<Vec<Webhook>> {
+        match self {
+            AnyWebhookRepository::Memory(repo) => repo.get_webhooks().await,
+            AnyWebhookRepository::Postgres(repo) => repo.get_webhooks().await,
+        }
+    }
+
+    async fn get_webhook(&self, id: &Uuid) -> WebhookResult<Webhook> {
+        match self {
+            AnyWebhookRepository::Memory(repo) => repo.get_webhook(id).await,
+            AnyWebhookRepository::Postgres(repo) => repo.get_webhook(id).await,
+        }
+    }
+
+    async fn create_webhook(&self, create_webhook: &CreateWebhook) -> WebhookResult<Webhook> {
+        match self {
+            AnyWebhookRepository::Memory(repo) => repo.create_webhook(create_webhook).await,
+            AnyWebhookRepository::Postgres(repo) => repo.create_webhook(create_webhook).await,
+        }
+    }
+
+    async fn delete_webhook(&self, id: &Uuid) -> WebhookResult<Uuid> {
+        match self {
+            AnyWebhookRepository::Memory(repo) => repo.delete_webhook(id).await,
+            AnyWebhookRepository::Postgres(repo) => repo.delete_webhook(id).await,
+        }
+    }
+
+    async fn record_delivery(&self, delivery: &Delivery) -> WebhookResult<()> {
+        match self {
+            AnyWebhookRepository::Memory(repo) => repo.record_delivery(delivery).await,
+            AnyWebhookRepository::Postgres(repo) => repo.record_delivery(delivery).await,
+        }
+    }
+
+    async fn get_deliveries(&self, webhook_id: &Uuid) -> WebhookResult<Vec<Delivery>> {
+        match self {
+            AnyWebhookRepository::Memory(repo) => repo.get_deliveries(webhook_id).await,
+            AnyWebhookRepository::Postgres(repo) => repo.get_deliveries(webhook_id).await,
+        }
+    }
+}
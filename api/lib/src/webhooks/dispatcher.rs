@@ -0,0 +1,485 @@
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+
+use chrono::Utc;
+use futures_util::future::join_all;
+use hmac::{Hmac, Mac};
+use hyper::client::connect::dns::Name;
+use reqwest::{
+    dns::{Addrs, Resolve, Resolving},
+    redirect,
+};
+use sha2::Sha256;
+use tokio::{
+    sync::broadcast,
+    task::{JoinHandle, JoinSet},
+};
+use tracing::Span;
+use uuid::Uuid;
+
+use super::{Delivery, Webhook, WebhookRepository};
+use crate::{events::FilmEvent, supervisor::Shutdown, telemetry::trace_headers};
+
+pub const SIGNATURE_HEADER: &str = "X-Webhook-Signature";
+const EVENT_HEADER: &str = "X-Webhook-Event";
+const DELIVERY_HEADER: &str = "X-Webhook-Delivery";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How many times a delivery is attempted and how long to wait in between.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    /// Exponential backoff: the wait after `attempt` doubles every time,
+    /// capped to `max_delay`.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        self.base_delay
+            .checked_mul(factor)
+            .map_or(self.max_delay, |delay| delay.min(self.max_delay))
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Hex encoded HMAC-SHA256 of `body` using the webhook `secret`.
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(body);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Whether `ip` is on the internet rather than on the API's own host or
+/// network: loopback, link-local, private, shared, multicast and reserved
+/// addresses aren't, nor the IPv6 ones wrapping such an IPv4 address.
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                || a == 0
+                // reserved, 240.0.0.0/4, along with the broadcast address
+                || a >= 240
+                // shared address space, RFC 6598
+                || (a == 100 && b & 0xc0 == 64)
+                // benchmarking, 198.18.0.0/15
+                || (a == 198 && b & 0xfe == 18))
+        }
+        IpAddr::V6(ip) => match wrapped_ipv4(ip) {
+            Some(ip) => is_public(IpAddr::V4(ip)),
+            None => {
+                let first = ip.segments()[0];
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_multicast()
+                    // unique local, fc00::/7
+                    || first & 0xfe00 == 0xfc00
+                    // link-local, fe80::/10
+                    || first & 0xffc0 == 0xfe80
+                    // site-local, fec0::/10
+                    || first & 0xffc0 == 0xfec0)
+            }
+        },
+    }
+}
+
+/// The IPv4 address `ip` reaches: IPv4-mapped (`::ffff:0:0/96`), NAT64
+/// (`64:ff9b::/96`) and 6to4 (`2002::/16`) addresses wrap one.
+fn wrapped_ipv4(ip: Ipv6Addr) -> Option<Ipv4Addr> {
+    let segments = ip.segments();
+    let ipv4 = |high: u16, low: u16| {
+        let [a, b] = high.to_be_bytes();
+        let [c, d] = low.to_be_bytes();
+        Ipv4Addr::new(a, b, c, d)
+    };
+    match segments {
+        [0x64, 0xff9b, 0, 0, 0, 0, high, low] => Some(ipv4(high, low)),
+        [0x2002, high, low, ..] => Some(ipv4(high, low)),
+        _ => ip.to_ipv4_mapped(),
+    }
+}
+
+/// Resolves the webhook hosts to their public addresses only, so a webhook
+/// can't be pointed at the API's own network.
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let addrs = tokio::net::lookup_host((name.as_str(), 0)).await?;
+            let public: Vec<SocketAddr> = addrs.filter(|addr| is_public(addr.ip())).collect();
+            if public.is_empty() {
+                return Err(format!("{} has no public address", name.as_str()).into());
+            }
+            let addrs: Addrs = Box::new(public.into_iter());
+            Ok(addrs)
+        })
+    }
+}
+
+/// Delivers film events to every subscribed webhook, retrying failed
+/// deliveries and recording each attempt in the [`WebhookRepository`].
+///
+/// Only public addresses are delivered to, unless private ones are allowed.
+/// Redirects aren't followed.
+pub struct WebhookDispatcher<W: WebhookRepository> {
+    repo: Arc<W>,
+    client: reqwest::Client,
+    retry_policy: RetryPolicy,
+    allow_private_addresses: bool,
+}
+
+impl<W: WebhookRepository> WebhookDispatcher<W> {
+    pub fn new(repo: Arc<W>) -> Self {
+        Self {
+            repo,
+            client: client(false),
+            retry_policy: RetryPolicy::default(),
+            allow_private_addresses: false,
+        }
+    }
+
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Delivers to loopback, link-local and private addresses too, for
+    /// receivers running next to the API.
+    pub fn allow_private_addresses(mut self, allow: bool) -> Self {
+        self.client = client(allow);
+        self.allow_private_addresses = allow;
+        self
+    }
+
+    /// Why `url` can't be delivered to, when its host is an address that
+    /// isn't allowed. Host names are checked as they're resolved.
+    fn refusal(&self, url: &str) -> Option<String> {
+        if self.allow_private_addresses {
+            return None;
+        }
+        let url = reqwest::Url::parse(url).ok()?;
+        let ip = url
+            .host_str()?
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .parse::<IpAddr>()
+            .ok()?;
+        (!is_public(ip)).then(|| format!("{} isn't a public address", ip))
+    }
+
+    /// Dispatches every event received until the channel is closed. Each
+    /// event is delivered in its own task so slow receivers don't hold back
+    /// the others.
+    pub fn spawn(self, events: broadcast::Receiver<FilmEvent>) -> JoinHandle<()> {
+        tokio::spawn(self.run(events, Shutdown::never()))
+    }
+
+    /// Like [`WebhookDispatcher::spawn`], until `shutdown` fires too. The
+    /// events already queued are delivered, and the deliveries already
+    /// started finished, before returning. The [`Supervisor`] running it
+    /// bounds how long that takes.
+    ///
+    /// [`Supervisor`]: crate::supervisor::Supervisor
+    pub async fn run(self, mut events: broadcast::Receiver<FilmEvent>, mut shutdown: Shutdown) {
+        let dispatcher = Arc::new(self);
+        let mut deliveries = JoinSet::new();
+        let dispatch = |deliveries: &mut JoinSet<()>, event: FilmEvent| {
+            let dispatcher = dispatcher.clone();
+            deliveries.spawn(async move { dispatcher.dispatch(&event).await });
+        };
+        loop {
+            tokio::select! {
+                event = events.recv() => match event {
+                    Ok(event) => dispatch(&mut deliveries, event),
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        tracing::warn!("Webhooks dispatcher skipped {} events", skipped);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+                // forget about the deliveries as they finish
+                Some(_) = deliveries.join_next() => {}
+                _ = shutdown.wait() => {
+                    // the changes behind them are done, so are their events
+                    loop {
+                        match events.try_recv() {
+                            Ok(event) => dispatch(&mut deliveries, event),
+                            Err(broadcast::error::TryRecvError::Lagged(skipped)) => {
+                                tracing::warn!("Webhooks dispatcher skipped {} events", skipped);
+                            }
+                            Err(_) => break,
+                        }
+                    }
+                    break;
+                }
+            }
+        }
+        if !deliveries.is_empty() {
+            tracing::info!("Finishing {} webhook deliveries", deliveries.len());
+        }
+        while deliveries.join_next().await.is_some() {}
+    }
+
+    /// Delivers `event` to every webhook subscribed to it and waits until
+    /// all of them succeed or run out of attempts.
+    pub async fn dispatch(&self, event: &FilmEvent) {
+        let webhooks = match self.repo.get_webhooks().await {
+            Ok(webhooks) => webhooks,
+            Err(e) => {
+                tracing::error!("Couldn't retrieve webhooks: {}", e);
+                return;
+            }
+        };
+        let body = payload(event);
+        let deliveries = webhooks
+            .iter()
+            .filter(|webhook| webhook.is_subscribed_to(event.kind()))
+            .map(|webhook| self.deliver(webhook, event, &body));
+        join_all(deliveries).await;
+    }
+
+    #[tracing::instrument(skip_all, fields(
+        otel.kind = "client", webhook.id = %webhook.id, event = event.kind().as_str(),
+    ))]
+    async fn deliver(&self, webhook: &Webhook, event: &FilmEvent, body: &[u8]) {
+        let event_id = Uuid::new_v4();
+        let signature = format!("sha256={}", sign(&webhook.secret, body));
+        // receivers can continue the trace of the delivery
+        let trace_headers = trace_headers(&Span::current());
+
+        let refusal = self.refusal(&webhook.url);
+        let mut attempts = 0;
+        for attempt in 1..=self.retry_policy.max_attempts {
+            attempts = attempt;
+            let (status_code, error) = match &refusal {
+                Some(refusal) => (None, Some(refusal.clone())),
+                None => {
+                    self.send(webhook, event, body, event_id, &signature, &trace_headers)
+                        .await
+                }
+            };
+            let success = error.is_none();
+            let delivery = Delivery {
+                id: Uuid::new_v4(),
+                webhook_id: webhook.id,
+                event_id,
+                event: event.kind(),
+                attempt,
+                status_code,
+                error,
+                success,
+                attempted_at: Utc::now(),
+            };
+            if let Err(e) = self.repo.record_delivery(&delivery).await {
+                tracing::error!("Couldn't record delivery {}: {}", delivery.id, e);
+            }
+
+            if success {
+                tracing::debug!("Event {} delivered to webhook {}", event_id, webhook.id);
+                return;
+            }
+            // the address won't change on the next attempt
+            if refusal.is_some() {
+                break;
+            }
+            if attempt < self.retry_policy.max_attempts {
+                tokio::time::sleep(self.retry_policy.delay(attempt)).await;
+            }
+        }
+
+        tracing::warn!(
+            "Giving up delivering event {} to webhook {} after {} attempts",
+            event_id,
+            webhook.id,
+            attempts
+        );
+    }
+
+    /// Posts the event, returning the status and what went wrong.
+    async fn send(
+        &self,
+        webhook: &Webhook,
+        event: &FilmEvent,
+        body: &[u8],
+        event_id: Uuid,
+        signature: &str,
+        trace_headers: &HashMap<String, String>,
+    ) -> (Option<u16>, Option<String>) {
+        let mut request = self.client.post(&webhook.url);
+        for (name, value) in trace_headers {
+            request = request.header(name, value);
+        }
+        let response = request
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(SIGNATURE_HEADER, signature)
+            .header(EVENT_HEADER, event.kind().as_str())
+            .header(DELIVERY_HEADER, event_id.to_string())
+            .body(body.to_vec())
+            .send()
+            .await;
+
+        match response {
+            Ok(response) if response.status().is_success() => {
+                (Some(response.status().as_u16()), None)
+            }
+            Ok(response) => (
+                Some(response.status().as_u16()),
+                Some(format!("Unexpected status {}", response.status())),
+            ),
+            Err(e) => (e.status().map(|s| s.as_u16()), Some(e.to_string())),
+        }
+    }
+}
+
+/// The webhooks HTTP client, resolving host names to public addresses
+/// unless private ones are allowed.
+fn client(allow_private_addresses: bool) -> reqwest::Client {
+    let builder = reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        // a redirect could lead anywhere
+        .redirect(redirect::Policy::none());
+    let builder = if allow_private_addresses {
+        builder
+    } else {
+        builder
+            .dns_resolver(Arc::new(PublicResolver))
+            // a proxy would resolve the hosts itself, wherever they point
+            .no_proxy()
+    };
+    builder
+        .build()
+        .expect("Couldn't build the webhooks HTTP client")
+}
+
+fn payload(event: &FilmEvent) -> Vec<u8> {
+    let data = match event {
+        FilmEvent::Created(film) | FilmEvent::Updated(film) => serde_json::json!(film),
+        FilmEvent::Deleted(id) => serde_json::json!({ "id": id }),
+    };
+    serde_json::json!({
+        "event": event.kind(),
+        "occurred_at": Utc::now(),
+        "data": data,
+    })
+    .to_string()
+    .into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delay_grows_exponentially_up_to_the_max() {
+        let policy = RetryPolicy {
+            max_attempts: 10,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(1),
+        };
+
+        assert_eq!(policy.delay(1), Duration::from_millis(100));
+        assert_eq!(policy.delay(2), Duration::from_millis(200));
+        assert_eq!(policy.delay(3), Duration::from_millis(400));
+        assert_eq!(policy.delay(5), Duration::from_secs(1));
+        assert_eq!(policy.delay(64), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn sign_matches_known_vector() {
+        // RFC 4231, test case 2
+        assert_eq!(
+            sign("Jefe", b"what do ya want for nothing?"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn only_public_addresses_are_public() {
+        for (ip, public) in [
+            ("93.184.216.34", true),
+            ("2606:2800:220:1:248:1893:25c8:1946", true),
+            // loopback
+            ("127.0.0.1", false),
+            ("::1", false),
+            // private and unique local
+            ("10.1.2.3", false),
+            ("172.16.0.1", false),
+            ("192.168.1.1", false),
+            ("fd00::1", false),
+            // link-local and site-local
+            ("169.254.169.254", false),
+            ("fe80::1", false),
+            ("fec0::1", false),
+            // shared address space
+            ("100.64.0.1", false),
+            // unspecified and "this network"
+            ("0.0.0.0", false),
+            ("0.1.2.3", false),
+            ("::", false),
+            // multicast
+            ("224.0.0.1", false),
+            ("239.255.255.250", false),
+            ("ff02::1", false),
+            ("ff0e::1", false),
+            // reserved and broadcast
+            ("240.0.0.1", false),
+            ("255.255.255.255", false),
+            // benchmarking
+            ("198.18.0.1", false),
+            ("198.19.255.255", false),
+            ("198.20.0.1", true),
+            // IPv4-mapped
+            ("::ffff:127.0.0.1", false),
+            ("::ffff:93.184.216.34", true),
+            // NAT64
+            ("64:ff9b::10.0.0.1", false),
+            ("64:ff9b::a9fe:a9fe", false),
+            ("64:ff9b::93.184.216.34", true),
+            // 6to4
+            ("2002:c0a8:0101::1", false),
+            ("2002:7f00:1::1", false),
+            ("2002:5db8:d822::1", true),
+        ] {
+            assert_eq!(is_public(ip.parse().unwrap()), public, "{}", ip);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn host_names_only_resolve_to_public_addresses() {
+        let resolved = PublicResolver.resolve("localhost".parse().unwrap()).await;
+
+        assert!(resolved.is_err());
+    }
+
+    #[test]
+    fn payload_contains_event_and_data() {
+        let id = Uuid::new_v4();
+        let payload = payload(&FilmEvent::Deleted(id));
+        let json = serde_json::from_slice::<serde_json::Value>(&payload).unwrap();
+
+        assert_eq!(json["event"], "film.deleted");
+        assert_eq!(json["data"]["id"], id.to_string());
+        assert!(json["occurred_at"].is_string());
+    }
+}
@@ -0,0 +1,185 @@
+use std::{collections::HashMap, sync::RwLock};
+
+use async_trait::async_trait;
+use chrono::Utc;
+use uuid::Uuid;
+
+use super::{CreateWebhook, Delivery, Webhook, WebhookRepository, WebhookResult};
+
+pub struct MemoryWebhookRepository {
+    webhooks: RwLock<HashMap<Uuid, Webhook>>,
+    deliveries: RwLock<Vec<Delivery>>,
+}
+
+impl MemoryWebhookRepository {
+    pub fn new() -> Self {
+        Self {
+            webhooks: RwLock::new(HashMap::new()),
+            deliveries: RwLock::new(Vec::new()),
+        }
+    }
+}
+
+impl Default for MemoryWebhookRepository {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl WebhookRepository for MemoryWebhookRepository {
+    async fn get_webhooks(&self) -> WebhookResult<Vec<Webhook>> {
+        self.webhooks
+            .read()
+            .map(|webhooks| webhooks.values().cloned().collect())
+            .map_err(|e| format!("An error happened while trying to read webhooks: {}", e))
+    }
+
+    async fn get_webhook(&self, webhook_id: &Uuid) -> WebhookResult<Webhook> {
+        self.webhooks
+            .read()
+            .map_err(|e| format!("An error happened while trying to read webhooks: {}", e))
+            .and_then(|webhooks| {
+                webhooks
+                    .get(webhook_id)
+                    .cloned()
+                    .ok_or_else(|| format!("Couldn't find webhook: {}", webhook_id))
+            })
+    }
+
+    async fn create_webhook(&self, create_webhook: &CreateWebhook) -> WebhookResult<Webhook> {
+        let mut webhooks = self
+            .webhooks
+            .write()
+            .map_err(|e| format!("An error happened while trying to create webhook: {}", e))?;
+        let webhook = Webhook {
+            id: Uuid::new_v4(),
+            url: create_webhook.url.clone(),
+            secret: create_webhook.secret.clone(),
+            events: create_webhook.events.clone(),
+            created_at: Some(Utc::now()),
+        };
+        webhooks.insert(webhook.id, webhook.clone());
+        tracing::trace!("Webhook with id {} correctly created", webhook.id);
+        Ok(webhook)
+    }
+
+    async fn delete_webhook(&self, webhook_id: &Uuid) -> WebhookResult<Uuid> {
+        self.webhooks
+            .write()
+            .map_err(|e| format!("An error happened while trying to delete webhook: {}", e))?
+            .remove(webhook_id);
+        if let Ok(mut deliveries) = self.deliveries.write() {
+            deliveries.retain(|delivery| delivery.webhook_id != *webhook_id);
+        }
+        Ok(webhook_id.to_owned())
+    }
+
+    async fn record_delivery(&self, delivery: &Delivery) -> WebhookResult<()> {
+        self.deliveries
+            .write()
+            .map(|mut deliveries| deliveries.push(delivery.clone()))
+            .map_err(|e| format!("An error happened while trying to record delivery: {}", e))
+    }
+
+    async fn get_deliveries(&self, webhook_id: &Uuid) -> WebhookResult<Vec<Delivery>> {
+        self.deliveries
+            .read()
+            .map(|deliveries| {
+                deliveries
+                    .iter()
+                    .filter(|delivery| delivery.webhook_id == *webhook_id)
+                    .cloned()
+                    .collect()
+            })
+            .map_err(|e| format!("An error happened while trying to read deliveries: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::FilmEventKind;
+
+    fn create_test_create_webhook() -> CreateWebhook {
+        CreateWebhook {
+            url: "http://localhost/hook".to_string(),
+            secret: "secret".to_string(),
+            events: vec![FilmEventKind::Created, FilmEventKind::Deleted],
+        }
+    }
+
+    fn create_test_delivery(webhook_id: Uuid) -> Delivery {
+        Delivery {
+            id: Uuid::new_v4(),
+            webhook_id,
+            event_id: Uuid::new_v4(),
+            event: FilmEventKind::Created,
+            attempt: 1,
+            status_code: Some(200),
+            error: None,
+            success: true,
+            attempted_at: Utc::now(),
+        }
+    }
+
+    #[actix_rt::test]
+    async fn create_and_get_webhook_works() {
+        let repo = MemoryWebhookRepository::new();
+        let create_webhook = create_test_create_webhook();
+
+        let webhook = repo.create_webhook(&create_webhook).await.unwrap();
+        let stored = repo.get_webhook(&webhook.id).await.unwrap();
+
+        assert_eq!(stored, webhook);
+        assert_eq!(stored.url, create_webhook.url);
+        assert_eq!(stored.secret, create_webhook.secret);
+        assert!(stored.is_subscribed_to(FilmEventKind::Deleted));
+        assert!(!stored.is_subscribed_to(FilmEventKind::Updated));
+        assert_eq!(repo.get_webhooks().await.unwrap().len(), 1);
+    }
+
+    #[actix_rt::test]
+    async fn get_webhook_fails_if_not_present() {
+        let repo = MemoryWebhookRepository::default();
+        assert!(repo.get_webhook(&Uuid::new_v4()).await.is_err());
+    }
+
+    #[actix_rt::test]
+    async fn deliveries_are_recorded_per_webhook() {
+        let repo = MemoryWebhookRepository::new();
+        let webhook = repo
+            .create_webhook(&create_test_create_webhook())
+            .await
+            .unwrap();
+        let delivery = create_test_delivery(webhook.id);
+
+        repo.record_delivery(&delivery).await.unwrap();
+        repo.record_delivery(&create_test_delivery(Uuid::new_v4()))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            repo.get_deliveries(&webhook.id).await.unwrap(),
+            vec![delivery]
+        );
+    }
+
+    #[actix_rt::test]
+    async fn delete_webhook_removes_its_deliveries() {
+        let repo = MemoryWebhookRepository::new();
+        let webhook = repo
+            .create_webhook(&create_test_create_webhook())
+            .await
+            .unwrap();
+        repo.record_delivery(&create_test_delivery(webhook.id))
+            .await
+            .unwrap();
+
+        let result = repo.delete_webhook(&webhook.id).await;
+
+        assert_eq!(result, Ok(webhook.id));
+        assert!(repo.get_webhook(&webhook.id).await.is_err());
+        assert!(repo.get_deliveries(&webhook.id).await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,342 @@
+mod any_webhook_repository;
+mod dispatcher;
+mod memory_webhook_repository;
+mod postgres_webhook_repository;
+mod secret_cipher;
+
+pub use any_webhook_repository::AnyWebhookRepository;
+pub use dispatcher::{sign, RetryPolicy, WebhookDispatcher, SIGNATURE_HEADER};
+pub use memory_webhook_repository::MemoryWebhookRepository;
+pub use postgres_webhook_repository::PostgresWebhookRepository;
+pub use secret_cipher::SecretCipher;
+
+use actix_web::{
+    web::{self, ServiceConfig},
+    HttpResponse,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use uuid::Uuid;
+
+use crate::{events::FilmEventKind, settings::Validate};
+
+/// The `[webhooks]` section of the [`Settings`](crate::settings::Settings).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct WebhooksSettings {
+    /// Whether webhooks may be delivered to loopback, link-local and private
+    /// addresses, like receivers running next to the API in development.
+    pub allow_private_addresses: bool,
+    /// Base64 encoded 32 bytes key the secrets are encrypted with in the
+    /// database. Required by the postgres repository.
+    pub secret_key: Option<String>,
+}
+
+impl Validate for WebhooksSettings {
+    fn problems(&self) -> Vec<String> {
+        match self.secret_cipher() {
+            Err(e) if self.secret_key.is_some() => {
+                vec![format!("webhooks.secret_key (WEBHOOKS_SECRET_KEY): {}", e)]
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl WebhooksSettings {
+    /// The cipher of the [`secret_key`](Self::secret_key).
+    pub fn secret_cipher(&self) -> Result<SecretCipher, String> {
+        SecretCipher::new(
+            self.secret_key
+                .as_deref()
+                .ok_or("webhooks.secret_key (WEBHOOKS_SECRET_KEY) isn't set")?,
+        )
+    }
+}
+
+pub type WebhookError = String;
+pub type WebhookResult<T> = Result<T, WebhookError>;
+
+/// A subscriber that wants to be notified of some film events.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Webhook {
+    pub id: Uuid,
+    pub url: String,
+    /// Key used to sign the payloads. Never sent back to clients.
+    #[serde(skip_serializing, default)]
+    pub secret: String,
+    pub events: Vec<FilmEventKind>,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+// the secret is left out, so it doesn't end up in the logs
+impl fmt::Debug for Webhook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Webhook")
+            .field("id", &self.id)
+            .field("url", &self.url)
+            .field("events", &self.events)
+            .field("created_at", &self.created_at)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Webhook {
+    pub fn is_subscribed_to(&self, kind: FilmEventKind) -> bool {
+        self.events.contains(&kind)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct CreateWebhook {
+    pub url: String,
+    pub secret: String,
+    pub events: Vec<FilmEventKind>,
+}
+
+impl fmt::Debug for CreateWebhook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CreateWebhook")
+            .field("url", &self.url)
+            .field("events", &self.events)
+            .finish_non_exhaustive()
+    }
+}
+
+impl CreateWebhook {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.url.starts_with("http://") || self.url.starts_with("https://")) {
+            return Err(format!("Invalid webhook url: {}", self.url));
+        }
+        if self.secret.is_empty() {
+            return Err("The webhook secret can't be empty".to_string());
+        }
+        if self.events.is_empty() {
+            return Err("The webhook must subscribe to at least one event".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// A single attempt to deliver an event to a webhook.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Delivery {
+    pub id: Uuid,
+    pub webhook_id: Uuid,
+    /// Shared by every attempt of the same event to the same webhook.
+    pub event_id: Uuid,
+    pub event: FilmEventKind,
+    pub attempt: u32,
+    pub status_code: Option<u16>,
+    pub error: Option<String>,
+    pub success: bool,
+    pub attempted_at: DateTime<Utc>,
+}
+
+#[cfg_attr(test, mockall::automock)]
+#[async_trait]
+pub trait WebhookRepository: Send + Sync + 'static {
+    async fn get_webhooks(&self) -> WebhookResult<Vec<Webhook>>;
+    async fn get_webhook(&self, id: &Uuid) -> WebhookResult<Webhook>;
+    async fn create_webhook(&self, create_webhook: &CreateWebhook) -> WebhookResult<Webhook>;
+    async fn delete_webhook(&self, id: &Uuid) -> WebhookResult<Uuid>;
+    async fn record_delivery(&self, delivery: &Delivery) -> WebhookResult<()>;
+    async fn get_deliveries(&self, webhook_id: &Uuid) -> WebhookResult<Vec<Delivery>>;
+}
+
+pub fn service<W: WebhookRepository>(cfg: &mut ServiceConfig) {
+    cfg.service(
+        web::scope("/webhooks")
+            // GET
+            .route("", web::get().to(get_all::<W>))
+            .route("/{webhook_id}", web::get().to(get::<W>))
+            .route("/{webhook_id}/deliveries", web::get().to(deliveries::<W>))
+            // POST
+            .route("", web::post().to(post::<W>))
+            // DELETE
+            .route("/{webhook_id}", web::delete().to(delete::<W>)),
+    );
+}
+
+async fn get_all<W: WebhookRepository>(repo: web::Data<W>) -> HttpResponse {
+    match repo.get_webhooks().await {
+        Ok(webhooks) => HttpResponse::Ok().json(webhooks),
+        Err(e) => {
+            HttpResponse::InternalServerError().body(format!("Internal server error: {:?}", e))
+        }
+    }
+}
+
+async fn get<W: WebhookRepository>(
+    webhook_id: web::Path<Uuid>,
+    repo: web::Data<W>,
+) -> HttpResponse {
+    match repo.get_webhook(&webhook_id).await {
+        Ok(webhook) => HttpResponse::Ok().json(webhook),
+        Err(_) => HttpResponse::NotFound().body("Not found"),
+    }
+}
+
+async fn deliveries<W: WebhookRepository>(
+    webhook_id: web::Path<Uuid>,
+    repo: web::Data<W>,
+) -> HttpResponse {
+    if repo.get_webhook(&webhook_id).await.is_err() {
+        return HttpResponse::NotFound().body("Not found");
+    }
+    match repo.get_deliveries(&webhook_id).await {
+        Ok(deliveries) => HttpResponse::Ok().json(deliveries),
+        Err(e) => {
+            HttpResponse::InternalServerError().body(format!("Internal server error: {:?}", e))
+        }
+    }
+}
+
+async fn post<W: WebhookRepository>(
+    create_webhook: web::Json<CreateWebhook>,
+    repo: web::Data<W>,
+) -> HttpResponse {
+    if let Err(e) = create_webhook.validate() {
+        return HttpResponse::BadRequest().body(e);
+    }
+    match repo.create_webhook(&create_webhook).await {
+        Ok(webhook) => HttpResponse::Ok().json(webhook),
+        Err(e) => {
+            HttpResponse::InternalServerError().body(format!("Internal server error: {:?}", e))
+        }
+    }
+}
+
+async fn delete<W: WebhookRepository>(
+    webhook_id: web::Path<Uuid>,
+    repo: web::Data<W>,
+) -> HttpResponse {
+    match repo.delete_webhook(&webhook_id).await {
+        Ok(id) => HttpResponse::Ok().json(id),
+        Err(e) => {
+            HttpResponse::InternalServerError().body(format!("Internal server error: {:?}", e))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{body::to_bytes, http::StatusCode};
+
+    fn create_test_webhook(id: Uuid) -> Webhook {
+        Webhook {
+            id,
+            url: "http://localhost/hook".to_string(),
+            secret: "secret".to_string(),
+            events: vec![FilmEventKind::Created],
+            created_at: Some(Utc::now()),
+        }
+    }
+
+    #[test]
+    fn create_webhook_validation() {
+        let valid = CreateWebhook {
+            url: "https://example.com/hook".to_string(),
+            secret: "secret".to_string(),
+            events: vec![FilmEventKind::Deleted],
+        };
+        assert!(valid.validate().is_ok());
+
+        let bad_url = CreateWebhook {
+            url: "ftp://example.com".to_string(),
+            ..valid.clone()
+        };
+        assert!(bad_url.validate().is_err());
+
+        let no_secret = CreateWebhook {
+            secret: "".to_string(),
+            ..valid.clone()
+        };
+        assert!(no_secret.validate().is_err());
+
+        let no_events = CreateWebhook {
+            events: vec![],
+            ..valid
+        };
+        assert!(no_events.validate().is_err());
+    }
+
+    #[test]
+    fn secret_is_not_serialized() {
+        let webhook = create_test_webhook(Uuid::new_v4());
+        let json = serde_json::to_string(&webhook).unwrap();
+        assert!(!json.contains("secret"));
+    }
+
+    #[test]
+    fn secret_is_not_debugged() {
+        let webhook = create_test_webhook(Uuid::new_v4());
+        let create_webhook = CreateWebhook {
+            url: webhook.url.clone(),
+            secret: webhook.secret.clone(),
+            events: webhook.events.clone(),
+        };
+
+        assert!(!format!("{:?}", webhook).contains("secret"));
+        assert!(!format!("{:?}", create_webhook).contains("secret"));
+    }
+
+    #[test]
+    fn secret_keys_are_checked() {
+        let settings = |secret_key: Option<&str>| WebhooksSettings {
+            secret_key: secret_key.map(str::to_string),
+            ..Default::default()
+        };
+
+        assert!(settings(None).problems().is_empty());
+        assert!(
+            settings(Some("MDEyMzQ1Njc4OWFiY2RlZjAxMjM0NTY3ODlhYmNkZWY="))
+                .problems()
+                .is_empty()
+        );
+        assert!(settings(Some("too short")).problems()[0].starts_with("webhooks.secret_key"));
+    }
+
+    #[actix_rt::test]
+    async fn get_works() {
+        let webhook_id = Uuid::new_v4();
+        let mut repo = MockWebhookRepository::default();
+        repo.expect_get_webhook()
+            .returning(|id| Ok(create_test_webhook(*id)));
+
+        let result = get(web::Path::from(webhook_id), web::Data::new(repo)).await;
+
+        let body = to_bytes(result.into_body()).await.unwrap();
+        let webhook = serde_json::from_slice::<'_, Webhook>(&body).unwrap();
+        assert_eq!(webhook.id, webhook_id);
+        assert!(webhook.secret.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn post_rejects_invalid_webhooks() {
+        let repo = MockWebhookRepository::default();
+        let create_webhook = CreateWebhook {
+            url: "not a url".to_string(),
+            secret: "secret".to_string(),
+            events: vec![FilmEventKind::Created],
+        };
+
+        let result = post(web::Json(create_webhook), web::Data::new(repo)).await;
+
+        assert_eq!(result.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_rt::test]
+    async fn deliveries_of_unknown_webhook_is_not_found() {
+        let mut repo = MockWebhookRepository::default();
+        repo.expect_get_webhook()
+            .returning(|id| Err(format!("Couldn't find webhook: {}", id)));
+
+        let result = deliveries(web::Path::from(Uuid::new_v4()), web::Data::new(repo)).await;
+
+        assert_eq!(result.status(), StatusCode::NOT_FOUND);
+    }
+}
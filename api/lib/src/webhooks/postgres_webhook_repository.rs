@@ -0,0 +1,225 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use super::{CreateWebhook, Delivery, SecretCipher, Webhook, WebhookRepository, WebhookResult};
+
+/// Keeps the webhooks in Postgres, with their secrets encrypted by the cipher.
+pub struct PostgresWebhookRepository {
+    pool: sqlx::PgPool,
+    cipher: SecretCipher,
+}
+
+impl PostgresWebhookRepository {
+    pub fn new(pool: sqlx::PgPool, cipher: SecretCipher) -> Self {
+        Self { pool, cipher }
+    }
+
+    /// Encrypts the secrets stored before they were encrypted, to run once
+    /// the schema is up to date.
+    pub async fn seal_plain_secrets(&self) -> WebhookResult<()> {
+        let webhooks = sqlx::query_as::<_, (Uuid, String)>(
+            r#"
+      SELECT id, secret
+      FROM webhooks
+      "#,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| e.to_string())?;
+        for (id, secret) in webhooks {
+            if SecretCipher::is_sealed(&secret) {
+                continue;
+            }
+            sqlx::query(
+                r#"
+      UPDATE webhooks
+      SET secret = $2
+      WHERE id = $1
+      "#,
+            )
+            .bind(id)
+            .bind(self.cipher.seal(&secret)?)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+
+    fn webhook(&self, row: WebhookRow) -> WebhookResult<Webhook> {
+        Webhook::try_from(WebhookRow {
+            secret: self.cipher.open(&row.secret)?,
+            ..row
+        })
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct WebhookRow {
+    id: Uuid,
+    url: String,
+    secret: String,
+    events: Vec<String>,
+    created_at: Option<DateTime<Utc>>,
+}
+
+impl TryFrom<WebhookRow> for Webhook {
+    type Error = String;
+
+    fn try_from(row: WebhookRow) -> Result<Self, Self::Error> {
+        Ok(Webhook {
+            id: row.id,
+            url: row.url,
+            secret: row.secret,
+            events: row
+                .events
+                .iter()
+                .map(|event| event.parse())
+                .collect::<Result<_, _>>()?,
+            created_at: row.created_at,
+        })
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct DeliveryRow {
+    id: Uuid,
+    webhook_id: Uuid,
+    event_id: Uuid,
+    event: String,
+    attempt: i32,
+    status_code: Option<i16>,
+    error: Option<String>,
+    success: bool,
+    attempted_at: DateTime<Utc>,
+}
+
+impl TryFrom<DeliveryRow> for Delivery {
+    type Error = String;
+
+    fn try_from(row: DeliveryRow) -> Result<Self, Self::Error> {
+        Ok(Delivery {
+            id: row.id,
+            webhook_id: row.webhook_id,
+            event_id: row.event_id,
+            event: row.event.parse()?,
+            attempt: row.attempt as u32,
+            status_code: row.status_code.map(|code| code as u16),
+            error: row.error,
+            success: row.success,
+            attempted_at: row.attempted_at,
+        })
+    }
+}
+
+#[async_trait]
+impl WebhookRepository for PostgresWebhookRepository {
+    async fn get_webhooks(&self) -> WebhookResult<Vec<Webhook>> {
+        sqlx::query_as::<_, WebhookRow>(
+            r#"
+      SELECT id, url, secret, events, created_at
+      FROM webhooks
+      "#,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .map(|row| self.webhook(row))
+        .collect()
+    }
+
+    async fn get_webhook(&self, webhook_id: &Uuid) -> WebhookResult<Webhook> {
+        sqlx::query_as::<_, WebhookRow>(
+            r#"
+      SELECT id, url, secret, events, created_at
+      FROM webhooks
+      WHERE id = $1
+      "#,
+        )
+        .bind(webhook_id)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|row| self.webhook(row))
+    }
+
+    async fn create_webhook(&self, create_webhook: &CreateWebhook) -> WebhookResult<Webhook> {
+        let events = create_webhook
+            .events
+            .iter()
+            .map(|event| event.to_string())
+            .collect::<Vec<_>>();
+        let secret = self.cipher.seal(&create_webhook.secret)?;
+        sqlx::query_as::<_, WebhookRow>(
+            r#"
+      INSERT INTO webhooks (url, secret, events)
+      VALUES ($1, $2, $3)
+      RETURNING id, url, secret, events, created_at
+      "#,
+        )
+        .bind(&create_webhook.url)
+        .bind(&secret)
+        .bind(&events)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|row| self.webhook(row))
+    }
+
+    async fn delete_webhook(&self, webhook_id: &Uuid) -> WebhookResult<Uuid> {
+        sqlx::query(
+            r#"
+      DELETE FROM webhooks
+      WHERE id = $1
+      "#,
+        )
+        .bind(webhook_id)
+        .execute(&self.pool)
+        .await
+        .map(|_| webhook_id.to_owned())
+        .map_err(|e| e.to_string())
+    }
+
+    async fn record_delivery(&self, delivery: &Delivery) -> WebhookResult<()> {
+        sqlx::query(
+            r#"
+      INSERT INTO webhook_deliveries
+        (id, webhook_id, event_id, event, attempt, status_code, error, success, attempted_at)
+      VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+      "#,
+        )
+        .bind(delivery.id)
+        .bind(delivery.webhook_id)
+        .bind(delivery.event_id)
+        .bind(delivery.event.to_string())
+        .bind(delivery.attempt as i32)
+        .bind(delivery.status_code.map(|code| code as i16))
+        .bind(&delivery.error)
+        .bind(delivery.success)
+        .bind(delivery.attempted_at)
+        .execute(&self.pool)
+        .await
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+    }
+
+    async fn get_deliveries(&self, webhook_id: &Uuid) -> WebhookResult<Vec<Delivery>> {
+        sqlx::query_as::<_, DeliveryRow>(
+            r#"
+      SELECT id, webhook_id, event_id, event, attempt, status_code, error, success, attempted_at
+      FROM webhook_deliveries
+      WHERE webhook_id = $1
+      ORDER BY attempted_at
+      "#,
+        )
+        .bind(webhook_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .map(Delivery::try_from)
+        .collect()
+    }
+}
@@ -0,0 +1,102 @@
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    Aes256Gcm, Key, Nonce,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use std::sync::Arc;
+
+/// Marks the stored secrets sealed by [`SecretCipher`], so the format can change.
+const PREFIX: &str = "v1:";
+const KEY_LENGTH: usize = 32;
+const NONCE_LENGTH: usize = 12;
+
+/// Encrypts the webhook secrets kept in the database with AES-256-GCM. They
+/// can't be hashed, the dispatcher needs them back to sign the payloads.
+#[derive(Clone)]
+pub struct SecretCipher(Arc<Aes256Gcm>);
+
+impl SecretCipher {
+    /// Builds the cipher from a base64 encoded 32 bytes key, like the ones
+    /// made by `openssl rand -base64 32`.
+    pub fn new(key: &str) -> Result<Self, String> {
+        let key = STANDARD
+            .decode(key.trim())
+            .map_err(|e| format!("the key isn't base64: {}", e))?;
+        if key.len() != KEY_LENGTH {
+            return Err(format!(
+                "the key is {} bytes long instead of {}",
+                key.len(),
+                KEY_LENGTH
+            ));
+        }
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key));
+        Ok(Self(Arc::new(cipher)))
+    }
+
+    /// Encrypts the secret with a random nonce, stored in front of it.
+    pub fn seal(&self, secret: &str) -> Result<String, String> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .0
+            .encrypt(&nonce, secret.as_bytes())
+            .map_err(|_| "the secret couldn't be encrypted".to_string())?;
+        let mut sealed = nonce.to_vec();
+        sealed.extend(ciphertext);
+        Ok(format!("{}{}", PREFIX, STANDARD.encode(sealed)))
+    }
+
+    /// Whether the stored secret was sealed, rather than stored as it is.
+    pub fn is_sealed(stored: &str) -> bool {
+        stored.starts_with(PREFIX)
+    }
+
+    /// Decrypts a secret sealed by [`SecretCipher::seal`] with the same key.
+    pub fn open(&self, sealed: &str) -> Result<String, String> {
+        let sealed = sealed
+            .strip_prefix(PREFIX)
+            .and_then(|sealed| STANDARD.decode(sealed).ok())
+            .filter(|sealed| sealed.len() > NONCE_LENGTH)
+            .ok_or_else(|| "the stored secret isn't encrypted".to_string())?;
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LENGTH);
+        let secret = self
+            .0
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| "the stored secret couldn't be decrypted with this key".to_string())?;
+        String::from_utf8(secret).map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &str = "MDEyMzQ1Njc4OWFiY2RlZjAxMjM0NTY3ODlhYmNkZWY=";
+
+    #[test]
+    fn secrets_are_sealed_and_opened() {
+        let cipher = SecretCipher::new(KEY).unwrap();
+
+        let sealed = cipher.seal("s3cr3t").unwrap();
+
+        assert!(SecretCipher::is_sealed(&sealed));
+        assert!(!SecretCipher::is_sealed("s3cr3t"));
+        assert!(!sealed.contains("s3cr3t"));
+        assert_ne!(sealed, cipher.seal("s3cr3t").unwrap());
+        assert_eq!(cipher.open(&sealed).unwrap(), "s3cr3t");
+    }
+
+    #[test]
+    fn secrets_only_open_with_their_key() {
+        let sealed = SecretCipher::new(KEY).unwrap().seal("s3cr3t").unwrap();
+        let other = SecretCipher::new(&STANDARD.encode([7u8; KEY_LENGTH])).unwrap();
+
+        assert!(other.open(&sealed).is_err());
+        assert!(other.open("s3cr3t").is_err());
+    }
+
+    #[test]
+    fn keys_are_32_bytes_of_base64() {
+        assert!(SecretCipher::new("not base64!").is_err());
+        assert!(SecretCipher::new(&STANDARD.encode([7u8; 16])).is_err());
+    }
+}
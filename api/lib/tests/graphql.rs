@@ -0,0 +1,126 @@
+mod integration {
+
+    use actix_web::{http::StatusCode, web, App};
+    use api_lib::film_repository::{FilmRepository, MemoryFilmRepository};
+    use serde_json::{json, Value};
+    use shared::models::CreateFilm;
+
+    fn create_test_create_film(id: &'static str) -> CreateFilm {
+        CreateFilm {
+            title: format!("title-{}", id),
+            director: format!("director-{}", id),
+            poster: format!("poster-{}", id),
+            year: 2001,
+        }
+    }
+
+    #[actix_rt::test]
+    async fn films_query_works() {
+        let repo = MemoryFilmRepository::default();
+        let film = repo
+            .create_film(&create_test_create_film("1"))
+            .await
+            .expect("create film failed");
+
+        let app = App::new()
+            .app_data(web::Data::new(repo))
+            .configure(api_lib::graphql::service::<MemoryFilmRepository>);
+        let app = actix_web::test::init_service(app).await;
+
+        let req = actix_web::test::TestRequest::post()
+            .uri("/graphql")
+            .set_json(json!({ "query": "{ films { id title director year } }" }))
+            .to_request();
+        let res = actix_web::test::call_service(&app, req).await;
+
+        assert_eq!(res.status(), StatusCode::OK);
+        let body: Value = actix_web::test::read_body_json(res).await;
+        assert_eq!(
+            body,
+            json!({
+                "data": {
+                    "films": [{
+                        "id": film.id.to_string(),
+                        "title": film.title,
+                        "director": film.director,
+                        "year": 2001
+                    }]
+                }
+            })
+        );
+    }
+
+    #[actix_rt::test]
+    async fn create_and_delete_mutations_work() {
+        let repo = web::Data::new(MemoryFilmRepository::default());
+
+        let app = App::new()
+            .app_data(repo.clone())
+            .configure(api_lib::graphql::service::<MemoryFilmRepository>);
+        let app = actix_web::test::init_service(app).await;
+
+        let req = actix_web::test::TestRequest::post()
+            .uri("/graphql")
+            .set_json(json!({
+                "query": "mutation Create($input: CreateFilmInput!) { createFilm(input: $input) { id title } }",
+                "variables": {
+                    "input": { "title": "Vertigo", "director": "Alfred Hitchcock", "year": 1958, "poster": "" }
+                }
+            }))
+            .to_request();
+        let body: Value = actix_web::test::call_and_read_body_json(&app, req).await;
+        let id = body["data"]["createFilm"]["id"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        assert_eq!(body["data"]["createFilm"]["title"], "Vertigo");
+        assert_eq!(repo.get_films().await.unwrap().len(), 1);
+
+        let req = actix_web::test::TestRequest::post()
+            .uri("/graphql")
+            .set_json(json!({
+                "query": format!(r#"mutation {{ deleteFilm(id: "{}") }}"#, id)
+            }))
+            .to_request();
+        let body: Value = actix_web::test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["data"]["deleteFilm"], id);
+        assert!(repo.get_films().await.unwrap().is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn invalid_query_returns_errors() {
+        let app = App::new()
+            .app_data(web::Data::new(MemoryFilmRepository::default()))
+            .configure(api_lib::graphql::service::<MemoryFilmRepository>);
+        let app = actix_web::test::init_service(app).await;
+
+        let req = actix_web::test::TestRequest::post()
+            .uri("/graphql")
+            .set_json(json!({ "query": "{ films { rating } }" }))
+            .to_request();
+        let body: Value = actix_web::test::call_and_read_body_json(&app, req).await;
+
+        assert!(body["errors"].as_array().is_some_and(|e| !e.is_empty()));
+    }
+
+    #[actix_rt::test]
+    async fn playground_is_served_in_debug_builds() {
+        let app = App::new()
+            .app_data(web::Data::new(MemoryFilmRepository::default()))
+            .configure(api_lib::graphql::service::<MemoryFilmRepository>);
+        let app = actix_web::test::init_service(app).await;
+
+        let req = actix_web::test::TestRequest::get()
+            .uri("/graphql")
+            .to_request();
+        let res = actix_web::test::call_service(&app, req).await;
+
+        if cfg!(debug_assertions) {
+            assert_eq!(res.status(), StatusCode::OK);
+            let body = actix_web::test::read_body(res).await;
+            assert!(String::from_utf8_lossy(&body).contains("GraphQL Playground"));
+        } else {
+            assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        }
+    }
+}
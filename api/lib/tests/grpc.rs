@@ -0,0 +1,174 @@
+mod integration {
+
+    use std::sync::Arc;
+
+    use api_lib::{
+        events::FilmEvents,
+        film_repository::{FilmRepository, MemoryFilmRepository, PublishingFilmRepository},
+        grpc::{
+            proto::{
+                film_event::Kind, film_service_client::FilmServiceClient, CreateFilmRequest,
+                DeleteFilmRequest, GetFilmRequest, ListFilmsRequest, UpdateFilmRequest,
+                WatchFilmsRequest,
+            },
+            FilmGrpcService,
+        },
+        supervisor::Shutdown,
+    };
+    use shared::models::CreateFilm;
+    use tonic::{transport::Channel, Code};
+
+    fn create_test_create_film(id: &'static str) -> CreateFilm {
+        CreateFilm {
+            title: format!("title-{}", id),
+            director: format!("director-{}", id),
+            poster: format!("poster-{}", id),
+            year: 2001,
+        }
+    }
+
+    /// Serves `service` on a random local port and connects a client to it.
+    async fn start<R: FilmRepository>(service: FilmGrpcService<R>) -> FilmServiceClient<Channel> {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        actix_rt::spawn(api_lib::grpc::serve(service, listener, Shutdown::never()));
+        FilmServiceClient::connect(format!("http://{}", address))
+            .await
+            .expect("couldn't connect to the grpc server")
+    }
+
+    #[actix_rt::test]
+    async fn list_and_get_work() {
+        let repo = Arc::new(MemoryFilmRepository::default());
+        let film = repo
+            .create_film(&create_test_create_film("1"))
+            .await
+            .unwrap();
+        let mut client = start(FilmGrpcService::new(repo)).await;
+
+        let films = client
+            .list_films(ListFilmsRequest {})
+            .await
+            .unwrap()
+            .into_inner()
+            .films;
+        assert_eq!(films.len(), 1);
+        assert_eq!(films[0].id, film.id.to_string());
+
+        let found = client
+            .get_film(GetFilmRequest {
+                id: film.id.to_string(),
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(found.title, film.title);
+        assert_eq!(found.year, 2001);
+        assert!(found.created_at.is_some());
+    }
+
+    #[actix_rt::test]
+    async fn create_update_and_delete_work() {
+        let repo = Arc::new(MemoryFilmRepository::default());
+        let mut client = start(FilmGrpcService::new(repo.clone())).await;
+
+        let created = client
+            .create_film(CreateFilmRequest {
+                title: "Vertigo".to_string(),
+                director: "Alfred Hitchcock".to_string(),
+                year: 1958,
+                poster: "".to_string(),
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(repo.get_films().await.unwrap().len(), 1);
+
+        let updated = client
+            .update_film(UpdateFilmRequest {
+                film: Some(api_lib::grpc::proto::Film {
+                    year: 1959,
+                    ..created.clone()
+                }),
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(updated.year, 1959);
+        assert!(updated.updated_at.is_some());
+
+        let deleted = client
+            .delete_film(DeleteFilmRequest {
+                id: created.id.clone(),
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(deleted.id, created.id);
+        assert!(repo.get_films().await.unwrap().is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn errors_are_mapped_to_status_codes() {
+        let mut client = start(FilmGrpcService::new(Arc::new(
+            MemoryFilmRepository::default(),
+        )))
+        .await;
+
+        let status = client
+            .get_film(GetFilmRequest {
+                id: uuid::Uuid::new_v4().to_string(),
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::NotFound);
+
+        let status = client
+            .get_film(GetFilmRequest {
+                id: "not-an-id".to_string(),
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+
+        let status = client
+            .update_film(UpdateFilmRequest { film: None })
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+
+        let status = client.watch_films(WatchFilmsRequest {}).await.unwrap_err();
+        assert_eq!(status.code(), Code::Unavailable);
+    }
+
+    #[actix_rt::test]
+    async fn watch_streams_changes() {
+        let events = FilmEvents::new();
+        let repo = Arc::new(PublishingFilmRepository::new(
+            MemoryFilmRepository::default(),
+            events.clone(),
+        ));
+        let mut client = start(FilmGrpcService::new(repo.clone()).with_events(events)).await;
+
+        let mut changes = client
+            .watch_films(WatchFilmsRequest {})
+            .await
+            .unwrap()
+            .into_inner();
+        let film = repo
+            .create_film(&create_test_create_film("1"))
+            .await
+            .unwrap();
+        repo.delete_film(&film.id).await.unwrap();
+
+        let created = changes.message().await.unwrap().unwrap();
+        assert_eq!(created.kind(), Kind::Created);
+        assert_eq!(created.film_id, film.id.to_string());
+        assert_eq!(created.film.unwrap().title, film.title);
+
+        let deleted = changes.message().await.unwrap().unwrap();
+        assert_eq!(deleted.kind(), Kind::Deleted);
+        assert_eq!(deleted.film_id, film.id.to_string());
+        assert!(deleted.film.is_none());
+    }
+}
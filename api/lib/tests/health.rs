@@ -1,7 +1,12 @@
+// the tests predate `call_service` taking the service by shared reference
+#[allow(clippy::unnecessary_mut_passed)]
 mod integration {
 
-    use actix_web::{http::StatusCode, App};
-    use api_lib::health::{service, API_VERSION};
+    use actix_web::{http::StatusCode, web, App};
+    use api_lib::{
+        film_repository::MemoryFilmRepository,
+        health::{ready_service, service, Health, HealthStatus, API_VERSION},
+    };
 
     #[actix_rt::test]
     async fn health_check_works() {
@@ -19,4 +24,37 @@ mod integration {
             .and_then(|h| h.to_str().ok());
         assert_eq!(data, Some(API_VERSION));
     }
+
+    #[actix_rt::test]
+    async fn live_works() {
+        let app = App::new().configure(service);
+        let app = actix_web::test::init_service(app).await;
+        let req = actix_web::test::TestRequest::get()
+            .uri("/health/live")
+            .to_request();
+
+        let health: Health = actix_web::test::call_and_read_body_json(&app, req).await;
+
+        assert_eq!(health.status, HealthStatus::Up);
+        assert_eq!(health.version, API_VERSION);
+        assert!(health.dependencies.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn ready_reports_the_repository() {
+        let app = App::new()
+            .app_data(web::Data::new(MemoryFilmRepository::new()))
+            .configure(ready_service::<MemoryFilmRepository>);
+        let app = actix_web::test::init_service(app).await;
+        let req = actix_web::test::TestRequest::get()
+            .uri("/health/ready")
+            .to_request();
+
+        let res = actix_web::test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let health: Health = actix_web::test::read_body_json(res).await;
+
+        assert_eq!(health.status, HealthStatus::Up);
+        assert_eq!(health.dependencies["films"].status, HealthStatus::Up);
+    }
 }
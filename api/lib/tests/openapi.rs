@@ -0,0 +1,43 @@
+mod integration {
+
+    use actix_web::{http::StatusCode, App};
+
+    const SPEC_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../openapi.json");
+
+    /// Fails when the committed `api/openapi.json` is out of date. Run the
+    /// tests with `UPDATE_OPENAPI=1` to regenerate it.
+    #[test]
+    fn committed_spec_is_up_to_date() {
+        let spec = api_lib::openapi::openapi().to_pretty_json().unwrap() + "\n";
+
+        if std::env::var_os("UPDATE_OPENAPI").is_some() {
+            std::fs::write(SPEC_PATH, &spec).expect("couldn't write the spec");
+        }
+
+        let committed = std::fs::read_to_string(SPEC_PATH).unwrap_or_default();
+        assert!(
+            committed == spec,
+            "api/openapi.json is out of date, run `UPDATE_OPENAPI=1 cargo test -p api-lib --test openapi` and commit it"
+        );
+    }
+
+    #[actix_rt::test]
+    async fn spec_and_docs_are_served() {
+        let app = App::new().configure(api_lib::openapi::service);
+        let app = actix_web::test::init_service(app).await;
+
+        let req = actix_web::test::TestRequest::get()
+            .uri("/openapi.json")
+            .to_request();
+        let spec: serde_json::Value = actix_web::test::call_and_read_body_json(&app, req).await;
+        assert_eq!(spec["info"]["title"], "Films API");
+        assert!(spec["paths"]["/v1/films/{film_id}"]["get"].is_object());
+        assert!(spec["components"]["schemas"]["Film"].is_object());
+
+        let req = actix_web::test::TestRequest::get()
+            .uri("/docs")
+            .to_request();
+        let res = actix_web::test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,63 @@
+mod integration {
+
+    use api_lib::film_repository::{FilmRepository, PostgresFilmRepository};
+    use shared::models::{CreateFilm, Film};
+    use sqlx::{postgres::PgPoolOptions, Executor};
+
+    /// Only available when `TEST_DATABASE_URL` points to a Postgres database,
+    /// in a schema of its own.
+    async fn postgres_repository() -> Option<PostgresFilmRepository> {
+        let url = std::env::var("TEST_DATABASE_URL").ok()?;
+        let schema = format!("test_{}", uuid::Uuid::new_v4().simple());
+        let search_path = format!("SET search_path TO {}, public", schema);
+        let pool = PgPoolOptions::new()
+            .max_connections(1)
+            .after_connect(move |conn, _| {
+                let search_path = search_path.clone();
+                Box::pin(async move { conn.execute(search_path.as_str()).await.map(|_| ()) })
+            })
+            .connect(&url)
+            .await
+            .expect("couldn't connect to the test database");
+        pool.execute(r#"CREATE EXTENSION IF NOT EXISTS "uuid-ossp" SCHEMA public"#)
+            .await
+            .expect("couldn't create the uuid extension");
+        pool.execute(format!("CREATE SCHEMA {}", schema).as_str())
+            .await
+            .expect("couldn't create the test schema");
+        pool.execute(include_str!("../../db/schema.sql"))
+            .await
+            .expect("couldn't create the schema");
+        Some(PostgresFilmRepository::new(pool))
+    }
+
+    #[actix_rt::test]
+    async fn updates_set_updated_at() {
+        let Some(repo) = postgres_repository().await else {
+            eprintln!("postgres is not available, skipping");
+            return;
+        };
+        let film = repo
+            .create_film(&CreateFilm {
+                title: "title".to_string(),
+                director: "director".to_string(),
+                year: 2001,
+                poster: "poster".to_string(),
+            })
+            .await
+            .unwrap();
+        assert!(film.updated_at.is_none());
+
+        let updated = repo
+            .update_film(&Film {
+                year: 1999,
+                ..film.clone()
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(updated.year, 1999);
+        assert_eq!(updated.created_at, film.created_at);
+        assert!(updated.updated_at.is_some());
+    }
+}
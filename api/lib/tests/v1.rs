@@ -1,8 +1,73 @@
+// the tests predate `call_service` taking the service by shared reference
+#[allow(clippy::unnecessary_mut_passed)]
 mod integration {
 
     use actix_web::{http::StatusCode, web, App};
-    use api_lib::film_repository::{FilmRepository, MemoryFilmRepository};
+    use api_lib::film_repository::{
+        FilmRepository, MemoryFilmRepository, PostgresFilmRepository, SqliteFilmRepository,
+    };
     use shared::models::{CreateFilm, Film};
+    use sqlx::{postgres::PgPoolOptions, sqlite::SqlitePoolOptions, Executor};
+
+    async fn memory_repository() -> Option<MemoryFilmRepository> {
+        Some(MemoryFilmRepository::default())
+    }
+
+    async fn sqlite_repository() -> Option<SqliteFilmRepository> {
+        // an in-memory database only lives as long as its connection
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .connect("sqlite::memory:")
+            .await
+            .expect("couldn't open the SQLite database");
+        let repo = SqliteFilmRepository::new(pool);
+        repo.migrate().await.expect("couldn't create the schema");
+        Some(repo)
+    }
+
+    /// Only available when `TEST_DATABASE_URL` points to a Postgres database.
+    /// Every test gets its own schema so they don't see each other's films.
+    async fn postgres_pool() -> Option<sqlx::PgPool> {
+        let url = std::env::var("TEST_DATABASE_URL").ok()?;
+        let schema = format!("test_{}", uuid::Uuid::new_v4().simple());
+        let search_path = format!("SET search_path TO {}, public", schema);
+        let pool = PgPoolOptions::new()
+            .max_connections(1)
+            .after_connect(move |conn, _| {
+                let search_path = search_path.clone();
+                Box::pin(async move { conn.execute(search_path.as_str()).await.map(|_| ()) })
+            })
+            .connect(&url)
+            .await
+            .expect("couldn't connect to the test database");
+        pool.execute(r#"CREATE EXTENSION IF NOT EXISTS "uuid-ossp" SCHEMA public"#)
+            .await
+            .expect("couldn't create the uuid extension");
+        pool.execute(format!("CREATE SCHEMA {}", schema).as_str())
+            .await
+            .expect("couldn't create the test schema");
+        pool.execute(include_str!("../../db/schema.sql"))
+            .await
+            .expect("couldn't create the schema");
+        Some(pool)
+    }
+
+    async fn postgres_repository() -> Option<PostgresFilmRepository> {
+        postgres_pool().await.map(PostgresFilmRepository::new)
+    }
+
+    /// Postgres with a healthy replica and one that is down, which reads must
+    /// skip over.
+    async fn postgres_replicated_repository() -> Option<PostgresFilmRepository> {
+        let pool = postgres_pool().await?;
+        let down = PgPoolOptions::new()
+            .acquire_timeout(std::time::Duration::from_millis(200))
+            .connect_lazy("postgres://postgres@127.0.0.1:1/films")
+            .expect("couldn't configure the replica");
+        Some(PostgresFilmRepository::new(pool.clone()).with_replicas(vec![down, pool]))
+    }
 
     fn create_test_film(id: &'static str) -> Film {
         Film {
@@ -25,9 +90,7 @@ mod integration {
         }
     }
 
-    #[actix_rt::test]
-    async fn get_films_works() {
-        let repo = MemoryFilmRepository::default();
+    async fn get_films_works<R: FilmRepository>(repo: R) {
         let create_film1 = create_test_create_film("1");
         let create_film2 = create_test_create_film("2");
         let _ = repo.create_film(&create_film1).await;
@@ -38,7 +101,7 @@ mod integration {
         let app = App::new()
             .app_data(repo.clone())
             .configure(api_lib::health::service)
-            .configure(api_lib::v1::service::<MemoryFilmRepository>);
+            .configure(api_lib::v1::service::<R>);
 
         let mut app = actix_web::test::init_service(app).await;
 
@@ -56,9 +119,7 @@ mod integration {
         assert_eq!(films.len(), 2);
     }
 
-    #[actix_rt::test]
-    async fn get_film_works() {
-        let repo = MemoryFilmRepository::default();
+    async fn get_film_works<R: FilmRepository>(repo: R) {
         let create_film = create_test_create_film("1");
         let film = repo
             .create_film(&create_film)
@@ -70,7 +131,7 @@ mod integration {
         let app = App::new()
             .app_data(repo.clone())
             .configure(api_lib::health::service)
-            .configure(api_lib::v1::service::<MemoryFilmRepository>);
+            .configure(api_lib::v1::service::<R>);
 
         let mut app = actix_web::test::init_service(app).await;
 
@@ -94,9 +155,7 @@ mod integration {
         assert!(result.updated_at.is_none());
     }
 
-    #[actix_rt::test]
-    async fn get_film_fails_if_file_is_not_present() {
-        let repo = MemoryFilmRepository::default();
+    async fn get_film_fails_if_file_is_not_present<R: FilmRepository>(repo: R) {
         let film = create_test_film("1");
 
         let repo = web::Data::new(repo);
@@ -104,7 +163,7 @@ mod integration {
         let app = App::new()
             .app_data(repo.clone())
             .configure(api_lib::health::service)
-            .configure(api_lib::v1::service::<MemoryFilmRepository>);
+            .configure(api_lib::v1::service::<R>);
 
         let mut app = actix_web::test::init_service(app).await;
 
@@ -118,9 +177,7 @@ mod integration {
         assert_eq!(res.status(), StatusCode::NOT_FOUND);
     }
 
-    #[actix_rt::test]
-    async fn create_film_works() {
-        let repo = MemoryFilmRepository::default();
+    async fn create_film_works<R: FilmRepository>(repo: R) {
         let create_film = create_test_create_film("1");
 
         let repo = web::Data::new(repo);
@@ -128,7 +185,7 @@ mod integration {
         let app = App::new()
             .app_data(repo.clone())
             .configure(api_lib::health::service)
-            .configure(api_lib::v1::service::<MemoryFilmRepository>);
+            .configure(api_lib::v1::service::<R>);
 
         let mut app = actix_web::test::init_service(app).await;
 
@@ -152,9 +209,7 @@ mod integration {
         assert!(created_file.updated_at.is_none());
     }
 
-    #[actix_rt::test]
-    async fn update_film_works() {
-        let repo = MemoryFilmRepository::default();
+    async fn update_film_works<R: FilmRepository>(repo: R) {
         let create_film = create_test_create_film("1");
         let created_file = repo.create_film(&create_film).await.unwrap();
 
@@ -163,7 +218,7 @@ mod integration {
         let app = App::new()
             .app_data(repo.clone())
             .configure(api_lib::health::service)
-            .configure(api_lib::v1::service::<MemoryFilmRepository>);
+            .configure(api_lib::v1::service::<R>);
 
         let mut app = actix_web::test::init_service(app).await;
 
@@ -194,9 +249,7 @@ mod integration {
         assert!(updated_file.updated_at.is_some());
     }
 
-    #[actix_rt::test]
-    async fn update_film_fails_if_file_is_not_present() {
-        let repo = MemoryFilmRepository::default();
+    async fn update_film_fails_if_file_is_not_present<R: FilmRepository>(repo: R) {
         let film = create_test_film("1");
 
         let repo = web::Data::new(repo);
@@ -204,7 +257,7 @@ mod integration {
         let app = App::new()
             .app_data(repo.clone())
             .configure(api_lib::health::service)
-            .configure(api_lib::v1::service::<MemoryFilmRepository>);
+            .configure(api_lib::v1::service::<R>);
 
         let mut app = actix_web::test::init_service(app).await;
 
@@ -219,9 +272,7 @@ mod integration {
         assert_eq!(res.status(), StatusCode::NOT_FOUND);
     }
 
-    #[actix_rt::test]
-    async fn delete_film_works() {
-        let repo = MemoryFilmRepository::default();
+    async fn delete_film_works<R: FilmRepository>(repo: R) {
         let create_film = create_test_create_film("1");
         let film = repo
             .create_film(&create_film)
@@ -233,7 +284,7 @@ mod integration {
         let app = App::new()
             .app_data(repo.clone())
             .configure(api_lib::health::service)
-            .configure(api_lib::v1::service::<MemoryFilmRepository>);
+            .configure(api_lib::v1::service::<R>);
 
         let mut app = actix_web::test::init_service(app).await;
 
@@ -252,16 +303,14 @@ mod integration {
         assert_eq!(deleted_id, film.id);
     }
 
-    #[actix_rt::test]
-    async fn delete_film_does_not_fail_if_film_is_not_present() {
-        let repo = MemoryFilmRepository::default();
+    async fn delete_film_does_not_fail_if_film_is_not_present<R: FilmRepository>(repo: R) {
         let film = create_test_film("1");
         let repo = web::Data::new(repo);
 
         let app = App::new()
             .app_data(repo.clone())
             .configure(api_lib::health::service)
-            .configure(api_lib::v1::service::<MemoryFilmRepository>);
+            .configure(api_lib::v1::service::<R>);
 
         let mut app = actix_web::test::init_service(app).await;
 
@@ -279,4 +328,101 @@ mod integration {
 
         assert_eq!(deleted_id, film.id);
     }
+
+    async fn delete_film_fails_if_film_is_not_present<R: FilmRepository>(repo: R) {
+        let film = create_test_film("1");
+        let repo = web::Data::new(repo);
+
+        let app = App::new()
+            .app_data(repo.clone())
+            .configure(api_lib::health::service)
+            .configure(api_lib::v1::service::<R>);
+
+        let mut app = actix_web::test::init_service(app).await;
+
+        let req = actix_web::test::TestRequest::delete()
+            .uri(&format!("/v1/films/{}", film.id))
+            .to_request();
+
+        let res = actix_web::test::call_service(&mut app, req).await;
+
+        assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[actix_rt::test]
+    async fn unreadable_requests_carry_the_request_id() {
+        let app = App::new()
+            .wrap(actix_web::middleware::from_fn(
+                api_lib::access_log::access_log,
+            ))
+            .app_data(web::Data::new(MemoryFilmRepository::default()))
+            .configure(api_lib::v1::service::<MemoryFilmRepository>);
+        let app = actix_web::test::init_service(app).await;
+
+        let requests = [
+            (
+                actix_web::test::TestRequest::get().uri("/v1/films/not-a-uuid"),
+                StatusCode::NOT_FOUND,
+            ),
+            (
+                actix_web::test::TestRequest::post()
+                    .uri("/v1/films")
+                    .insert_header(("content-type", "application/json"))
+                    .set_payload("{"),
+                StatusCode::BAD_REQUEST,
+            ),
+        ];
+        for (req, status) in requests {
+            let req = req.insert_header(("x-request-id", "abc")).to_request();
+            let res = actix_web::test::call_service(&app, req).await;
+            assert_eq!(res.status(), status);
+            let body = actix_web::test::read_body(res).await;
+            let body = String::from_utf8(body.to_vec()).unwrap();
+            assert!(body.ends_with("(request id: abc)"), "{}", body);
+        }
+    }
+
+    /// Runs every test above against the repository returned by `$repo`,
+    /// skipping them when it's not available.
+    macro_rules! backend_tests {
+        ($backend:ident, $repo:ident, [$($test:ident),* $(,)?]) => {
+            mod $backend {
+                $(
+                    #[actix_rt::test]
+                    async fn $test() {
+                        match super::$repo().await {
+                            Some(repo) => super::$test(repo).await,
+                            None => eprintln!("{} is not available, skipping", stringify!($backend)),
+                        }
+                    }
+                )*
+            }
+        };
+    }
+
+    macro_rules! all_backend_tests {
+        ($($backend:ident => $repo:ident, [$($only:ident),* $(,)?]),* $(,)?) => {
+            $(
+                backend_tests!($backend, $repo, [
+                    get_films_works,
+                    get_film_works,
+                    get_film_fails_if_file_is_not_present,
+                    create_film_works,
+                    update_film_works,
+                    update_film_fails_if_file_is_not_present,
+                    delete_film_works,
+                    $($only,)*
+                ]);
+            )*
+        };
+    }
+
+    // the memory repository ignores films that aren't there, the databases
+    // report them
+    all_backend_tests!(
+        memory => memory_repository, [delete_film_does_not_fail_if_film_is_not_present],
+        sqlite => sqlite_repository, [delete_film_fails_if_film_is_not_present],
+        postgres => postgres_repository, [delete_film_fails_if_film_is_not_present],
+        postgres_replicated => postgres_replicated_repository, [delete_film_fails_if_film_is_not_present],
+    );
 }
@@ -0,0 +1,136 @@
+mod integration {
+
+    use actix_web::{
+        http::{header, StatusCode},
+        middleware::from_fn,
+        web::{self, ServiceConfig},
+    };
+    use api_lib::{
+        access_log::{access_log, REQUEST_ID},
+        film_repository::MemoryFilmRepository,
+        testing,
+        v2::{ErrorBody, ErrorCode, FilmPage, FilmPatch},
+    };
+    use chrono::{Duration, TimeZone, Utc};
+    use shared::models::{CreateFilm, Film};
+
+    fn v2(repo: MemoryFilmRepository) -> impl FnOnce(&mut ServiceConfig) {
+        move |cfg| {
+            cfg.app_data(web::Data::new(repo)).service(
+                web::scope("")
+                    .wrap(from_fn(access_log))
+                    .configure(api_lib::v2::service::<MemoryFilmRepository>),
+            );
+        }
+    }
+
+    fn create_film(id: usize) -> CreateFilm {
+        CreateFilm {
+            title: format!("title-{}", id),
+            director: format!("director-{}", id),
+            poster: format!("poster-{}", id),
+            year: 2001,
+        }
+    }
+
+    #[actix_rt::test]
+    async fn films_go_through_their_whole_life() {
+        let app = testing::app(v2(MemoryFilmRepository::default())).await;
+
+        // created
+        let req = actix_web::test::TestRequest::post()
+            .uri("/v2/films")
+            .set_json(create_film(1))
+            .to_request();
+        let res = actix_web::test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::CREATED);
+        let location = res.headers().get(header::LOCATION).unwrap().clone();
+        let film: Film = actix_web::test::read_body_json(res).await;
+        assert_eq!(location, format!("/v2/films/{}", film.id).as_str());
+
+        // patched
+        let req = actix_web::test::TestRequest::patch()
+            .uri(location.to_str().unwrap())
+            .set_json(FilmPatch {
+                year: Some(1999),
+                ..Default::default()
+            })
+            .to_request();
+        let patched: Film = actix_web::test::call_and_read_body_json(&app, req).await;
+        assert_eq!(patched.year, 1999);
+        assert_eq!(patched.title, film.title);
+
+        // replaced
+        let req = actix_web::test::TestRequest::put()
+            .uri(location.to_str().unwrap())
+            .set_json(create_film(2))
+            .to_request();
+        let replaced: Film = actix_web::test::call_and_read_body_json(&app, req).await;
+        assert_eq!(replaced.id, film.id);
+        assert_eq!(replaced.title, "title-2");
+        assert_eq!(replaced.year, 2001);
+
+        // deleted
+        let req = actix_web::test::TestRequest::delete()
+            .uri(location.to_str().unwrap())
+            .to_request();
+        let res = actix_web::test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+
+        let req = actix_web::test::TestRequest::get()
+            .uri(location.to_str().unwrap())
+            .to_request();
+        let res = actix_web::test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        let error: ErrorBody = actix_web::test::read_body_json(res).await;
+        assert_eq!(error.code, ErrorCode::NotFound);
+    }
+
+    #[actix_rt::test]
+    async fn films_are_listed_in_pages() {
+        // a minute apart, so the order doesn't depend on the random ids
+        let first = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let films = (0..5).map(|id| Film {
+            id: uuid::Uuid::new_v4(),
+            title: format!("title-{}", id),
+            created_at: Some(first + Duration::minutes(id)),
+            ..Default::default()
+        });
+        let app = testing::app(v2(MemoryFilmRepository::with_films(films))).await;
+
+        let req = actix_web::test::TestRequest::get()
+            .uri("/v2/films?page=3&per_page=2")
+            .to_request();
+        let page: FilmPage = actix_web::test::call_and_read_body_json(&app, req).await;
+
+        assert_eq!(page.data.len(), 1);
+        assert_eq!(page.data[0].title, "title-4");
+        assert_eq!((page.total, page.total_pages), (5, 3));
+    }
+
+    #[actix_rt::test]
+    async fn unreadable_requests_are_typed_errors() {
+        let app = testing::app(v2(MemoryFilmRepository::default())).await;
+
+        for req in [
+            actix_web::test::TestRequest::get().uri("/v2/films/not-a-uuid"),
+            actix_web::test::TestRequest::get().uri("/v2/films?page=first"),
+            actix_web::test::TestRequest::post()
+                .uri("/v2/films")
+                .insert_header(header::ContentType::json())
+                .set_payload(r#"{"title": "Missing fields"}"#),
+            actix_web::test::TestRequest::patch()
+                .uri(&format!("/v2/films/{}", uuid::Uuid::new_v4()))
+                .insert_header(header::ContentType::json())
+                .set_payload(r#"{"rating": 5}"#),
+        ] {
+            let res = actix_web::test::call_service(&app, req.to_request()).await;
+
+            assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+            let request_id = res.headers().get(REQUEST_ID).unwrap().clone();
+            let error: ErrorBody = actix_web::test::read_body_json(res).await;
+            assert_eq!(error.code, ErrorCode::InvalidRequest);
+            assert_eq!(error.request_id, request_id.to_str().unwrap());
+        }
+    }
+}
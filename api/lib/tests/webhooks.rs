@@ -0,0 +1,320 @@
+mod integration {
+
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+        time::Duration,
+    };
+
+    use actix_web::{http::StatusCode, web, App, HttpRequest, HttpResponse, HttpServer};
+    use api_lib::{
+        events::{FilmEvent, FilmEventKind, FilmEvents},
+        film_repository::{MemoryFilmRepository, PublishingFilmRepository},
+        supervisor::Supervisor,
+        webhooks::{
+            sign, CreateWebhook, Delivery, MemoryWebhookRepository, RetryPolicy, Webhook,
+            WebhookDispatcher, WebhookRepository, SIGNATURE_HEADER,
+        },
+    };
+    use shared::models::{CreateFilm, Film};
+
+    struct Received {
+        signature: String,
+        event: String,
+        body: Vec<u8>,
+    }
+
+    #[derive(Default)]
+    struct Stub {
+        received: Mutex<Vec<Received>>,
+        failures: AtomicUsize,
+    }
+
+    async fn receive(req: HttpRequest, body: web::Bytes, stub: web::Data<Stub>) -> HttpResponse {
+        let header = |name: &str| {
+            req.headers()
+                .get(name)
+                .and_then(|h| h.to_str().ok())
+                .unwrap_or_default()
+                .to_string()
+        };
+        stub.received.lock().unwrap().push(Received {
+            signature: header(SIGNATURE_HEADER),
+            event: header("X-Webhook-Event"),
+            body: body.to_vec(),
+        });
+        let failing = stub
+            .failures
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+            .is_ok();
+        if failing {
+            HttpResponse::InternalServerError().finish()
+        } else {
+            HttpResponse::Ok().finish()
+        }
+    }
+
+    /// Starts a local receiver that fails the first `failures` requests.
+    fn start_stub(failures: usize) -> (String, Arc<Stub>) {
+        let stub = Arc::new(Stub {
+            failures: AtomicUsize::new(failures),
+            ..Default::default()
+        });
+        let data = web::Data::from(stub.clone());
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(data.clone())
+                .route("/hook", web::post().to(receive))
+        })
+        .workers(1)
+        .listen(listener)
+        .unwrap()
+        .run();
+        actix_rt::spawn(server);
+        (format!("http://127.0.0.1:{}/hook", port), stub)
+    }
+
+    /// Delivers to the local receivers of [`start_stub`].
+    fn dispatcher(
+        webhooks: &Arc<MemoryWebhookRepository>,
+    ) -> WebhookDispatcher<MemoryWebhookRepository> {
+        WebhookDispatcher::new(webhooks.clone())
+            .with_retry_policy(fast_retries())
+            .allow_private_addresses(true)
+    }
+
+    fn fast_retries() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(10),
+            max_delay: Duration::from_millis(50),
+        }
+    }
+
+    fn create_test_create_film(id: &'static str) -> CreateFilm {
+        CreateFilm {
+            title: format!("title-{}", id),
+            director: format!("director-{}", id),
+            poster: format!("poster-{}", id),
+            year: 2001,
+        }
+    }
+
+    async fn create_webhook(
+        repo: &MemoryWebhookRepository,
+        url: &str,
+        events: Vec<FilmEventKind>,
+    ) -> Webhook {
+        repo.create_webhook(&CreateWebhook {
+            url: url.to_string(),
+            secret: "top-secret".to_string(),
+            events,
+        })
+        .await
+        .expect("create webhook failed")
+    }
+
+    #[actix_rt::test]
+    async fn film_creation_is_delivered_signed() {
+        let (url, stub) = start_stub(0);
+        let events = FilmEvents::new();
+        let webhooks = Arc::new(MemoryWebhookRepository::default());
+        let _dispatcher = dispatcher(&webhooks).spawn(events.subscribe());
+
+        let films = web::Data::new(PublishingFilmRepository::new(
+            MemoryFilmRepository::default(),
+            events,
+        ));
+        let app = App::new()
+            .app_data(films)
+            .app_data(web::Data::from(webhooks.clone()))
+            .configure(api_lib::v1::service::<PublishingFilmRepository<MemoryFilmRepository>>)
+            .configure(api_lib::webhooks::service::<MemoryWebhookRepository>);
+        let app = actix_web::test::init_service(app).await;
+
+        let req = actix_web::test::TestRequest::post()
+            .uri("/webhooks")
+            .set_json(CreateWebhook {
+                url,
+                secret: "top-secret".to_string(),
+                events: vec![FilmEventKind::Created],
+            })
+            .to_request();
+        let webhook: Webhook = actix_web::test::call_and_read_body_json(&app, req).await;
+
+        let req = actix_web::test::TestRequest::post()
+            .uri("/v1/films")
+            .set_json(create_test_create_film("1"))
+            .to_request();
+        let film: Film = actix_web::test::call_and_read_body_json(&app, req).await;
+
+        let mut deliveries: Vec<Delivery> = vec![];
+        for _ in 0..100 {
+            let req = actix_web::test::TestRequest::get()
+                .uri(&format!("/webhooks/{}/deliveries", webhook.id))
+                .to_request();
+            deliveries = actix_web::test::call_and_read_body_json(&app, req).await;
+            if !deliveries.is_empty() {
+                break;
+            }
+            actix_rt::time::sleep(Duration::from_millis(20)).await;
+        }
+
+        assert_eq!(deliveries.len(), 1);
+        assert!(deliveries[0].success);
+        assert_eq!(deliveries[0].status_code, Some(200));
+        assert_eq!(deliveries[0].event, FilmEventKind::Created);
+
+        let received = stub.received.lock().unwrap();
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0].event, "film.created");
+        assert_eq!(
+            received[0].signature,
+            format!("sha256={}", sign("top-secret", &received[0].body))
+        );
+        let payload: serde_json::Value = serde_json::from_slice(&received[0].body).unwrap();
+        assert_eq!(payload["event"], "film.created");
+        assert_eq!(payload["data"]["id"], film.id.to_string());
+        assert_eq!(payload["data"]["title"], film.title);
+    }
+
+    #[actix_rt::test]
+    async fn failed_deliveries_are_retried_and_recorded() {
+        let (url, stub) = start_stub(2);
+        let webhooks = Arc::new(MemoryWebhookRepository::default());
+        let webhook = create_webhook(&webhooks, &url, vec![FilmEventKind::Deleted]).await;
+        let dispatcher = dispatcher(&webhooks);
+
+        dispatcher
+            .dispatch(&FilmEvent::Deleted(uuid::Uuid::new_v4()))
+            .await;
+
+        let deliveries = webhooks.get_deliveries(&webhook.id).await.unwrap();
+        assert_eq!(deliveries.len(), 3);
+        assert_eq!(
+            deliveries.iter().map(|d| d.attempt).collect::<Vec<_>>(),
+            vec![1, 2, 3]
+        );
+        assert_eq!(
+            deliveries.iter().map(|d| d.success).collect::<Vec<_>>(),
+            vec![false, false, true]
+        );
+        assert_eq!(deliveries[0].status_code, Some(500));
+        assert!(deliveries
+            .iter()
+            .all(|d| d.event_id == deliveries[0].event_id));
+        assert_eq!(stub.received.lock().unwrap().len(), 3);
+    }
+
+    #[actix_rt::test]
+    async fn delivery_gives_up_after_max_attempts() {
+        let (url, stub) = start_stub(10);
+        let webhooks = Arc::new(MemoryWebhookRepository::default());
+        let webhook = create_webhook(&webhooks, &url, vec![FilmEventKind::Updated]).await;
+        let dispatcher = dispatcher(&webhooks);
+
+        dispatcher
+            .dispatch(&FilmEvent::Updated(Film::default()))
+            .await;
+
+        let deliveries = webhooks.get_deliveries(&webhook.id).await.unwrap();
+        assert_eq!(deliveries.len(), 3);
+        assert!(deliveries.iter().all(|d| !d.success && d.error.is_some()));
+        assert_eq!(stub.received.lock().unwrap().len(), 3);
+    }
+
+    #[actix_rt::test]
+    async fn queued_events_are_delivered_on_shutdown() {
+        let (url, stub) = start_stub(0);
+        let events = FilmEvents::new();
+        let webhooks = Arc::new(MemoryWebhookRepository::default());
+        let webhook = create_webhook(&webhooks, &url, vec![FilmEventKind::Deleted]).await;
+        let dispatcher = dispatcher(&webhooks);
+        let film_events = events.subscribe();
+        for _ in 0..3 {
+            events.publish(FilmEvent::Deleted(uuid::Uuid::new_v4()));
+        }
+
+        // the dispatcher only starts once asked to stop
+        let mut supervisor = Supervisor::new();
+        supervisor.spawn("webhooks", |shutdown| dispatcher.run(film_events, shutdown));
+        supervisor.shutdown(Duration::from_secs(5)).await;
+
+        let deliveries = webhooks.get_deliveries(&webhook.id).await.unwrap();
+        assert_eq!(deliveries.len(), 3);
+        assert_eq!(stub.received.lock().unwrap().len(), 3);
+    }
+
+    #[actix_rt::test]
+    async fn private_addresses_are_refused() {
+        let (url, stub) = start_stub(0);
+        let webhooks = Arc::new(MemoryWebhookRepository::default());
+        let by_address = create_webhook(&webhooks, &url, vec![FilmEventKind::Deleted]).await;
+        let by_name = create_webhook(
+            &webhooks,
+            &url.replace("127.0.0.1", "localhost"),
+            vec![FilmEventKind::Deleted],
+        )
+        .await;
+        let dispatcher = WebhookDispatcher::new(webhooks.clone()).with_retry_policy(fast_retries());
+
+        dispatcher
+            .dispatch(&FilmEvent::Deleted(uuid::Uuid::new_v4()))
+            .await;
+
+        for webhook in [&by_address, &by_name] {
+            let deliveries = webhooks.get_deliveries(&webhook.id).await.unwrap();
+            assert!(deliveries.iter().all(|d| !d.success), "{:?}", deliveries);
+        }
+        // a refused address isn't tried again
+        assert_eq!(
+            webhooks.get_deliveries(&by_address.id).await.unwrap().len(),
+            1
+        );
+        assert!(stub.received.lock().unwrap().is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn unsubscribed_events_are_not_delivered() {
+        let (url, stub) = start_stub(0);
+        let webhooks = Arc::new(MemoryWebhookRepository::default());
+        let webhook = create_webhook(&webhooks, &url, vec![FilmEventKind::Created]).await;
+        let dispatcher = dispatcher(&webhooks);
+
+        dispatcher
+            .dispatch(&FilmEvent::Deleted(uuid::Uuid::new_v4()))
+            .await;
+
+        assert!(webhooks
+            .get_deliveries(&webhook.id)
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(stub.received.lock().unwrap().is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn create_invalid_webhook_fails() {
+        let webhooks = web::Data::new(MemoryWebhookRepository::default());
+        let app = App::new()
+            .app_data(webhooks)
+            .configure(api_lib::webhooks::service::<MemoryWebhookRepository>);
+        let app = actix_web::test::init_service(app).await;
+
+        let req = actix_web::test::TestRequest::post()
+            .uri("/webhooks")
+            .set_json(CreateWebhook {
+                url: "localhost".to_string(),
+                secret: "top-secret".to_string(),
+                events: vec![FilmEventKind::Created],
+            })
+            .to_request();
+        let res = actix_web::test::call_service(&app, req).await;
+
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    }
+}
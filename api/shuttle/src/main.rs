@@ -1,33 +1,154 @@
-use actix_files::Files;
-use actix_web::web::{self, ServiceConfig};
+use actix_web::{
+    dev::Service,
+    middleware::{from_fn, Compress},
+    web::{self, ServiceConfig},
+};
+use api_lib::{
+    access_log::access_log,
+    database::DatabaseError,
+    deprecation::Deprecation,
+    events::FilmEvents,
+    film_repository::{
+        read_your_writes, MeteredFilmRepository, PostgresFilmRepository, PublishingFilmRepository,
+    },
+    metrics::Metrics,
+    rate_limit::RateLimit,
+    security_headers::security_headers,
+    settings::Settings,
+    telemetry::trace_requests,
+    webhooks::{PostgresWebhookRepository, WebhookDispatcher},
+};
 use shuttle_actix_web::ShuttleActixWeb;
 use shuttle_runtime::CustomError;
 use sqlx::Executor;
 
+type Repository = PublishingFilmRepository<MeteredFilmRepository<PostgresFilmRepository>>;
+
 #[shuttle_runtime::main]
 async fn actix_web(
-    #[shuttle_shared_db::Postgres] pool: sqlx::PgPool,
+    #[shuttle_shared_db::Postgres] database_url: String,
+    #[shuttle_runtime::Secrets] secrets: shuttle_runtime::SecretStore,
 ) -> ShuttleActixWeb<impl FnOnce(&mut ServiceConfig) + Send + Clone + 'static> {
+    // same settings as api-actix, with the provisioned database. Shuttle
+    // can't set variables, they're read from the secrets instead
+    let mut settings =
+        Settings::read_with_secrets(|name| secrets.get(name)).map_err(CustomError::new)?;
+    settings.database.url = Some(database_url);
+    settings.validate().map_err(CustomError::new)?;
+
+    // connect with the configured pool settings
+    let pool = settings
+        .database
+        .connect()
+        .await
+        .map_err(CustomError::new)?;
+    let replicas = settings
+        .database
+        .connect_replicas()
+        .map_err(CustomError::new)?;
+
     // initialize the database if not already initialized
     pool.execute(include_str!("../../db/schema.sql"))
         .await
+        .map_err(DatabaseError::Schema)
         .map_err(CustomError::new)?;
 
+    // report the pools and time the repository at /metrics, scraped with
+    // the metrics token
+    let metrics = Metrics::new();
+    metrics.register_pool("primary", pool.clone());
+    for (i, replica) in replicas.iter().enumerate() {
+        metrics.register_pool(&format!("replica-{}", i), replica.clone());
+    }
+
     // create a film repository. In this case for postgres.
-    let film_repository = api_lib::film_repository::PostgresFilmRepository::new(pool);
+    // Every change is published so webhooks can be notified.
+    let events = FilmEvents::new();
+    let film_repository = PublishingFilmRepository::new(
+        MeteredFilmRepository::new(
+            PostgresFilmRepository::new(pool.clone()).with_replicas(replicas),
+            metrics.clone(),
+        ),
+        events.clone(),
+    );
     let film_repository = web::Data::new(film_repository);
 
+    // seed the database with the bundled fixtures when asked to, with the
+    // `SEED_DATABASE` secret
+    if settings.repository.seed {
+        api_lib::seed::seed(film_repository.get_ref())
+            .await
+            .map_err(CustomError::msg)?;
+    }
+
+    // deliver film events to the registered webhooks
+    // with their secrets encrypted, including the ones stored before
+    let cipher = settings
+        .webhooks
+        .secret_cipher()
+        .map_err(CustomError::msg)?;
+    let webhooks = PostgresWebhookRepository::new(pool, cipher);
+    webhooks
+        .seal_plain_secrets()
+        .await
+        .map_err(CustomError::msg)?;
+    let webhooks = web::Data::new(webhooks);
+    // shuttle stops the service itself, so the dispatcher runs until then
+    WebhookDispatcher::new(webhooks.clone().into_inner())
+        .allow_private_addresses(settings.webhooks.allow_private_addresses)
+        .spawn(events.subscribe());
+    let events = web::Data::new(events);
+
+    // keep track of who is viewing or editing each film
+    let presence = web::Data::new(api_lib::presence::PresenceHub::new());
+
+    // limit every client, shared by every worker
+    let rate_limit = RateLimit::in_memory(settings.rate_limit.clone());
+
+    // CSP, HSTS & co., allowing the inline scripts of the front end by hash
+    let security_settings = settings
+        .security_headers
+        .clone()
+        .allow_inline_scripts(std::path::Path::new("static/index.html"));
+
     // start the service
     let config = move |cfg: &mut ServiceConfig| {
         cfg.service(
-            web::scope("/api")
-                .app_data(film_repository)
-                .configure(api_lib::health::service)
-                .configure(
-                    api_lib::v1::service::<api_lib::film_repository::PostgresFilmRepository>,
-                ),
-        )
-        .service(Files::new("/", "static").index_file("index.html"));
+            web::scope("")
+                // CSP, HSTS & co. on the API and the front end
+                .wrap(security_headers(&security_settings))
+                .service(
+                    web::scope("/api")
+                        // every request reads its own writes, even with read replicas
+                        .wrap_fn(|req, srv| read_your_writes(srv.call(req)))
+                        .wrap(rate_limit.middleware())
+                        // Deprecation and Sunset headers on the v1 routes
+                        .wrap(Deprecation::new(&settings.deprecation).middleware())
+                        .wrap(api_lib::cors::cors(&settings.cors))
+                        .wrap(metrics.middleware())
+                        // gives every request an id and logs it once served
+                        .wrap(from_fn(access_log))
+                        // continues the trace of callers sending a `traceparent`
+                        .wrap(from_fn(trace_requests))
+                        .wrap(Compress::default())
+                        .app_data(film_repository)
+                        .app_data(presence)
+                        .app_data(webhooks)
+                        .app_data(events)
+                        .configure(api_lib::health::service)
+                        .configure(api_lib::health::ready_service::<Repository>)
+                        .configure(api_lib::openapi::service)
+                        .configure(api_lib::presence::service)
+                        .configure(api_lib::v1::service::<Repository>)
+                        .configure(api_lib::v2::service::<Repository>)
+                        .configure(api_lib::graphql::service::<Repository>)
+                        .configure(api_lib::webhooks::service::<PostgresWebhookRepository>),
+                )
+                .app_data(web::Data::new(metrics))
+                .configure(api_lib::metrics::service(&settings.metrics))
+                // last, the front end answers every other path
+                .configure(api_lib::static_files::service("static")),
+        );
     };
 
     Ok(config.into())
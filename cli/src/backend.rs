@@ -0,0 +1,50 @@
+use api_lib::film_repository::{FilmRepository, PostgresFilmRepository};
+use client::FilmsClient;
+use shared::models::{CreateFilm, Film};
+use uuid::Uuid;
+
+/// Where the films are read from and written to.
+pub enum Backend {
+    Api(FilmsClient),
+    Postgres(PostgresFilmRepository),
+}
+
+impl Backend {
+    pub async fn get_films(&self) -> Result<Vec<Film>, String> {
+        match self {
+            Backend::Api(client) => client.list().await.map_err(|e| e.to_string()),
+            Backend::Postgres(repo) => repo.get_films().await.map_err(|e| e.to_string()),
+        }
+    }
+
+    pub async fn get_film(&self, id: &Uuid) -> Result<Film, String> {
+        match self {
+            Backend::Api(client) => client.get(id).await.map_err(|e| e.to_string()),
+            Backend::Postgres(repo) => repo.get_film(id).await.map_err(|e| e.to_string()),
+        }
+    }
+
+    pub async fn create_film(&self, create_film: &CreateFilm) -> Result<Film, String> {
+        match self {
+            Backend::Api(client) => client.create(create_film).await.map_err(|e| e.to_string()),
+            Backend::Postgres(repo) => repo
+                .create_film(create_film)
+                .await
+                .map_err(|e| e.to_string()),
+        }
+    }
+
+    pub async fn update_film(&self, film: &Film) -> Result<Film, String> {
+        match self {
+            Backend::Api(client) => client.update(film).await.map_err(|e| e.to_string()),
+            Backend::Postgres(repo) => repo.update_film(film).await.map_err(|e| e.to_string()),
+        }
+    }
+
+    pub async fn delete_film(&self, id: &Uuid) -> Result<Uuid, String> {
+        match self {
+            Backend::Api(client) => client.delete(id).await.map_err(|e| e.to_string()),
+            Backend::Postgres(repo) => repo.delete_film(id).await.map_err(|e| e.to_string()),
+        }
+    }
+}
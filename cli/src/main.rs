@@ -0,0 +1,236 @@
+mod backend;
+mod output;
+
+use std::{io::Read, path::PathBuf};
+
+use api_lib::film_repository::PostgresFilmRepository;
+use backend::Backend;
+use clap::{Parser, Subcommand};
+use client::FilmsClient;
+use output::Format;
+use shared::models::{CreateFilm, Film};
+use uuid::Uuid;
+
+/// Manage the films catalogue.
+#[derive(Parser, Debug)]
+#[command(name = "films-cli", version)]
+struct Cli {
+    /// Url where the API is mounted.
+    #[arg(
+        long,
+        env = "FILMS_API_URL",
+        default_value = "http://localhost:8080/api",
+        global = true
+    )]
+    api_url: String,
+    /// Bearer token sent to the API.
+    #[arg(long, env = "FILMS_API_TOKEN", global = true)]
+    token: Option<String>,
+    /// Talk directly to this Postgres database instead of the API.
+    #[arg(long, global = true)]
+    database_url: Option<String>,
+    /// Output format.
+    #[arg(long, short, value_enum, default_value_t = Format::Table, global = true)]
+    output: Format,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// List the films, optionally only those whose title or director
+    /// contains the search text.
+    List {
+        #[arg(long, short)]
+        search: Option<String>,
+    },
+    /// Show a film.
+    Get { id: Uuid },
+    /// Add a film.
+    Add {
+        #[arg(long)]
+        title: String,
+        #[arg(long)]
+        director: String,
+        #[arg(long)]
+        year: u16,
+        #[arg(long, default_value = "")]
+        poster: String,
+    },
+    /// Change some fields of a film.
+    Edit {
+        id: Uuid,
+        #[arg(long)]
+        title: Option<String>,
+        #[arg(long)]
+        director: Option<String>,
+        #[arg(long)]
+        year: Option<u16>,
+        #[arg(long)]
+        poster: Option<String>,
+    },
+    /// Delete a film.
+    Delete { id: Uuid },
+    /// Add every film of a JSON array (`-` reads it from stdin).
+    Import { file: PathBuf },
+    /// Write every film as a JSON array (to stdout when no file is given).
+    Export { file: Option<PathBuf> },
+}
+
+#[tokio::main]
+async fn main() {
+    dotenv::dotenv().ok();
+    let cli = Cli::parse();
+
+    if let Err(e) = run(cli).await {
+        eprintln!("🔥 {}", e);
+        std::process::exit(1);
+    }
+}
+
+async fn run(cli: Cli) -> Result<(), String> {
+    let backend = match &cli.database_url {
+        Some(database_url) => {
+            let pool = sqlx::PgPool::connect(database_url)
+                .await
+                .map_err(|e| format!("Couldn't connect to the database: {}", e))?;
+            Backend::Postgres(PostgresFilmRepository::new(pool))
+        }
+        None => {
+            let client = FilmsClient::new(&cli.api_url);
+            Backend::Api(match &cli.token {
+                Some(token) => client.with_token(token),
+                None => client,
+            })
+        }
+    };
+
+    match cli.command {
+        Command::List { search } => {
+            let mut films = backend.get_films().await?;
+            if let Some(search) = search {
+                let search = search.to_lowercase();
+                films.retain(|film| {
+                    film.title.to_lowercase().contains(&search)
+                        || film.director.to_lowercase().contains(&search)
+                });
+            }
+            println!("{}", output::films(&films, cli.output));
+        }
+        Command::Get { id } => {
+            let film = backend.get_film(&id).await?;
+            println!("{}", output::film(&film, cli.output));
+        }
+        Command::Add {
+            title,
+            director,
+            year,
+            poster,
+        } => {
+            let film = backend
+                .create_film(&CreateFilm {
+                    title,
+                    director,
+                    year,
+                    poster,
+                })
+                .await?;
+            println!("{}", output::film(&film, cli.output));
+        }
+        Command::Edit {
+            id,
+            title,
+            director,
+            year,
+            poster,
+        } => {
+            let current = backend.get_film(&id).await?;
+            let film = backend
+                .update_film(&Film {
+                    title: title.unwrap_or(current.title),
+                    director: director.unwrap_or(current.director),
+                    year: year.unwrap_or(current.year),
+                    poster: poster.unwrap_or(current.poster),
+                    ..current
+                })
+                .await?;
+            println!("{}", output::film(&film, cli.output));
+        }
+        Command::Delete { id } => {
+            let id = backend.delete_film(&id).await?;
+            println!("Deleted {}", id);
+        }
+        Command::Import { file } => {
+            let films: Vec<CreateFilm> = serde_json::from_str(&read_input(&file)?)
+                .map_err(|e| format!("Invalid films file: {}", e))?;
+            let mut created = Vec::with_capacity(films.len());
+            for film in &films {
+                created.push(backend.create_film(film).await?);
+            }
+            println!("{}", output::films(&created, cli.output));
+        }
+        Command::Export { file } => {
+            let films = backend.get_films().await?;
+            let json = output::films(&films, Format::Json);
+            match file {
+                Some(file) => std::fs::write(&file, json)
+                    .map_err(|e| format!("Couldn't write {}: {}", file.display(), e))?,
+                None => println!("{}", json),
+            }
+        }
+    }
+    Ok(())
+}
+
+fn read_input(file: &PathBuf) -> Result<String, String> {
+    if file.as_os_str() == "-" {
+        let mut input = String::new();
+        std::io::stdin()
+            .read_to_string(&mut input)
+            .map_err(|e| format!("Couldn't read stdin: {}", e))?;
+        Ok(input)
+    } else {
+        std::fs::read_to_string(file)
+            .map_err(|e| format!("Couldn't read {}: {}", file.display(), e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn cli_is_valid() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn edit_only_takes_given_fields() {
+        let cli = Cli::try_parse_from([
+            "films-cli",
+            "edit",
+            "6f05e5f2-133c-11ee-be9f-0ab7e0d8c876",
+            "--year",
+            "1971",
+            "-o",
+            "json",
+        ])
+        .unwrap();
+
+        assert_eq!(cli.output, Format::Json);
+        match cli.command {
+            Command::Edit {
+                title,
+                year,
+                poster,
+                ..
+            } => {
+                assert_eq!(title, None);
+                assert_eq!(year, Some(1971));
+                assert_eq!(poster, None);
+            }
+            command => panic!("unexpected command {:?}", command),
+        }
+    }
+}
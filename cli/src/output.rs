@@ -0,0 +1,76 @@
+use clap::ValueEnum;
+use comfy_table::{presets::UTF8_FULL, Table};
+use shared::models::Film;
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Table,
+    Json,
+}
+
+pub fn films(films: &[Film], format: Format) -> String {
+    match format {
+        Format::Table => {
+            let mut table = Table::new();
+            table
+                .load_preset(UTF8_FULL)
+                .set_header(vec!["id", "title", "director", "year", "poster"]);
+            for film in films {
+                table.add_row(vec![
+                    film.id.to_string(),
+                    film.title.clone(),
+                    film.director.clone(),
+                    film.year.to_string(),
+                    film.poster.clone(),
+                ]);
+            }
+            table.to_string()
+        }
+        Format::Json => serde_json::to_string_pretty(films).expect("films are serializable"),
+    }
+}
+
+pub fn film(film: &Film, format: Format) -> String {
+    match format {
+        Format::Table => films(std::slice::from_ref(film), format),
+        Format::Json => serde_json::to_string_pretty(film).expect("films are serializable"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    fn create_test_film(title: &str) -> Film {
+        Film {
+            id: Uuid::new_v4(),
+            title: title.to_string(),
+            director: "Director test name".to_string(),
+            year: 2001,
+            poster: "Poster test name".to_string(),
+            created_at: None,
+            updated_at: None,
+        }
+    }
+
+    #[test]
+    fn table_has_a_row_per_film() {
+        let films = vec![create_test_film("first"), create_test_film("second")];
+
+        let table = super::films(&films, Format::Table);
+
+        assert!(table.contains("director"));
+        assert!(table.contains(&films[0].id.to_string()));
+        assert!(table.contains("second"));
+    }
+
+    #[test]
+    fn json_roundtrips() {
+        let film = create_test_film("first");
+
+        let json = super::film(&film, Format::Json);
+
+        assert_eq!(serde_json::from_str::<Film>(&json).unwrap(), film);
+    }
+}
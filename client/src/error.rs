@@ -0,0 +1,52 @@
+use std::fmt;
+
+pub type ClientResult<T> = Result<T, ClientError>;
+
+#[derive(Debug)]
+pub enum ClientError {
+    /// The API couldn't be reached.
+    Network(reqwest::Error),
+    /// The response body wasn't what we expected.
+    Decode(reqwest::Error),
+    NotFound(String),
+    Unauthorized(String),
+    /// Any other unsuccessful response.
+    Status {
+        status: u16,
+        message: String,
+    },
+}
+
+impl ClientError {
+    /// Whether sending the same request again may succeed.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            ClientError::Network(_) => true,
+            ClientError::Status { status, .. } => *status >= 500,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ClientError::Network(e) => write!(f, "Couldn't reach the API: {}", e),
+            ClientError::Decode(e) => write!(f, "Invalid response from the API: {}", e),
+            ClientError::NotFound(message) => write!(f, "Not found: {}", message),
+            ClientError::Unauthorized(message) => write!(f, "Unauthorized: {}", message),
+            ClientError::Status { status, message } => {
+                write!(f, "The API answered {}: {}", status, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ClientError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ClientError::Network(e) | ClientError::Decode(e) => Some(e),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,136 @@
+//! Typed client for the films REST API. Works both natively and in the
+//! browser (`wasm32`).
+
+mod error;
+mod retry;
+
+pub use error::{ClientError, ClientResult};
+pub use retry::RetryPolicy;
+
+use reqwest::{Method, RequestBuilder, StatusCode};
+use serde::de::DeserializeOwned;
+use shared::models::{CreateFilm, Film};
+use uuid::Uuid;
+
+#[derive(Debug, Clone)]
+pub struct FilmsClient {
+    http: reqwest::Client,
+    /// Url where the API is mounted, e.g. `http://localhost:8080/api`.
+    base_url: String,
+    token: Option<String>,
+    retry_policy: RetryPolicy,
+}
+
+impl FilmsClient {
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            token: None,
+            retry_policy: RetryPolicy::default(),
+        }
+    }
+
+    /// Sends `token` as a bearer token with every request.
+    pub fn with_token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    pub async fn list(&self) -> ClientResult<Vec<Film>> {
+        self.send(Method::GET, "", |request| request).await
+    }
+
+    pub async fn get(&self, id: &Uuid) -> ClientResult<Film> {
+        self.send(Method::GET, &format!("/{}", id), |request| request)
+            .await
+    }
+
+    pub async fn create(&self, create_film: &CreateFilm) -> ClientResult<Film> {
+        self.send(Method::POST, "", |request| request.json(create_film))
+            .await
+    }
+
+    pub async fn update(&self, film: &Film) -> ClientResult<Film> {
+        self.send(Method::PUT, "", |request| request.json(film))
+            .await
+    }
+
+    /// Deletes the film and returns its id.
+    pub async fn delete(&self, id: &Uuid) -> ClientResult<Uuid> {
+        self.send(Method::DELETE, &format!("/{}", id), |request| request)
+            .await
+    }
+
+    /// Films whose title or director contains `query`, ignoring case.
+    pub async fn search(&self, query: &str) -> ClientResult<Vec<Film>> {
+        let query = query.to_lowercase();
+        let films = self.list().await?;
+        Ok(films
+            .into_iter()
+            .filter(|film| {
+                film.title.to_lowercase().contains(&query)
+                    || film.director.to_lowercase().contains(&query)
+            })
+            .collect())
+    }
+
+    fn films_url(&self, path: &str) -> String {
+        format!("{}/v1/films{}", self.base_url, path)
+    }
+
+    /// Sends the request, retrying idempotent ones on network errors and
+    /// server errors.
+    async fn send<T, F>(&self, method: Method, path: &str, build: F) -> ClientResult<T>
+    where
+        T: DeserializeOwned,
+        F: Fn(RequestBuilder) -> RequestBuilder,
+    {
+        let max_attempts = if method == Method::POST {
+            1
+        } else {
+            self.retry_policy.max_attempts.max(1)
+        };
+        let url = self.films_url(path);
+
+        let mut attempt = 1;
+        loop {
+            let mut request = build(self.http.request(method.clone(), &url));
+            if let Some(token) = &self.token {
+                request = request.bearer_auth(token);
+            }
+            let result = match request.send().await {
+                Ok(response) => read_response(response).await,
+                Err(e) => Err(ClientError::Network(e)),
+            };
+            match result {
+                Err(e) if e.is_retryable() && attempt < max_attempts => {
+                    retry::sleep(self.retry_policy.delay(attempt)).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+async fn read_response<T: DeserializeOwned>(response: reqwest::Response) -> ClientResult<T> {
+    let status = response.status();
+    if status.is_success() {
+        return response.json::<T>().await.map_err(ClientError::Decode);
+    }
+    let message = response.text().await.unwrap_or_default();
+    Err(match status {
+        StatusCode::NOT_FOUND => ClientError::NotFound(message),
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => ClientError::Unauthorized(message),
+        _ => ClientError::Status {
+            status: status.as_u16(),
+            message,
+        },
+    })
+}
@@ -0,0 +1,205 @@
+mod integration {
+
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+        time::Duration,
+    };
+
+    use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+    use api_lib::film_repository::MemoryFilmRepository;
+    use client::{ClientError, FilmsClient, RetryPolicy};
+    use shared::models::CreateFilm;
+
+    /// Serves the films API on a random port and returns its base url.
+    fn start_api() -> String {
+        let repo = web::Data::new(MemoryFilmRepository::default());
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = HttpServer::new(move || {
+            App::new().service(
+                web::scope("/api")
+                    .app_data(repo.clone())
+                    .configure(api_lib::v1::service::<MemoryFilmRepository>),
+            )
+        })
+        .workers(1)
+        .listen(listener)
+        .unwrap()
+        .run();
+        actix_rt::spawn(server);
+        format!("http://127.0.0.1:{}/api", port)
+    }
+
+    #[derive(Default)]
+    struct Stub {
+        requests: AtomicUsize,
+        failures: AtomicUsize,
+        authorization: Mutex<Option<String>>,
+    }
+
+    async fn films(req: HttpRequest, stub: web::Data<Stub>) -> HttpResponse {
+        stub.requests.fetch_add(1, Ordering::SeqCst);
+        *stub.authorization.lock().unwrap() = req
+            .headers()
+            .get("Authorization")
+            .and_then(|h| h.to_str().ok())
+            .map(String::from);
+        let failing = stub
+            .failures
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+            .is_ok();
+        if failing {
+            HttpResponse::ServiceUnavailable().body("Try again")
+        } else {
+            HttpResponse::Ok().json(Vec::<shared::models::Film>::new())
+        }
+    }
+
+    /// Serves a films endpoint that fails the first `failures` requests.
+    fn start_stub(failures: usize) -> (String, Arc<Stub>) {
+        let stub = Arc::new(Stub {
+            failures: AtomicUsize::new(failures),
+            ..Default::default()
+        });
+        let data = web::Data::from(stub.clone());
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(data.clone())
+                .route("/api/v1/films", web::route().to(films))
+        })
+        .workers(1)
+        .listen(listener)
+        .unwrap()
+        .run();
+        actix_rt::spawn(server);
+        (format!("http://127.0.0.1:{}/api", port), stub)
+    }
+
+    fn fast_retries() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(10),
+            max_delay: Duration::from_millis(50),
+        }
+    }
+
+    fn create_test_create_film(title: &str, director: &str) -> CreateFilm {
+        CreateFilm {
+            title: title.to_string(),
+            director: director.to_string(),
+            poster: "poster".to_string(),
+            year: 2001,
+        }
+    }
+
+    #[actix_rt::test]
+    async fn crud_works() {
+        let client = FilmsClient::new(start_api());
+
+        let film = client
+            .create(&create_test_create_film("Vertigo", "Alfred Hitchcock"))
+            .await
+            .unwrap();
+        assert_eq!(client.list().await.unwrap(), vec![film.clone()]);
+        assert_eq!(client.get(&film.id).await.unwrap(), film);
+
+        let updated = client
+            .update(&shared::models::Film {
+                year: 1958,
+                ..film.clone()
+            })
+            .await
+            .unwrap();
+        assert_eq!(updated.year, 1958);
+
+        assert_eq!(client.delete(&film.id).await.unwrap(), film.id);
+        assert!(client.list().await.unwrap().is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn search_matches_title_or_director() {
+        let client = FilmsClient::new(start_api());
+        for (title, director) in [
+            ("Vertigo", "Alfred Hitchcock"),
+            ("Psycho", "Alfred Hitchcock"),
+            ("Alien", "Ridley Scott"),
+        ] {
+            client
+                .create(&create_test_create_film(title, director))
+                .await
+                .unwrap();
+        }
+
+        let by_director = client.search("hitchcock").await.unwrap();
+        let by_title = client.search("ALIEN").await.unwrap();
+
+        assert_eq!(by_director.len(), 2);
+        assert_eq!(by_title.len(), 1);
+        assert_eq!(by_title[0].title, "Alien");
+    }
+
+    #[actix_rt::test]
+    async fn missing_film_is_not_found() {
+        let client = FilmsClient::new(start_api());
+
+        let result = client.get(&uuid::Uuid::new_v4()).await;
+
+        assert!(matches!(result, Err(ClientError::NotFound(_))));
+    }
+
+    #[actix_rt::test]
+    async fn server_errors_are_retried() {
+        let (url, stub) = start_stub(2);
+        let client = FilmsClient::new(url).with_retry_policy(fast_retries());
+
+        let films = client.list().await.unwrap();
+
+        assert!(films.is_empty());
+        assert_eq!(stub.requests.load(Ordering::SeqCst), 3);
+    }
+
+    #[actix_rt::test]
+    async fn gives_up_after_max_attempts() {
+        let (url, stub) = start_stub(10);
+        let client = FilmsClient::new(url).with_retry_policy(fast_retries());
+
+        let result = client.list().await;
+
+        assert!(matches!(
+            result,
+            Err(ClientError::Status { status: 503, .. })
+        ));
+        assert_eq!(stub.requests.load(Ordering::SeqCst), 3);
+    }
+
+    #[actix_rt::test]
+    async fn creation_is_not_retried() {
+        let (url, stub) = start_stub(10);
+        let client = FilmsClient::new(url).with_retry_policy(fast_retries());
+
+        let result = client
+            .create(&create_test_create_film("Vertigo", "Alfred Hitchcock"))
+            .await;
+
+        assert!(result.is_err());
+        assert_eq!(stub.requests.load(Ordering::SeqCst), 1);
+    }
+
+    #[actix_rt::test]
+    async fn token_is_sent_as_bearer() {
+        let (url, stub) = start_stub(0);
+        let client = FilmsClient::new(url).with_token("s3cr3t");
+
+        client.list().await.unwrap();
+
+        assert_eq!(
+            stub.authorization.lock().unwrap().as_deref(),
+            Some("Bearer s3cr3t")
+        );
+    }
+}
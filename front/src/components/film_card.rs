@@ -1,4 +1,8 @@
-use crate::{components::Button, models::ButtonType};
+use crate::{
+    components::Button,
+    models::ButtonType,
+    presence::{PresenceConnection, PresenceState},
+};
 use dioxus::prelude::*;
 use shared::models::Film;
 
@@ -9,6 +13,10 @@ pub fn FilmCard<'a>(
     on_edit: EventHandler<'a, MouseEvent>,
     on_delete: EventHandler<'a, MouseEvent>,
 ) -> Element {
+    let presence = use_shared_state::<PresenceState>(cx).unwrap();
+    let user = use_context::<PresenceConnection>(cx).map_or("", |c| c.user.as_str());
+    let editors = presence.read().editors_label(&film.id, user);
+
     cx.render(rsx!(
         li {
             class: "film-card md:basis-1/4 p-4 rounded box-border bg-neutral-100 drop-shadow-md transition-all ease-in-out hover:drop-shadow-xl flex-col flex justify-start items-stretch animate-fade animate-duration-500 animate-ease-in-out animate-normal animate-fill-both",
@@ -31,6 +39,14 @@ pub fn FilmCard<'a>(
                     class: "text-sm text-gray-500",
                     "{film.year.to_string()}"
                 }
+                if let Some(editors) = editors {
+                    rsx!(
+                        p {
+                            class: "text-xs text-amber-700 mt-2 animate-pulse",
+                            "✏️ {editors} editing"
+                        }
+                    )
+                }
             }
             footer {
                 class: "flex justify-end space-x-2 mt-auto",
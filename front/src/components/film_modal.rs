@@ -4,6 +4,7 @@ use uuid::Uuid;
 
 use crate::components::Button;
 use crate::models::{ButtonType, FilmModalVisibility};
+use crate::presence::{PresenceConnection, PresenceState};
 
 #[derive(Props)]
 pub struct FilmModalProps<'a> {
@@ -15,6 +16,9 @@ pub struct FilmModalProps<'a> {
 
 pub fn FilmModal<'a>(cx: Scope<'a, FilmModalProps>) -> Element<'a> {
     let is_modal_visible = use_shared_state::<FilmModalVisibility>(cx).unwrap();
+    let presence = use_shared_state::<PresenceState>(cx).unwrap();
+    let presence_connection = use_context::<PresenceConnection>(cx);
+    let visible = is_modal_visible.read().0;
     let draft_film = use_state::<Film>(cx, || Film {
         title: "".to_string(),
         poster: "".to_string(),
@@ -43,9 +47,49 @@ pub fn FilmModal<'a>(cx: Scope<'a, FilmModalProps>) -> Element<'a> {
         });
     }
 
-    if !is_modal_visible.read().0 {
+    // let the others know which film is being edited
+    use_effect(cx, (&cx.props.film, &visible), move |(film, visible)| {
+        let connection = presence_connection.cloned();
+        async move {
+            if let Some(connection) = connection {
+                match film.filter(|_| visible) {
+                    Some(film) => connection.join(film.id),
+                    None => connection.leave(),
+                }
+            }
+        }
+    });
+
+    if !visible {
         return None;
     }
+
+    let user = presence_connection.map_or("", |c| c.user.as_str());
+    let editing_film = cx.props.film.as_ref().map(|film| film.id);
+    let (editors, title_holder, director_holder, year_holder, poster_holder) = {
+        let presence = presence.read();
+        let holder = |field: &str| {
+            editing_film.and_then(|film_id| presence.lock_holder(&film_id, field, user))
+        };
+        (
+            editing_film.and_then(|film_id| presence.editors_label(&film_id, user)),
+            holder("title"),
+            holder("director"),
+            holder("year"),
+            holder("poster"),
+        )
+    };
+    let lock = move |field: &str| {
+        if let Some(connection) = presence_connection {
+            connection.lock(field);
+        }
+    };
+    let unlock = move |field: &str| {
+        if let Some(connection) = presence_connection {
+            connection.unlock(field);
+        }
+    };
+
     cx.render(rsx!(
         article {
             class: "z-50 w-full h-full fixed top-0 right-0 bg-gray-800 bg-opacity-50 flex flex-col justify-center items-center",
@@ -57,6 +101,14 @@ pub fn FilmModal<'a>(cx: Scope<'a, FilmModalProps>) -> Element<'a> {
                         class: "text-xl text-teal-950 font-semibold",
                         "🎬 Film"
                     }
+                    if let Some(editors) = editors {
+                        rsx!(
+                            p {
+                                class: "text-sm text-amber-700 animate-pulse",
+                                "✏️ {editors} is editing this film"
+                            }
+                        )
+                    }
                 }
                 form {
                     class: "w-full flex-1 flex flex-col justify-stretch items-start gap-y-2",
@@ -66,9 +118,20 @@ pub fn FilmModal<'a>(cx: Scope<'a, FilmModalProps>) -> Element<'a> {
                             class: "text-sm font-semibold",
                             "Title"
                         }
+                        if let Some(holder) = &title_holder {
+                            rsx!(
+                                span {
+                                    class: "text-xs text-amber-700 ml-2",
+                                    "{holder} is typing…"
+                                }
+                            )
+                        }
                         input {
                             class: "w-full border border-gray-300 rounded-lg p-2",
                             "type": "text",
+                            disabled: title_holder.is_some(),
+                            onfocus: move |_| lock("title"),
+                            onblur: move |_| unlock("title"),
                             placeholder: "Enter film title",
                             value: "{draft_film.get().title}",
                             oninput: move |evt| {
@@ -85,9 +148,20 @@ pub fn FilmModal<'a>(cx: Scope<'a, FilmModalProps>) -> Element<'a> {
                             class: "text-sm font-semibold",
                             "Director"
                         }
+                        if let Some(holder) = &director_holder {
+                            rsx!(
+                                span {
+                                    class: "text-xs text-amber-700 ml-2",
+                                    "{holder} is typing…"
+                                }
+                            )
+                        }
                         input {
                             class: "w-full border border-gray-300 rounded-lg p-2",
                             "type": "text",
+                            disabled: director_holder.is_some(),
+                            onfocus: move |_| lock("director"),
+                            onblur: move |_| unlock("director"),
                             placeholder: "Enter film director",
                             value: "{draft_film.get().director}",
                             oninput: move |evt| {
@@ -104,9 +178,20 @@ pub fn FilmModal<'a>(cx: Scope<'a, FilmModalProps>) -> Element<'a> {
                             class: "text-sm font-semibold",
                             "Year"
                         }
+                        if let Some(holder) = &year_holder {
+                            rsx!(
+                                span {
+                                    class: "text-xs text-amber-700 ml-2",
+                                    "{holder} is typing…"
+                                }
+                            )
+                        }
                         input {
                             class: "w-full border border-gray-300 rounded-lg p-2",
                             "type": "number",
+                            disabled: year_holder.is_some(),
+                            onfocus: move |_| lock("year"),
+                            onblur: move |_| unlock("year"),
                             placeholder: "Enter film year",
                             value: "{draft_film.get().year.to_string()}",
                             oninput: move |evt| {
@@ -123,9 +208,20 @@ pub fn FilmModal<'a>(cx: Scope<'a, FilmModalProps>) -> Element<'a> {
                             class: "text-sm font-semibold",
                             "Poster"
                         }
+                        if let Some(holder) = &poster_holder {
+                            rsx!(
+                                span {
+                                    class: "text-xs text-amber-700 ml-2",
+                                    "{holder} is typing…"
+                                }
+                            )
+                        }
                         input {
                             class: "w-full border border-gray-300 rounded-lg p-2",
                             "type": "text",
+                            disabled: poster_holder.is_some(),
+                            onfocus: move |_| lock("poster"),
+                            onblur: move |_| unlock("poster"),
                             placeholder: "Enter film poster URL",
                             value: "{draft_film.get().poster}",
                             oninput: move |evt| {
@@ -2,31 +2,30 @@
 // import the prelude to get access to the `rsx!` macro and the `Scope` and `Element` types
 mod components;
 mod models;
+mod presence;
 
+use client::FilmsClient;
 use components::{FilmCard, FilmModal, Footer, Header};
 use dioxus::prelude::*;
 use models::FilmModalVisibility;
-use shared::models::Film;
+use shared::models::{CreateFilm, Film};
 
-const API_ENDPOINT: &str = "api/v1";
+const API_ENDPOINT: &str = "api";
 
-fn films_endpoint() -> String {
+fn films_client() -> FilmsClient {
     let window = web_sys::window().expect("no global `window` exists");
     let location = window.location();
     let host = location.host().expect("should have a host");
     let protocol = location.protocol().expect("should have a protocol");
-    let endpoint = format!("{}//{}/{}", protocol, host, API_ENDPOINT);
-    format!("{}/films", endpoint)
+    FilmsClient::new(format!("{}//{}/{}", protocol, host, API_ENDPOINT))
 }
 
 async fn get_films() -> Vec<Film> {
-    log::info!("Getting films {}", films_endpoint());
-    reqwest::get(&films_endpoint())
-        .await
-        .unwrap()
-        .json::<Vec<Film>>()
-        .await
-        .unwrap()
+    log::info!("Getting films");
+    films_client().list().await.unwrap_or_else(|err| {
+        log::error!("Error getting films: {}", err);
+        vec![]
+    })
 }
 
 fn main() {
@@ -38,6 +37,7 @@ fn main() {
 // create a component that renders a div with the text "Hello, world!"
 fn App(cx: Scope) -> Element {
     use_shared_state_provider(cx, || FilmModalVisibility(false));
+    presence::use_presence_provider(cx);
     let is_modal_visible = use_shared_state::<FilmModalVisibility>(cx).unwrap();
     let films = use_state::<Option<Vec<Film>>>(cx, || None);
     let selected_film = use_state::<Option<Film>>(cx, || None);
@@ -59,17 +59,14 @@ fn App(cx: Scope) -> Element {
         let force_get_films = force_get_films.clone();
         cx.spawn({
             async move {
-                let response = reqwest::Client::new()
-                    .delete(&format!("{}/{}", &films_endpoint(), filmId))
-                    .send()
-                    .await;
+                let response = films_client().delete(&filmId).await;
                 match response {
                     Ok(_data) => {
                         log::info!("Film deleted");
                         force_get_films.set(());
                     }
                     Err(err) => {
-                        log::info!("Error deleting film: {:?}", err);
+                        log::info!("Error deleting film: {}", err);
                     }
                 }
             }
@@ -83,17 +80,17 @@ fn App(cx: Scope) -> Element {
 
         cx.spawn({
             async move {
+                let client = films_client();
                 let response = if current_selected_film.get().is_some() {
-                    reqwest::Client::new()
-                        .put(&films_endpoint())
-                        .json(&film)
-                        .send()
-                        .await
+                    client.update(&film).await
                 } else {
-                    reqwest::Client::new()
-                        .post(&films_endpoint())
-                        .json(&film)
-                        .send()
+                    client
+                        .create(&CreateFilm {
+                            title: film.title,
+                            director: film.director,
+                            year: film.year,
+                            poster: film.poster,
+                        })
                         .await
                 };
                 match response {
@@ -104,7 +101,7 @@ fn App(cx: Scope) -> Element {
                         force_get_films.set(());
                     }
                     Err(err) => {
-                        log::info!("Error creating film: {:?}", err);
+                        log::info!("Error creating film: {}", err);
                     }
                 }
             }
@@ -0,0 +1,160 @@
+use std::collections::HashMap;
+
+use dioxus::prelude::*;
+use shared::presence::{FilmPresence, PresenceEvent, PresenceRequest};
+use uuid::Uuid;
+use wasm_bindgen::{closure::Closure, JsCast};
+use web_sys::{MessageEvent, WebSocket};
+
+const PRESENCE_ENDPOINT: &str = "api/presence";
+const USER_STORAGE_KEY: &str = "rusty-films-user";
+
+/// Who is viewing or editing each film, as last reported by the API.
+#[derive(Default)]
+pub struct PresenceState {
+    pub films: HashMap<Uuid, FilmPresence>,
+}
+
+impl PresenceState {
+    /// Users other than `user` editing `film_id`, joined in a single label.
+    pub fn editors_label(&self, film_id: &Uuid, user: &str) -> Option<String> {
+        let editors = self.films.get(film_id)?.editors_except(user);
+        if editors.is_empty() {
+            None
+        } else {
+            Some(editors.join(", "))
+        }
+    }
+
+    /// The user holding `field` of `film_id`, when it's not `user`.
+    pub fn lock_holder(&self, film_id: &Uuid, field: &str, user: &str) -> Option<String> {
+        self.films
+            .get(film_id)?
+            .lock_holder(field)
+            .filter(|holder| *holder != user)
+            .map(|holder| holder.to_string())
+    }
+}
+
+/// The presence WebSocket together with the name of the local user.
+#[derive(Clone)]
+pub struct PresenceConnection {
+    socket: Option<WebSocket>,
+    pub user: String,
+}
+
+impl PresenceConnection {
+    pub fn join(&self, film_id: Uuid) {
+        self.send(&PresenceRequest::Join {
+            film_id,
+            user: self.user.clone(),
+            editing: true,
+        });
+    }
+
+    pub fn leave(&self) {
+        self.send(&PresenceRequest::Leave);
+    }
+
+    pub fn lock(&self, field: &str) {
+        self.send(&PresenceRequest::Lock {
+            field: field.to_string(),
+        });
+    }
+
+    pub fn unlock(&self, field: &str) {
+        self.send(&PresenceRequest::Unlock {
+            field: field.to_string(),
+        });
+    }
+
+    fn send(&self, request: &PresenceRequest) {
+        let Some(socket) = &self.socket else {
+            return;
+        };
+        if socket.ready_state() != WebSocket::OPEN {
+            return;
+        }
+        match serde_json::to_string(request) {
+            Ok(text) => {
+                if let Err(err) = socket.send_with_str(&text) {
+                    log::info!("Error sending presence message: {:?}", err);
+                }
+            }
+            Err(err) => log::info!("Error serializing presence message: {:?}", err),
+        }
+    }
+}
+
+/// Opens the presence WebSocket and shares both the connection and the
+/// received [`PresenceState`] with every component below `cx`.
+pub fn use_presence_provider(cx: &ScopeState) {
+    use_shared_state_provider(cx, PresenceState::default);
+    let state = use_shared_state::<PresenceState>(cx).unwrap().clone();
+    use_context_provider(cx, move || connect(state));
+}
+
+fn connect(state: UseSharedState<PresenceState>) -> PresenceConnection {
+    let user = current_user();
+    let socket = match WebSocket::new(&presence_endpoint()) {
+        Ok(socket) => Some(socket),
+        Err(err) => {
+            log::info!("Error opening presence connection: {:?}", err);
+            None
+        }
+    };
+
+    if let Some(socket) = &socket {
+        let on_message = Closure::<dyn FnMut(MessageEvent)>::new(move |event: MessageEvent| {
+            let Some(text) = event.data().as_string() else {
+                return;
+            };
+            match serde_json::from_str::<PresenceEvent>(&text) {
+                Ok(PresenceEvent::Snapshot(presence)) => {
+                    let mut state = state.write();
+                    if presence.viewers.is_empty() {
+                        state.films.remove(&presence.film_id);
+                    } else {
+                        state.films.insert(presence.film_id, presence);
+                    }
+                }
+                Ok(PresenceEvent::LockDenied { field, holder, .. }) => {
+                    log::info!("{} is already editing the {}", holder, field);
+                }
+                Err(err) => log::info!("Error reading presence message: {:?}", err),
+            }
+        });
+        socket.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+        // the socket lives as long as the app, so does its handler
+        on_message.forget();
+    }
+
+    PresenceConnection { socket, user }
+}
+
+fn presence_endpoint() -> String {
+    let window = web_sys::window().expect("no global `window` exists");
+    let location = window.location();
+    let host = location.host().expect("should have a host");
+    let protocol = match location.protocol().as_deref() {
+        Ok("https:") => "wss:",
+        _ => "ws:",
+    };
+    format!("{}//{}/{}", protocol, host, PRESENCE_ENDPOINT)
+}
+
+/// A guest name kept in local storage so it survives page reloads.
+fn current_user() -> String {
+    let storage = web_sys::window().and_then(|window| window.local_storage().ok().flatten());
+    if let Some(user) = storage
+        .as_ref()
+        .and_then(|storage| storage.get_item(USER_STORAGE_KEY).ok().flatten())
+    {
+        return user;
+    }
+    let user = format!("Guest-{}", &Uuid::new_v4().simple().to_string()[..4]);
+    if let Some(storage) = storage {
+        let _ = storage.set_item(USER_STORAGE_KEY, &user);
+    }
+    user
+}
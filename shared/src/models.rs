@@ -1,6 +1,6 @@
 use serde::{Deserialize, Serialize};
 
-#[cfg_attr(feature = "backend", derive(sqlx::FromRow))]
+#[cfg_attr(feature = "backend", derive(sqlx::FromRow, utoipa::ToSchema))]
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Default)]
 pub struct Film {
     pub id: uuid::Uuid,
@@ -13,7 +13,7 @@ pub struct Film {
     pub updated_at: Option<chrono::DateTime<chrono::Utc>>,
 }
 
-#[cfg_attr(feature = "backend", derive(sqlx::FromRow))]
+#[cfg_attr(feature = "backend", derive(sqlx::FromRow, utoipa::ToSchema))]
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Default)]
 pub struct CreateFilm {
     pub title: String,
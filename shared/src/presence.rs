@@ -0,0 +1,71 @@
+use serde::{Deserialize, Serialize};
+
+/// Messages sent by a client through the presence WebSocket.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PresenceRequest {
+    /// The user opened a film, either to look at it or to edit it.
+    Join {
+        film_id: uuid::Uuid,
+        user: String,
+        editing: bool,
+    },
+    /// The user closed the film it was viewing.
+    Leave,
+    /// The user started editing a field of the film it joined.
+    Lock { field: String },
+    /// The user stopped editing a field of the film it joined.
+    Unlock { field: String },
+}
+
+/// Messages sent by the server through the presence WebSocket.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PresenceEvent {
+    /// Current viewers and locks of a film. Sent every time they change.
+    Snapshot(FilmPresence),
+    /// A lock request was rejected because somebody else holds the field.
+    LockDenied {
+        film_id: uuid::Uuid,
+        field: String,
+        holder: String,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct FilmPresence {
+    pub film_id: uuid::Uuid,
+    pub viewers: Vec<Viewer>,
+    pub locks: Vec<FieldLock>,
+}
+
+impl FilmPresence {
+    /// Names of the users editing the film, excluding `user`.
+    pub fn editors_except(&self, user: &str) -> Vec<String> {
+        self.viewers
+            .iter()
+            .filter(|viewer| viewer.editing && viewer.user != user)
+            .map(|viewer| viewer.user.clone())
+            .collect()
+    }
+
+    /// The user holding the lock on `field`, if any.
+    pub fn lock_holder(&self, field: &str) -> Option<&str> {
+        self.locks
+            .iter()
+            .find(|lock| lock.field == field)
+            .map(|lock| lock.user.as_str())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Viewer {
+    pub user: String,
+    pub editing: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FieldLock {
+    pub field: String,
+    pub user: String,
+}